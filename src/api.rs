@@ -0,0 +1,212 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::discord::slash::PollRequest;
+use crate::discord::DiscordBot;
+use crate::reddit::DisabledUsers;
+use crate::store::Store;
+
+// The most posts /posts hands back in one go
+const MAX_POSTS: usize = 500;
+
+/// An http api for driving the bot from other tools, everything needs the bearer token
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiConfig {
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    pub token: String,
+}
+
+fn default_listen() -> String {
+    String::from("127.0.0.1:9185")
+}
+
+/// Everything the endpoints get at, the same things the slash commands do
+#[derive(Clone)]
+pub struct ApiState {
+    token: Arc<String>,
+    bot: DiscordBot,
+    store: Arc<dyn Store>,
+    poll_requests: mpsc::Sender<PollRequest>,
+    watched_users: Arc<Vec<String>>,
+    disabled_users: DisabledUsers,
+    max_poll_age: Option<Duration>,
+}
+
+impl ApiState {
+    pub fn new(
+        config: &ApiConfig,
+        bot: DiscordBot,
+        store: Arc<dyn Store>,
+        poll_requests: mpsc::Sender<PollRequest>,
+        watched_users: Vec<String>,
+        disabled_users: DisabledUsers,
+        max_poll_age: Option<Duration>,
+    ) -> ApiState {
+        return ApiState {
+            token: Arc::new(config.token.clone()),
+            bot: bot,
+            store: store,
+            poll_requests: poll_requests,
+            watched_users: Arc::new(watched_users),
+            disabled_users: disabled_users,
+            max_poll_age: max_poll_age,
+        }
+    }
+}
+
+/// Serve the api until we're stopped
+pub async fn serve(config: ApiConfig, state: ApiState) {
+    let address = match config.listen.parse::<SocketAddr>() {
+        Ok(a) => a,
+        Err(e) => {
+            error!("Can't serve the api on {}: {}", config.listen, e);
+            return;
+        }
+    };
+    let server = match axum::Server::try_bind(&address) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Can't serve the api on {}: {}", address, e);
+            return;
+        }
+    };
+    let app = Router::new()
+        .route("/status", get(status))
+        .route("/posts", get(posts))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/poll", post(poll))
+        .route("/users", get(users))
+        .route("/users/:name/enable", post(enable_user))
+        .route("/users/:name/disable", post(disable_user))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+    warn!("Serving the api on http://{}", address);
+    if let Err(e) = server.serve(app.into_make_service()).await {
+        error!("Api server fell over: {}", e);
+    }
+}
+
+type ApiResult = Result<Json<serde_json::Value>, (StatusCode, String)>;
+
+// Nothing gets through without the token
+async fn authorize<B>(State(state): State<ApiState>, request: Request<B>, next: Next<B>) -> Response {
+    let given = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    match given {
+        Some(token) if same(token.as_bytes(), state.token.as_bytes()) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, "Needs Authorization: Bearer <api.token>").into_response(),
+    }
+}
+
+// Takes as long whatever the token is, so it can't be guessed a byte at a time
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn status(State(state): State<ApiState>) -> ApiResult {
+    let shards: Vec<serde_json::Value> = state.bot.shard_states().await.iter()
+        .map(|(id, stage, latency)| serde_json::json!({
+            "id": id.0,
+            "stage": stage.to_string(),
+            "latency_ms": latency.map(|l| l.as_millis() as u64),
+        }))
+        .collect();
+    let readiness = state.bot.readiness(state.max_poll_age).await;
+    Ok(Json(serde_json::json!({
+        "paused": state.bot.is_paused(),
+        "ready": readiness.is_ok(),
+        "problems": readiness.err().unwrap_or_default(),
+        "shards": shards,
+        "stats": crate::runtime::STATS.report().lines().collect::<Vec<&str>>(),
+    })))
+}
+
+#[derive(Deserialize)]
+struct PostsQuery {
+    // How far back to look
+    hours: Option<i64>,
+    limit: Option<usize>,
+}
+
+// Newest first, out of the archive
+async fn posts(State(state): State<ApiState>, Query(query): Query<PostsQuery>) -> ApiResult {
+    let hours = query.hours.unwrap_or(24).max(0);
+    let since = chrono::Utc::now().timestamp().saturating_sub(hours.saturating_mul(60 * 60));
+    let posts = state.store.recent_posts(since, query.limit.unwrap_or(50).min(MAX_POSTS))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(serde_json::json!({ "posts": posts })))
+}
+
+async fn pause(State(state): State<ApiState>) -> ApiResult {
+    let changed = state.bot.set_paused(true).await;
+    if changed {
+        warn!("Sniffing paused through the api");
+    }
+    Ok(Json(serde_json::json!({ "paused": true, "changed": changed })))
+}
+
+async fn resume(State(state): State<ApiState>) -> ApiResult {
+    let changed = state.bot.set_paused(false).await;
+    if changed {
+        warn!("Sniffing resumed through the api");
+    }
+    Ok(Json(serde_json::json!({ "paused": false, "changed": changed })))
+}
+
+// Same as /forcepoll, waits for the poll to finish
+async fn poll(State(state): State<ApiState>) -> ApiResult {
+    let (result_tx, result_rx) = oneshot::channel();
+    if let Err(_) = state.poll_requests.send(result_tx).await {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, String::from("The reddit loop isn't running")));
+    }
+    match result_rx.await {
+        Ok(Ok(found)) => Ok(Json(serde_json::json!({ "found": found }))),
+        Ok(Err(e)) => Err((StatusCode::BAD_GATEWAY, String::from(format!("Poll failed: {}", e)))),
+        Err(_) => Err((StatusCode::SERVICE_UNAVAILABLE, String::from("The reddit loop never answered"))),
+    }
+}
+
+async fn users(State(state): State<ApiState>) -> ApiResult {
+    let users: Vec<serde_json::Value> = state.watched_users.iter()
+        .map(|u| serde_json::json!({ "name": u, "enabled": !state.disabled_users.is_disabled(u) }))
+        .collect();
+    Ok(Json(serde_json::json!({ "users": users })))
+}
+
+async fn enable_user(State(state): State<ApiState>, Path(name): Path<String>) -> ApiResult {
+    set_user(&state, &name, true)
+}
+
+async fn disable_user(State(state): State<ApiState>, Path(name): Path<String>) -> ApiResult {
+    set_user(&state, &name, false)
+}
+
+// Only people we're watching, any case
+fn set_user(state: &ApiState, name: &str, enabled: bool) -> ApiResult {
+    let name = name.trim_start_matches("u/").trim_start_matches("/u/");
+    let user = match state.watched_users.iter().find(|u| u.eq_ignore_ascii_case(name)) {
+        Some(u) => u,
+        None => return Err((StatusCode::NOT_FOUND, String::from(format!("We're not watching /u/{}", name)))),
+    };
+    match enabled {
+        true => state.disabled_users.enable(user),
+        false => state.disabled_users.disable(user),
+    }
+    Ok(Json(serde_json::json!({ "name": user, "enabled": enabled })))
+}
@@ -1,3 +1,3 @@
-// File for module exports
-
+// File for module exports
+
 pub mod player;
\ No newline at end of file
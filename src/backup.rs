@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::path::Path;
+
+use chrono::Utc;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::store::{self, StoreConfig};
+
+// What everything's called inside a backup
+const MANIFEST_ENTRY: &str = "manifest.json";
+const CONFIG_ENTRY: &str = "config.toml";
+const DATABASE_ENTRY: &str = "sniffer.db";
+
+/// What's in a backup and where it came from
+#[derive(Serialize, Deserialize, Debug)]
+struct Manifest {
+    version: String,
+    created_at: i64,
+    profile: Option<String>,
+}
+
+/// Bundle the database (relayed posts, the archive, cursors, message ids) and the config file
+/// as it is on disk into one .tar.gz. Encrypted configs stay encrypted
+pub fn create(config: &Config, config_path: &str, output: &str) -> Result<String, String> {
+    let store = store::open(&config.store)?;
+    // The copy has to be a file for tar, it goes once it's in the archive
+    let snapshot = std::env::temp_dir().join(format!("sniffer_backup_{}.db", std::process::id()));
+    let snapshot_path = snapshot.to_string_lossy().to_string();
+    let _ = std::fs::remove_file(&snapshot);
+    store.snapshot(&snapshot_path)?;
+
+    let result = write_archive(config, config_path, output, &snapshot);
+    let _ = std::fs::remove_file(&snapshot);
+    result?;
+    Ok(format!("Backed up {} and {} to {}", config.store.path, config_path, output))
+}
+
+fn write_archive(config: &Config, config_path: &str, output: &str, snapshot: &Path) -> Result<(), String> {
+    let fail = |e: std::io::Error| String::from(format!("Couldn't write the backup to {}: {}", output, e));
+    let file = File::create(output).map_err(fail)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let manifest = Manifest {
+        version: String::from(env!("CARGO_PKG_VERSION")),
+        created_at: Utc::now().timestamp(),
+        profile: config.profile.clone(),
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest).map_err(|e| String::from(format!("Couldn't write the manifest: {}", e)))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST_ENTRY, manifest.as_slice()).map_err(fail)?;
+
+    // Running off environment variables alone is fine, there's just no file to keep
+    if Path::new(config_path).exists() {
+        archive.append_path_with_name(config_path, CONFIG_ENTRY).map_err(fail)?;
+    }
+    else {
+        warn!("No config file at {}, the backup won't have one", config_path);
+    }
+    archive.append_path_with_name(snapshot, DATABASE_ENTRY).map_err(fail)?;
+
+    archive.into_inner().and_then(|gz| gz.finish()).map_err(fail)?;
+    Ok(())
+}
+
+/// Unpack a backup, the config goes to config_path and the database to wherever that config
+/// says it lives. Won't write over anything that's already there unless forced
+pub fn restore(path: &str, config_path: &str, profile: Option<&str>, force: bool) -> Result<String, String> {
+    let fail = |e: std::io::Error| String::from(format!("Couldn't read the backup {}: {}", path, e));
+    let file = File::open(path).map_err(fail)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut restored = Vec::<String>::new();
+    // Config goes in before the database, so we know where the database should go
+    for entry in archive.entries().map_err(fail)? {
+        let mut entry = entry.map_err(fail)?;
+        let name = entry.path().map_err(fail)?.to_string_lossy().to_string();
+        match name.as_str() {
+            MANIFEST_ENTRY => {
+                match serde_json::from_reader::<_, Manifest>(&mut entry) {
+                    Ok(m) => println!("Backup from version {} made at {}", m.version, m.created_at),
+                    Err(e) => return Err(String::from(format!("{} doesn't look like one of our backups: {}", path, e))),
+                }
+            }
+            CONFIG_ENTRY => {
+                if Path::new(config_path).exists() && !force {
+                    println!("Keeping the config already at {}, --force to replace it", config_path);
+                    continue;
+                }
+                entry.unpack(config_path).map_err(fail)?;
+                restored.push(String::from(config_path));
+            }
+            DATABASE_ENTRY => {
+                let database = match Config::load(config_path, profile) {
+                    Ok(c) => c.store.path,
+                    Err(e) => {
+                        println!("Couldn't load {} ({}), putting the database in the default spot", config_path, e);
+                        StoreConfig::default().path
+                    }
+                };
+                if Path::new(&database).exists() && !force {
+                    return Err(String::from(format!("There's already a database at {}, --force to replace it", database)));
+                }
+                entry.unpack(&database).map_err(fail)?;
+                restored.push(database);
+            }
+            other => println!("Skipping {} in the backup, don't know what it is", other),
+        }
+    }
+    if restored.is_empty() {
+        return Err(String::from("Nothing restored"));
+    }
+    Ok(format!("Restored {}", restored.join(" and ")))
+}
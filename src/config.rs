@@ -0,0 +1,680 @@
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::discord;
+use crate::filter::{FilterConfig, WatchRule};
+use crate::reddit;
+
+pub mod secrets;
+
+/// Where we look for the config if we aren't told otherwise with --config
+pub const DEFAULT_CONFIG_PATH: &str = "sniffer.toml";
+// Never poll reddit faster than this, whatever the config says
+pub const MIN_POLL_INTERVAL: u64 = 15;
+// Any slower and it's not really sniffing
+const MAX_POLL_INTERVAL: u64 = 60 * 60;
+// Everything that has to be there one way or another, so we can say all of what's missing at once
+const REQUIRED: &[&str] = &[
+    "discord.bot_token",
+    "discord.application_id",
+    "discord.guild_id",
+    "channels.audio",
+];
+
+/// Everything the bot is set up with, see sniffer.example.toml for a commented one
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    pub discord: DiscordConfig,
+    pub channels: ChannelConfig,
+    #[serde(default)]
+    pub watch: WatchConfig,
+    #[serde(default)]
+    pub reddit: RedditConfig,
+    #[serde(default)]
+    pub polling: PollingConfig,
+    // Which posts get relayed at all, destinations can have their own instead
+    #[serde(default)]
+    pub filters: FilterConfig,
+    // Which sources go to which destinations, everything goes everywhere without any
+    #[serde(default)]
+    pub rules: Vec<WatchRule>,
+    #[serde(default)]
+    pub posting: PostingConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    // Where we keep what we've relayed between runs
+    #[serde(default)]
+    pub store: crate::store::StoreConfig,
+    // Vault or AWS for the token and reddit credentials, instead of them sitting in here
+    #[serde(default)]
+    pub secrets: secrets::SecretsConfig,
+    // Prometheus metrics over http, off without it
+    pub metrics: Option<crate::metrics::MetricsConfig>,
+    // Same for /healthz and /readyz
+    pub health: Option<crate::health::HealthConfig>,
+    // And an api for driving the bot from other tools
+    pub api: Option<crate::api::ApiConfig>,
+    // Where errors get sent, batched up. Only admin alerts get DMed without it
+    pub alerts: Option<discord::alerts::AlertConfig>,
+    // Slack channels to relay to as well, rules pick them out as slack:<name>
+    pub slack: Option<crate::slack::SlackConfig>,
+    // A mastodon account to toot from, rules pick it out as mastodon
+    pub mastodon: Option<crate::mastodon::MastodonConfig>,
+    // Email too, one per post or as a digest, rules pick it out as email
+    pub email: Option<crate::email::EmailConfig>,
+    // An IRC channel as well, rules pick it out as irc
+    pub irc: Option<crate::irc::IrcConfig>,
+    // Our own json POSTed anywhere else, rules pick them out as hook:<name>
+    #[serde(default)]
+    pub hooks: Vec<crate::hooks::HookConfig>,
+    // Errors and panics to sentry, needs the sentry feature
+    pub sentry: Option<crate::reporting::SentryConfig>,
+    // Set from the command line, not the file
+    #[serde(skip)]
+    pub dry_run: bool,
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DiscordConfig {
+    pub bot_token: String,
+    pub application_id: u64,
+    pub guild_id: u64,
+    // Discord user that gets DMed when things go badly wrong
+    pub admin_user: Option<u64>,
+    // Run as many shards as discord recommends instead of just 1
+    #[serde(default)]
+    pub auto_shards: bool,
+    // Skip the gateway entirely and only post through destination webhooks
+    #[serde(default)]
+    pub webhook_only: bool,
+    // What the bot shows as its activity
+    #[serde(default)]
+    pub presence: discord::Presence,
+    // Emoji reactions admins can use on our posts
+    #[serde(default)]
+    pub moderation: discord::moderation::ModerationConfig,
+    // Regular word on how the shards are doing
+    pub shard_reports: Option<discord::ShardReportConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChannelConfig {
+    pub audio: u64,
+    // Where debug messages go, if anywhere
+    pub test: Option<u64>,
+    // Old style setup, only used if there aren't any destinations
+    pub main: Option<u64>,
+    pub archive: Option<u64>,
+    // Every channel we post to, each with its own options
+    #[serde(default)]
+    pub destinations: Vec<discord::Destination>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct WatchConfig {
+    // Not needed if the rules say who to watch
+    #[serde(default)]
+    pub sniffer: String,
+    // More reddit users to follow along with the sniffer
+    #[serde(default)]
+    pub users: Vec<String>,
+    // Users whose comments we sniff too, not just their posts
+    #[serde(default)]
+    pub comment_users: Vec<String>,
+    #[serde(default)]
+    pub subreddits: Vec<String>,
+    // Reddit searches to run every poll, like `subreddit:foo "sniffer"`
+    #[serde(default)]
+    pub searches: Vec<String>,
+    // Multireddits to watch, like user/m/name
+    #[serde(default)]
+    pub multireddits: Vec<String>,
+    // Reddit live threads to stream into discord
+    #[serde(default)]
+    pub live_threads: Vec<reddit::live::LiveThreadWatch>,
+    // Wiki pages to post a diff of whenever they change
+    #[serde(default)]
+    pub wiki_pages: Vec<reddit::wiki::WikiWatch>,
+    // Relay reports and removals from a subreddit we moderate to a private channel
+    pub modqueue: Option<reddit::modqueue::ModqueueConfig>,
+    // Tell someone when the accounts we follow change status
+    pub account_alerts: Option<reddit::accounts::AccountAlertConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RedditConfig {
+    // Log into reddit's oauth api with a script app, otherwise we go in anonymous
+    pub auth: Option<reddit::oauth::RedditCredentials>,
+    // User agent, timeouts and such for talking to reddit
+    #[serde(default)]
+    pub client: reddit::oauth::ClientConfig,
+    // Where we used to keep track of the last thing we saw from each source between runs,
+    // that's in the database now and this only gets read to move old ones over
+    #[serde(default = "default_anchor_file")]
+    pub anchor_file: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PollingConfig {
+    // Seconds between reddit polls, plus up to jitter random seconds so we're not
+    // hitting them like clockwork
+    #[serde(default = "default_poll_interval")]
+    pub interval: u64,
+    #[serde(default = "default_poll_jitter")]
+    pub jitter: u64,
+    // Same link or near enough the same post within this many minutes only gets relayed once, 0 turns it off
+    #[serde(default = "default_duplicate_window")]
+    pub duplicate_window_minutes: u64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        PollingConfig {
+            interval: default_poll_interval(),
+            jitter: default_poll_jitter(),
+            duplicate_window_minutes: default_duplicate_window(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PostingConfig {
+    // Post sniffs as rich embeds instead of plain text, for destinations that don't say
+    #[serde(default)]
+    pub embed_posts: bool,
+    // What to do with our messages when a sniffed post gets deleted, same deal
+    #[serde(default)]
+    pub deleted_action: discord::DeletedAction,
+    // Keyword -> role pings for the destinations that want them
+    #[serde(default)]
+    pub role_pings: Vec<discord::RolePing>,
+    // Pin posts that hit a score after a while
+    pub auto_pin: Option<discord::AutoPinConfig>,
+    // Only relay posts that have hit a score after a while
+    pub score_gate: Option<reddit::ScoreGate>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoggingConfig {
+    // Off and it's just the console, for when something else is already catching stdout
+    #[serde(default = "default_log_file")]
+    pub file: bool,
+    #[serde(default = "default_log_path")]
+    pub path: String,
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    // Megabytes before the log rolls over, 0 to only go by rotate, and how many old ones we keep
+    #[serde(default = "default_log_size")]
+    pub size: u64,
+    #[serde(default = "default_log_roll_count")]
+    pub roll_count: u32,
+    // A fresh file every hour or day as well
+    pub rotate: Option<crate::logging::LogRotation>,
+    // Old logs get deleted once they're this old, however many there are
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            file: default_log_file(),
+            path: default_log_path(),
+            level: default_log_level(),
+            size: default_log_size(),
+            roll_count: default_log_roll_count(),
+            rotate: None,
+            max_age_days: None,
+        }
+    }
+}
+
+// Environment variables like SNIFFER_DISCORD__BOT_TOKEN or SNIFFER_POLLING__INTERVAL, double
+// underscores between the sections. Values are read like they'd be written in the toml, so
+// SNIFFER_WATCH__USERS='["a", "b"]' works, and anything that isn't valid toml is just a string
+const ENV_PREFIX: &str = "SNIFFER_";
+
+fn env_overrides() -> Vec<(String, Vec<String>, toml::Value)> {
+    let mut overrides = Vec::new();
+    for (name, raw) in std::env::vars() {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            // These pick the profile and unlock the file, they aren't values
+            Some("PROFILE") | Some(secrets::AGE_KEY_VAR) | Some(secrets::AGE_PASSPHRASE_VAR) => continue,
+            Some(p) if !p.is_empty() => p,
+            _ => continue,
+        };
+        let keys = path.split("__").map(|k| k.to_lowercase()).collect();
+        let value = match format!("value = {}", raw).parse::<toml::Table>() {
+            Ok(mut t) => t.remove("value").unwrap_or_else(|| toml::Value::String(raw.clone())),
+            Err(_) => toml::Value::String(raw),
+        };
+        overrides.push((name, keys, value));
+    }
+    // Shorter paths first so SNIFFER_WATCH__MODQUEUE__CHANNEL can land inside a SNIFFER_WATCH__MODQUEUE
+    overrides.sort_by_key(|(_, keys, _)| keys.len());
+    overrides
+}
+
+fn set_path(table: &mut toml::Table, keys: &[String], value: toml::Value) -> Result<(), String> {
+    let (last, sections) = match keys.split_last() {
+        Some(k) => k,
+        None => return Err(String::from("no key to set")),
+    };
+    let mut current = table;
+    for key in sections {
+        let entry = current.entry(key.clone()).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        current = match entry.as_table_mut() {
+            Some(t) => t,
+            None => return Err(String::from(format!("{} isn't a section", key))),
+        };
+    }
+    current.insert(last.clone(), value);
+    Ok(())
+}
+
+// Sections merge key by key, anything else (lists included) just replaces what was there
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(o)) => merge_tables(b, o),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn has_path(table: &toml::Table, path: &str) -> bool {
+    let mut current = table;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        match (current.get(key), keys.peek()) {
+            (Some(_), None) => return true,
+            (Some(toml::Value::Table(t)), Some(_)) => current = t,
+            _ => return false,
+        }
+    }
+    false
+}
+
+// Bot tokens are three base64 chunks with dots between them
+fn check_token(token: &str) -> Result<(), String> {
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(String::from("discord.bot_token is empty"));
+    }
+    if token.starts_with("Bot ") {
+        return Err(String::from("discord.bot_token shouldn't start with \"Bot \", serenity adds that"));
+    }
+    let parts: Vec<&str> = token.split('.').collect();
+    let base64 = |p: &&str| !p.is_empty() && p.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if parts.len() != 3 || !parts.iter().all(base64) {
+        return Err(String::from("discord.bot_token doesn't look like a bot token, it should be three chunks split by dots"));
+    }
+    Ok(())
+}
+
+// Snowflakes are a timestamp shifted up 22 bits, so anything real is way past this
+fn looks_like_id(id: u64) -> bool {
+    id >= 1 << 32
+}
+
+fn default_anchor_file() -> String {
+    String::from("sniffer_anchors.json")
+}
+fn default_poll_interval() -> u64 {
+    45
+}
+fn default_poll_jitter() -> u64 {
+    10
+}
+fn default_duplicate_window() -> u64 {
+    6 * 60
+}
+fn default_log_file() -> bool {
+    true
+}
+fn default_log_path() -> String {
+    String::from("./sniffer_log.txt")
+}
+fn default_log_level() -> String {
+    String::from("warn")
+}
+fn default_log_size() -> u64 {
+    500
+}
+fn default_log_roll_count() -> u32 {
+    10
+}
+
+impl Config {
+    /// Read the config file, lay the profile's section over it if we're given one, then any
+    /// SNIFFER_* environment variables over that. With enough of those set the file doesn't
+    /// have to exist at all
+    pub fn load(path: &str, profile: Option<&str>) -> Result<Config, String> {
+        let overrides = env_overrides();
+        let mut table = match fs::read(path) {
+            Ok(bytes) => {
+                let text = match secrets::is_encrypted(&bytes) {
+                    true => secrets::decrypt(&bytes).map_err(|e| String::from(format!("Couldn't decrypt config file {}: {}", path, e)))?,
+                    false => match String::from_utf8(bytes) {
+                        Ok(t) => t,
+                        Err(e) => return Err(String::from(format!("Couldn't read config file {}: {}", path, e))),
+                    },
+                };
+                match text.parse::<toml::Table>() {
+                    Ok(t) => t,
+                    Err(e) => return Err(String::from(format!("Couldn't parse config file {}: {}", path, e))),
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !overrides.is_empty() => toml::Table::new(),
+            Err(e) => return Err(String::from(format!("Couldn't read config file {}: {}", path, e))),
+        };
+        let profiles = table.remove("profiles");
+        if let Some(name) = profile {
+            match profiles.as_ref().and_then(|p| p.get(name)).and_then(|p| p.as_table()) {
+                Some(overlay) => merge_tables(&mut table, overlay.clone()),
+                None => return Err(String::from(format!("Config {} doesn't have a [profiles.{}] section", path, name))),
+            }
+        }
+        for (name, keys, value) in overrides {
+            if let Err(e) = set_path(&mut table, &keys, value) {
+                return Err(String::from(format!("Couldn't apply {}: {}", name, e)));
+            }
+        }
+        let missing: Vec<&str> = REQUIRED.iter().copied().filter(|r| !has_path(&table, r)).collect();
+        if !missing.is_empty() {
+            return Err(String::from(format!("Config {} is missing {}", path, missing.join(", "))));
+        }
+        match toml::Value::Table(table).try_into::<Config>() {
+            Ok(mut c) => {
+                c.profile = profile.map(String::from);
+                Ok(c)
+            }
+            Err(e) => Err(String::from(format!("Couldn't load config from {} and the environment: {}", path, e))),
+        }
+    }
+
+    /// Everything we can tell is wrong without actually connecting anywhere, all of it
+    /// at once. Whether the channels really exist is up to the bot's preflight
+    pub fn check(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::<String>::new();
+        if let Err(e) = check_token(&self.discord.bot_token) {
+            problems.push(e);
+        }
+        for (name, id) in self.discord_ids() {
+            if !looks_like_id(id) {
+                problems.push(String::from(format!("{} is {}, that's not a discord id", name, id)));
+            }
+        }
+        if self.destinations().is_empty() {
+            problems.push(String::from("Nowhere to post, add some channels.destinations"));
+        }
+        // Users, subreddits, searches, multis and rules all count
+        if reddit::sources(self).is_empty() {
+            problems.push(String::from("There's nothing to watch, we need somebody to sniff"));
+        }
+        let sinks = self.sink_names();
+        for rule in &self.rules {
+            let name = rule.name.clone().unwrap_or_else(|| rule.sources.join(", "));
+            if rule.destinations.is_empty() && rule.sinks.is_empty() {
+                problems.push(String::from(format!("Rule {} doesn't send anything anywhere", name)));
+            }
+            for sink in rule.sinks.iter().filter(|s| !sinks.contains(s)) {
+                problems.push(String::from(format!("Rule {} sends to {}, but there's nothing called that", name, sink)));
+            }
+        }
+        if let Some(mastodon) = &self.mastodon {
+            if !mastodon.instance.starts_with("https://") && !mastodon.instance.starts_with("http://") {
+                problems.push(String::from(format!("mastodon.instance is {}, it should look like https://mastodon.social", mastodon.instance)));
+            }
+        }
+        if let Some(irc) = &self.irc {
+            if !irc.channel.starts_with('#') && !irc.channel.starts_with('&') {
+                problems.push(String::from(format!("irc.channel is {}, channels start with a #", irc.channel)));
+            }
+            if irc.nick.is_empty() || irc.nick.contains(' ') {
+                problems.push(String::from(format!("irc.nick is \"{}\", that's not a nick", irc.nick)));
+            }
+            if irc.sasl_username.is_some() != irc.sasl_password.is_some() {
+                problems.push(String::from("irc needs both sasl_username and sasl_password, or neither"));
+            }
+        }
+        if let Some(email) = &self.email {
+            if email.to.is_empty() {
+                problems.push(String::from("email.to is empty, nobody would get anything"));
+            }
+            if email.digest_minutes == Some(0) {
+                problems.push(String::from("email.digest_minutes is 0, leave it out to email every post"));
+            }
+            if email.username.is_some() != email.password.is_some() {
+                problems.push(String::from("email needs both username and password, or neither"));
+            }
+        }
+        if let Some(slack) = &self.slack {
+            for d in &slack.destinations {
+                match (&d.webhook, &d.channel) {
+                    (Some(_), Some(_)) | (None, None) => {
+                        problems.push(String::from(format!("Slack destination {} needs a webhook or a channel, just the one", d.name)));
+                    }
+                    (None, Some(_)) if slack.token.is_none() => {
+                        problems.push(String::from(format!("Slack destination {} has a channel, that needs slack.token", d.name)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for (i, hook) in self.hooks.iter().enumerate() {
+            if !hook.url.starts_with("https://") && !hook.url.starts_with("http://") {
+                problems.push(String::from(format!("Hook {} has url {}, that's not an http url", hook.name, hook.url)));
+            }
+            if hook.secret.as_deref() == Some("") {
+                problems.push(String::from(format!("Hook {} has an empty secret, leave it out to not sign", hook.name)));
+            }
+            if self.hooks[..i].iter().any(|h| h.name == hook.name) {
+                problems.push(String::from(format!("There's more than one hook called {}", hook.name)));
+            }
+        }
+        if self.polling.interval < MIN_POLL_INTERVAL || self.polling.interval > MAX_POLL_INTERVAL {
+            problems.push(String::from(format!(
+                "polling.interval is {}s, it has to be between {}s and {}s", self.polling.interval, MIN_POLL_INTERVAL, MAX_POLL_INTERVAL
+            )));
+        }
+        if self.polling.jitter > self.polling.interval {
+            problems.push(String::from(format!("polling.jitter is {}s, more than the interval itself", self.polling.jitter)));
+        }
+        if self.reddit.client.max_concurrent == 0 {
+            problems.push(String::from("reddit.client.max_concurrent is 0, we'd never get anything"));
+        }
+        if self.reddit.client.timeout_secs == 0 {
+            problems.push(String::from("reddit.client.timeout_secs is 0, every request would time out"));
+        }
+        if let Err(e) = crate::filter::Filters::new(&self.filters, &self.destinations(), &self.rules) {
+            problems.push(e);
+        }
+        for multi in &self.watch.multireddits {
+            if reddit::Source::parse_multi(multi).is_none() {
+                problems.push(String::from(format!("{} isn't a multireddit, they look like user/m/name", multi)));
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            if metrics.listen.parse::<std::net::SocketAddr>().is_err() {
+                problems.push(String::from(format!("metrics.listen is {}, it should look like 127.0.0.1:9184", metrics.listen)));
+            }
+        }
+        if let Err(e) = crate::logging::filter(&self.logging.level) {
+            problems.push(String::from(format!("logging.level: {}", e)));
+        }
+        if self.logging.file && self.logging.size == 0 && self.logging.rotate.is_none() {
+            problems.push(String::from("logging.size is 0 and there's no logging.rotate, the log would never roll over"));
+        }
+        if let Some(sentry) = &self.sentry {
+            if !(0.0..=1.0).contains(&sentry.sample_rate) {
+                problems.push(String::from(format!("sentry.sample_rate is {}, it should be between 0 and 1", sentry.sample_rate)));
+            }
+        }
+        if self.logging.max_age_days == Some(0) {
+            problems.push(String::from("logging.max_age_days is 0, every old log would be deleted as soon as it rolled"));
+        }
+        if let Some(health) = &self.health {
+            if health.listen.parse::<std::net::SocketAddr>().is_err() {
+                problems.push(String::from(format!("health.listen is {}, it should look like 0.0.0.0:8080", health.listen)));
+            }
+        }
+        if let Some(api) = &self.api {
+            if api.listen.parse::<std::net::SocketAddr>().is_err() {
+                problems.push(String::from(format!("api.listen is {}, it should look like 127.0.0.1:9185", api.listen)));
+            }
+            if api.token.len() < 16 {
+                problems.push(String::from("api.token is too short, anyone could guess it. Make it at least 16 characters"));
+            }
+            if self.discord.webhook_only {
+                problems.push(String::from("The api doesn't run in webhook only mode, there's no bot to drive"));
+            }
+        }
+        if self.discord.shard_reports.as_ref().map_or(false, |r| r.interval_minutes == 0) {
+            problems.push(String::from("discord.shard_reports.interval_minutes is 0, that's a lot of reports"));
+        }
+        if self.alerts.as_ref().map_or(false, |a| a.max_per_hour == 0) {
+            problems.push(String::from("alerts.max_per_hour is 0, nothing would ever get sent"));
+        }
+        if self.store.retention_days == Some(0) {
+            problems.push(String::from("store.retention_days is 0, we'd forget everything straight away"));
+        }
+        if self.watch.modqueue.is_some() && self.reddit.auth.is_none() {
+            problems.push(String::from("watch.modqueue needs reddit.auth for a mod account"));
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems),
+        }
+    }
+
+    /// Everywhere our posts go, with anything a destination didn't set filled in from [posting].
+    /// Old configs just have a main and archive channel, those get turned into destinations
+    pub fn destinations(&self) -> Vec<discord::Destination> {
+        let mut destinations = self.channels.destinations.clone();
+        if destinations.is_empty() {
+            if let Some(c) = self.channels.main {
+                destinations.push(discord::Destination { ping_roles: true, pin_high_scores: true, ..discord::Destination::bare(c) });
+            }
+            if let Some(c) = self.channels.archive {
+                destinations.push(discord::Destination { include_url: true, ..discord::Destination::bare(c) });
+            }
+        }
+        for rule in &self.rules {
+            for channel in &rule.destinations {
+                if !destinations.iter().any(|d| d.channel == *channel) {
+                    destinations.push(discord::Destination::bare(*channel));
+                }
+            }
+        }
+        for d in destinations.iter_mut() {
+            d.embed = d.embed.or(Some(self.posting.embed_posts));
+            if d.deleted_action.is_none() {
+                d.deleted_action = Some(self.posting.deleted_action.clone());
+            }
+        }
+        destinations
+    }
+
+    /// Webhooks are the only thing that dress posts up as their author, so they're the only
+    /// reason to look up author icons
+    pub fn has_webhooks(&self) -> bool {
+        self.destinations().iter().any(|d| d.webhook.is_some())
+    }
+
+    /// Everything besides discord channels that rules can send to, like slack:general
+    pub fn sink_names(&self) -> Vec<String> {
+        let mut names = Vec::<String>::new();
+        if let Some(slack) = &self.slack {
+            names.extend(slack.destinations.iter().map(|d| d.sink_name()));
+        }
+        if self.mastodon.is_some() {
+            names.push(String::from(crate::mastodon::SINK_NAME));
+        }
+        if self.email.is_some() {
+            names.push(String::from(crate::email::SINK_NAME));
+        }
+        if self.irc.is_some() {
+            names.push(String::from(crate::irc::SINK_NAME));
+        }
+        names.extend(self.hooks.iter().map(|h| h.sink_name()));
+        names
+    }
+
+    /// Every discord id in the config and where it came from, for checking them
+    pub fn discord_ids(&self) -> Vec<(String, u64)> {
+        let mut ids = vec![
+            (String::from("discord.application_id"), self.discord.application_id),
+            (String::from("discord.guild_id"), self.discord.guild_id),
+            (String::from("channels.audio"), self.channels.audio),
+        ];
+        if let Some(id) = self.channels.test {
+            ids.push((String::from("channels.test"), id));
+        }
+        if let Some(id) = self.discord.admin_user {
+            ids.push((String::from("discord.admin_user"), id));
+        }
+        if let Some(id) = self.channels.main {
+            ids.push((String::from("channels.main"), id));
+        }
+        if let Some(id) = self.channels.archive {
+            ids.push((String::from("channels.archive"), id));
+        }
+        for d in &self.channels.destinations {
+            ids.push((String::from("channels.destinations channel"), d.channel));
+        }
+        for (name, id) in self.side_channels() {
+            ids.push((name, id));
+        }
+        ids
+    }
+
+    /// Channels the side watchers post to, outside of the destinations
+    pub fn side_channels(&self) -> Vec<(String, u64)> {
+        let mut channels = Vec::new();
+        for w in &self.watch.live_threads {
+            channels.push((String::from(format!("watch.live_threads {} channel", w.id)), w.channel));
+        }
+        for w in &self.watch.wiki_pages {
+            channels.push((String::from(format!("watch.wiki_pages {} channel", w.page)), w.channel));
+        }
+        if let Some(m) = &self.watch.modqueue {
+            channels.push((String::from("watch.modqueue.channel"), m.channel));
+        }
+        if let Some(c) = self.alerts.as_ref().and_then(|a| a.channel) {
+            channels.push((String::from("alerts.channel"), c));
+        }
+        if let Some(c) = self.discord.shard_reports.as_ref().and_then(|r| r.channel) {
+            channels.push((String::from("discord.shard_reports.channel"), c));
+        }
+        if let Some(c) = self.watch.account_alerts.as_ref().and_then(|a| a.channel) {
+            channels.push((String::from("watch.account_alerts.channel"), c));
+        }
+        channels
+    }
+
+    /// Every reddit user we follow, the sniffer first
+    pub fn watched_users(&self) -> Vec<String> {
+        let mut users = Vec::<String>::new();
+        if !self.watch.sniffer.trim().is_empty() {
+            users.push(self.watch.sniffer.clone());
+        }
+        let rule_users = self.rules.iter()
+            .filter_map(|r| r.parsed_sources().ok())
+            .flatten()
+            .filter_map(|s| match s {
+                reddit::Source::User(u) | reddit::Source::UserComments(u) => Some(u),
+                _ => None,
+            });
+        for user in self.watch.users.iter().map(|u| u.trim_start_matches("u/").to_string()).chain(rule_users) {
+            if !users.iter().any(|u| u.eq_ignore_ascii_case(&user)) {
+                users.push(user);
+            }
+        }
+        users
+    }
+}
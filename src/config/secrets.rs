@@ -0,0 +1,226 @@
+use std::io::Read;
+use std::str::FromStr;
+
+use age::secrecy::Secret;
+use serde::Deserialize;
+
+use super::Config;
+
+// Where the key for an age encrypted config file comes from, either an identity
+// (AGE-SECRET-KEY-...) or the passphrase it was encrypted with
+pub const AGE_KEY_VAR: &str = "AGE_KEY";
+pub const AGE_PASSPHRASE_VAR: &str = "AGE_PASSPHRASE";
+
+/// Where secrets in the config can come from. A secret is any of the supported values written
+/// like `vault:secret/data/sniffer#bot_token` or `aws:sniffer/prod#bot_token`, the part after
+/// the # picks a key out of the secret and without it you get the whole thing
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SecretsConfig {
+    pub vault: Option<VaultConfig>,
+    pub aws: Option<AwsConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct VaultConfig {
+    // Like https://vault.example.com:8200
+    pub address: String,
+    // Falls back to VAULT_TOKEN, which is where the vault cli leaves it
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AwsConfig {
+    // Otherwise it's whatever the usual AWS environment and profile setup says
+    pub region: Option<String>,
+}
+
+/// One of the backends a secret can live in
+enum SecretSource {
+    Vault,
+    Aws,
+}
+
+impl SecretSource {
+    // Split a config value into where it lives, the path and the key, if it's a secret at all
+    fn parse(value: &str) -> Option<(SecretSource, &str, Option<&str>)> {
+        let (source, rest) = if let Some(rest) = value.strip_prefix("vault:") {
+            (SecretSource::Vault, rest)
+        }
+        else if let Some(rest) = value.strip_prefix("aws:") {
+            (SecretSource::Aws, rest)
+        }
+        else {
+            return None;
+        };
+        match rest.split_once('#') {
+            Some((path, key)) => Some((source, path, Some(key))),
+            None => Some((source, rest, None)),
+        }
+    }
+
+    async fn fetch(&self, config: &SecretsConfig, path: &str, key: Option<&str>) -> Result<String, String> {
+        match self {
+            SecretSource::Vault => match &config.vault {
+                Some(vault) => fetch_vault(vault, path, key).await,
+                None => Err(String::from("there's no [secrets.vault] section")),
+            },
+            SecretSource::Aws => fetch_aws(config.aws.clone().unwrap_or_default(), path, key).await,
+        }
+    }
+}
+
+/// Swap every secret reference in the config for the real thing. Only the discord token, the
+/// reddit credentials and the other platforms' tokens get looked at, nothing else is secret
+pub async fn resolve(config: &mut Config) -> Result<(), String> {
+    let secrets = config.secrets.clone();
+    let mut problems = Vec::<String>::new();
+    let mut fields = vec![(String::from("discord.bot_token"), &mut config.discord.bot_token)];
+    if let Some(auth) = config.reddit.auth.as_mut() {
+        fields.push((String::from("reddit.auth.client_id"), &mut auth.client_id));
+        fields.push((String::from("reddit.auth.client_secret"), &mut auth.client_secret));
+        fields.push((String::from("reddit.auth.username"), &mut auth.username));
+        fields.push((String::from("reddit.auth.password"), &mut auth.password));
+    }
+    if let Some(api) = config.api.as_mut() {
+        fields.push((String::from("api.token"), &mut api.token));
+    }
+    if let Some(token) = config.slack.as_mut().and_then(|s| s.token.as_mut()) {
+        fields.push((String::from("slack.token"), token));
+    }
+    if let Some(mastodon) = config.mastodon.as_mut() {
+        fields.push((String::from("mastodon.access_token"), &mut mastodon.access_token));
+    }
+    if let Some(password) = config.email.as_mut().and_then(|e| e.password.as_mut()) {
+        fields.push((String::from("email.password"), password));
+    }
+    if let Some(password) = config.irc.as_mut().and_then(|i| i.sasl_password.as_mut()) {
+        fields.push((String::from("irc.sasl_password"), password));
+    }
+    for hook in config.hooks.iter_mut() {
+        if let Some(secret) = hook.secret.as_mut() {
+            fields.push((format!("hooks.{}.secret", hook.name), secret));
+        }
+    }
+    for (name, value) in fields {
+        let (source, path, key) = match SecretSource::parse(value) {
+            Some(s) => s,
+            None => continue,
+        };
+        match source.fetch(&secrets, path, key).await {
+            Ok(secret) => {
+                debug!("Got {} from the secrets backend", name);
+                *value = secret;
+            }
+            Err(e) => problems.push(String::from(format!("Couldn't get {} from {}: {}", name, value, e))),
+        }
+    }
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems.join("\n")),
+    }
+}
+
+/// Whether a config file is age encrypted, armored or not
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"age-encryption.org/") || bytes.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+}
+
+/// Decrypt an age encrypted config file with the key or passphrase from SNIFFER_AGE_KEY or
+/// SNIFFER_AGE_PASSPHRASE, make one with `age -r <recipient> -a sniffer.toml > sniffer.toml.age`
+pub fn decrypt(bytes: &[u8]) -> Result<String, String> {
+    let decryptor = match age::Decryptor::new(age::armor::ArmoredReader::new(bytes)) {
+        Ok(d) => d,
+        Err(e) => return Err(String::from(format!("not a file age can read: {}", e))),
+    };
+    let key = std::env::var(format!("SNIFFER_{}", AGE_KEY_VAR)).ok();
+    let passphrase = std::env::var(format!("SNIFFER_{}", AGE_PASSPHRASE_VAR)).ok();
+    let reader = match (decryptor, key, passphrase) {
+        (age::Decryptor::Recipients(d), Some(key), _) => {
+            let identity = match age::x25519::Identity::from_str(key.trim()) {
+                Ok(i) => i,
+                Err(e) => return Err(String::from(format!("SNIFFER_{} isn't an age identity: {}", AGE_KEY_VAR, e))),
+            };
+            d.decrypt(std::iter::once(&identity as &dyn age::Identity))
+        }
+        (age::Decryptor::Passphrase(d), _, Some(passphrase)) => d.decrypt(&Secret::new(passphrase), None),
+        (age::Decryptor::Recipients(_), None, _) => {
+            return Err(String::from(format!("it's encrypted to a key, set SNIFFER_{}", AGE_KEY_VAR)));
+        }
+        (age::Decryptor::Passphrase(_), _, None) => {
+            return Err(String::from(format!("it's encrypted with a passphrase, set SNIFFER_{}", AGE_PASSPHRASE_VAR)));
+        }
+    };
+    let mut reader = match reader {
+        Ok(r) => r,
+        Err(e) => return Err(String::from(format!("wrong key: {}", e))),
+    };
+    let mut text = String::new();
+    match reader.read_to_string(&mut text) {
+        Ok(_) => Ok(text),
+        Err(e) => Err(String::from(format!("couldn't read it back: {}", e))),
+    }
+}
+
+// Works with both KV engines, v2 nests the secret a level deeper under data
+async fn fetch_vault(config: &VaultConfig, path: &str, key: Option<&str>) -> Result<String, String> {
+    let token = match config.token.clone().or_else(|| std::env::var("VAULT_TOKEN").ok()) {
+        Some(t) => t,
+        None => return Err(String::from("no vault token in [secrets.vault] or VAULT_TOKEN")),
+    };
+    let url = format!("{}/v1/{}", config.address.trim_end_matches('/'), path.trim_start_matches('/'));
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header("X-Vault-Token", token)
+        .send().await
+        .and_then(|r| r.error_for_status());
+    let body = match response {
+        Ok(r) => match r.json::<serde_json::Value>().await {
+            Ok(b) => b,
+            Err(e) => return Err(String::from(format!("vault gave us something odd: {}", e))),
+        },
+        Err(e) => return Err(String::from(format!("vault said no: {}", e))),
+    };
+    let data = match body["data"].get("data") {
+        Some(d) if d.is_object() => d,
+        _ => &body["data"],
+    };
+    pick_key(data, key)
+}
+
+async fn fetch_aws(config: AwsConfig, id: &str, key: Option<&str>) -> Result<String, String> {
+    let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+    if let Some(region) = config.region {
+        loader = loader.region(aws_config::Region::new(region));
+    }
+    let client = aws_sdk_secretsmanager::Client::new(&loader.load().await);
+    let secret = match client.get_secret_value().secret_id(id).send().await {
+        Ok(s) => s,
+        Err(e) => return Err(String::from(format!("secrets manager said no: {}", e))),
+    };
+    let text = match secret.secret_string() {
+        Some(t) => t,
+        None => return Err(String::from("it's a binary secret, we only do strings")),
+    };
+    match key {
+        // Key/value secrets come out as a json object
+        Some(_) => match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(v) => pick_key(&v, key),
+            Err(e) => Err(String::from(format!("asked for a key but the secret isn't json: {}", e))),
+        },
+        None => Ok(text.to_string()),
+    }
+}
+
+fn pick_key(data: &serde_json::Value, key: Option<&str>) -> Result<String, String> {
+    let value = match key {
+        Some(k) => match data.get(k) {
+            Some(v) => v,
+            None => return Err(String::from(format!("the secret doesn't have a {} key", k))),
+        },
+        None => data,
+    };
+    match value {
+        serde_json::Value::String(s) => Ok(s.clone()),
+        other => Err(String::from(format!("expected a string, got {}", other))),
+    }
+}
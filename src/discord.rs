@@ -1,275 +1,2033 @@
-// For sniffer post struct
-use crate::reddit::SnifferPost;
-use crate::Secrets;
-use crate::audio::player::{AudioPlayer};
-use crate::commands::Parser;
-
-use std::sync::Arc;
-use tokio::select;
-use tokio::sync::{RwLock, Mutex};
-use tokio_util::sync::CancellationToken;
-
-// For Discord
-use serenity::{
-    prelude::*,
-    model::{id::{ChannelId, EmojiId}},
-    model::{event::ResumedEvent, gateway::{Ready, Activity}},
-    client::{Client, bridge::gateway::ShardManager},
-    model::channel::{Message, ReactionType},
-    async_trait,
-};
-
-// Enable songbird register trait for serenity
-use songbird::SerenityInit;
-
-// The reset presence and activity action for both ready and result
-async fn set_status(ctx: &Context) {
-    ctx.reset_presence().await;
-    ctx.set_activity(Activity::watching("the sniffer")).await;
-}
-
-fn react_success(ctx: &Context, message: &Message) {
-    tokio::task::block_in_place(move || {
-        tokio::runtime::Handle::current().block_on(async move {
-            message.react(ctx.http.clone(), ReactionType::Custom{
-                animated: false,
-                id: EmojiId(801166698610294895),
-                name: Some(String::from(":guthchamp:")),
-            }).await.expect("Failed to react to post");
-        })
-    });
-}
-
-fn react_fail(ctx: &Context, message: &Message) {
-    tokio::task::block_in_place(move || {
-        tokio::runtime::Handle::current().block_on(async move {
-            message.react(ctx.http.clone(), ReactionType::Custom{
-                animated: false,
-                id: EmojiId(886356280934006844),
-                name: Some(String::from(":final_pepe:")),
-            }).await.expect("Failed to react to post");
-        })
-    });
-}
-
-struct BotEventHandler {
-    listen_channel: ChannelId,
-    parser: Parser,
-}
-
-#[async_trait]
-impl EventHandler for BotEventHandler {
-
-    async fn ready(&self, ctx: Context, ready: Ready) {
-        warn!("Connected as {}, setting bot to online", ready.user.name);
-        set_status(&ctx).await;
-    }
-
-    async fn resume(&self, ctx: Context, _: ResumedEvent) {
-        warn!("Resumed (reconnected)");
-        set_status(&ctx).await;
-    }
-
-    async fn message(&self, ctx: Context, new_message: Message) {
-        // Make sure we're listening in our designated channel, and we ignore messages from ourselves
-        if (new_message.channel_id == self.listen_channel) && !new_message.author.bot {
-
-            match self.parser.process(&ctx, &new_message).await {
-                Ok(()) => {
-                    react_success(&ctx, &new_message);
-                }
-                Err(e) => {
-                    error!("{}", e);
-                    react_fail(&ctx, &new_message);
-                }
-            }
-        }
-    }
-}
-
-
-
-pub struct DiscordBot {
-    serenity_bot: Arc<RwLock<Client>>,
-    bot_http: Arc<serenity::http::client::Http>,
-    shard_handle: Option<futures_locks::Mutex<tokio::task::JoinHandle<()>>>,
-    shard_cancel_token: CancellationToken,
-    shard_manager: Arc<Mutex<ShardManager>>,
-    chat_channel: ChannelId,
-    test_channel: ChannelId,
-    archive_channel: ChannelId,
-    audio_player: Arc<Mutex<AudioPlayer>>,
-    command_parser: Parser,
-}
-
-impl DiscordBot {
-    pub async fn new(secrets: Secrets) -> DiscordBot {
-        info!("Created the discord bot");
-        // Configure the client with your Discord bot token in the environment.
-        let token = secrets.bot_token;
-        let audio_channel = ChannelId(secrets.audio_channel);
-
-        // Create an audio player in a mutex and its serenity callback listener
-        let audio_player_lock = AudioPlayer::new(
-            secrets.audio_channel, 
-            10,
-            std::time::Duration::from_secs(60),
-        ).await;
-        warn!("Created audio player instance");
-
-        // Create our command parser
-        let parser = Parser::new(audio_player_lock.clone()); // Give it the lock as it'll need to run audio commands
-
-        // Create a new instance of the Client, logging in as a bot. This will
-        // automatically prepend your bot token with "Bot ", which is a requirement
-        // by Discord for bot users.
-        let mut audioplayer = audio_player_lock.lock().await; // Lock the player so we can do some work
-        let serenity_bot = Client::builder(&token)
-            .event_handler(BotEventHandler{ listen_channel: audio_channel, parser: parser.clone() })
-            .register_songbird_with(audioplayer.get_songbird())
-            .await
-            .expect("Error creating client");
-        // Initialize songbird with it
-        audioplayer.init_player(serenity_bot.cache_and_http.clone(), 1, secrets.guild_id).await;
-        drop(audioplayer); // drop the lock so we can pass it off to our bot struct
-
-        // Get a shared ref of our http cache so we can use it to send messages in an async fashion
-        let http = serenity_bot.cache_and_http.http.clone();
-        // And for shard manager too
-        let manager_clone = serenity_bot.shard_manager.clone();
-        let bot = DiscordBot {
-                serenity_bot: Arc::new(RwLock::new(serenity_bot)),
-                bot_http: http,
-                shard_handle: None,
-                shard_cancel_token: CancellationToken::new(),
-                shard_manager: manager_clone,
-                chat_channel: ChannelId(secrets.main_channel), // main channel
-                test_channel: ChannelId(secrets.test_channel),
-                archive_channel: ChannelId(secrets.archive_channel), // the archive channel
-                audio_player: audio_player_lock.clone(),
-                command_parser: parser,
-            };
-
-        return bot;
-    }
-
-    pub async fn start_shards(&mut self, num_shards: u64) {
-        let bot = self.serenity_bot.clone();
-        let cloned_token = self.shard_cancel_token.clone();
-        self.shard_handle = Some(futures_locks::Mutex::new(
-            tokio::spawn(async move {
-                let mut lock = bot.write().await;
-                select! {
-                    _ = lock.start_shards(num_shards) => {  
-                        warn!("Shard threads stopped")
-                    }
-                    _ = cloned_token.cancelled() => {
-                        warn!("Cancelled our shards")
-                    }
-                }
-            })
-        ));
-        warn!("Started shards");
-        
-    }
-
-    pub async fn print_shard_info(&self) {
-        let lock = self.shard_manager.lock().await;
-        let shard_runners = lock.runners.lock().await;
-        for (id, runner) in shard_runners.iter() {
-            warn!(
-                "Shard ID {} is {} with a latency of {:?}",
-                id, runner.stage, runner.latency,
-            );
-        }
-    }
-
-    pub async fn shutdown(self) {
-        self.stop_audio().await;
-        self.stop_shards().await; // we hold a write lock on serenity here, it's its run future
-    }
-
-    //TODO: Find a way to make sure we can get the same instance of our original audio player
-    async fn stop_audio(&self) {
-        // If we have a player, hang up
-        //if let Some(player_lock) = &self.audio_player {
-            let mut player = self.audio_player.lock().await;
-            if let Err(x) = player.shutdown() {
-                error!("Error shutting down player: {}", x);
-            }
-            // This is dumb as hell, but if we don't wait a little bit we'll remove the shards
-            // before it has a chance to leave, they should really have a leave_blocking function
-            // There's nothing we can poll to check to see if we've fully left either, the
-            // associated structs reflect the state immediately
-
-            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-        //}
-    }
-
-    async fn stop_shards(&self) {
-        // Start the cancel
-        self.shard_cancel_token.cancel();
-        // Wait on our handle
-        match &self.shard_handle{
-            Some(x) => {
-                let handle_lock = x.lock();
-                handle_lock.await;
-                warn!("Successfully waited on future");
-            }
-            None => {
-                error!("We don't have a shard handle")
-            }
-        }
-    }
-
-    pub async fn post_message(&self, message: SnifferPost) {
-        let http = &self.bot_http;
-        info!("Trying to send message: {}", message);
-        let mut message_text = message.discord_string();
-
-        // Send message to our primary channel
-        self.chat_channel.say(&http, message_text.clone()).await.expect("Error sending message to main channel");
-
-        // Send message to our archive channel with url attached
-        // Append the post url to this one if we have it
-        match message.url { 
-            Some(m) => {
-                message_text.push_str(format!("\n<{}>", m).as_str());
-            }
-            None => {}
-        }
-        self.archive_channel.say(&http, message_text).await.expect("Error sending message to archive");
-    }
-
-    #[allow(dead_code)]
-    pub async fn post_debug_string(&self, message: String) {
-        let http = &self.bot_http;
-        warn!("Trying to send debug message");
-        self.test_channel.say(&http, message.clone()).await.expect("Error sending test message");
-    }
-}
-
-
-
-impl Clone for DiscordBot {
-    fn clone(&self) -> Self {
-        DiscordBot {
-            serenity_bot: self.serenity_bot.clone(),
-            bot_http: self.bot_http.clone(),
-            shard_handle: {
-                match &self.shard_handle {
-                    Some(h) => Some(h.clone()),
-                    None => None,
-                }
-            },
-            shard_cancel_token: self.shard_cancel_token.clone(),
-            shard_manager: self.shard_manager.clone(),
-            chat_channel: self.chat_channel.clone(),
-            test_channel: self.test_channel.clone(),
-            archive_channel: self.archive_channel.clone(),
-            audio_player: self.audio_player.clone(),
-            command_parser: self.command_parser.clone(),
-        }
-    }
-}
-
+pub mod slash;
+pub mod webhook;
+pub mod moderation;
+pub mod queue;
+pub mod media;
+pub mod forum;
+pub mod split;
+pub mod alerts;
+
+// For sniffer post struct
+use crate::reddit::{SnifferPost, PostEvent, MutedAuthors, DisabledUsers, Removal};
+use crate::reddit::oauth::RedditApi;
+use crate::config::Config;
+use crate::pipeline::PostSink;
+use crate::audio::player::{AudioPlayer};
+use crate::commands::Parser;
+use crate::retry::with_backoff;
+use crate::filter::{FilterConfig, Filters};
+use crate::store::{DeadLetter, MessageRecord, Store};
+use crate::metrics;
+use tracing::{instrument, Instrument};
+use slash::{SlashCommands, BotHealth, PollRequest, ReloadRequest, RedriveRequest, ShardManagerContainer, HISTORY_SIZE};
+use webhook::{WebhookPoster, WebhookPost};
+use moderation::{ReactionModerator, MUTE_BUTTON_PREFIX};
+use alerts::Alerts;
+use queue::SendQueue;
+use media::Media;
+use split::{split_message, message_chunks, MESSAGE_LENGTH};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::{mpsc, RwLock, Mutex};
+use tokio_util::sync::CancellationToken;
+
+// For Discord
+use serenity::{
+    prelude::*,
+    model::{id::{ChannelId, EmojiId, MessageId, RoleId, UserId}},
+    model::{event::ResumedEvent, gateway::{Ready, Activity}},
+    client::{Client, bridge::gateway::{ShardId, ShardManager}},
+    gateway::ConnectionStage,
+    model::channel::{Channel, ChannelType, Embed, Message, Reaction, ReactionType},
+    model::interactions::{Interaction, message_component::ButtonStyle},
+    model::permissions::Permissions,
+    builder::CreateMessage,
+    async_trait,
+};
+
+// Enable songbird register trait for serenity
+use songbird::SerenityInit;
+
+use serde::Deserialize;
+use handlebars::Handlebars;
+
+// How hard we try to get a message out before giving up on it
+const SEND_ATTEMPTS: u32 = 5;
+const SEND_RETRY_DELAY: Duration = Duration::from_secs(1);
+// How often we check if discord wants us on a different number of shards
+const SHARD_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+// How many sends can pile up before whoever's posting has to wait
+const SEND_QUEUE_SIZE: usize = 100;
+// Longest we'll wait for the send queue to empty out when we're stopping
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+// How often we look at the shards between reports, to catch one going bad
+const SHARD_WATCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A channel we send sniffed posts to, it can live in any guild the bot is in
+#[derive(Deserialize, Debug, Clone)]
+pub struct Destination {
+    pub channel: u64,
+    // Only posts from these subreddits land here, empty means all of them
+    #[serde(default)]
+    pub subreddits: Vec<String>,
+    // Use these filters here instead of the global ones
+    pub filters: Option<FilterConfig>,
+    // Tack the post's link onto the message, what the archive channel does
+    #[serde(default)]
+    pub include_url: bool,
+    // Post through this webhook url instead of as the bot
+    pub webhook: Option<String>,
+    // Whether keyword role pings go out here
+    #[serde(default)]
+    pub ping_roles: bool,
+    // Start a discussion thread off each post, archived after this many minutes of quiet
+    #[serde(default)]
+    pub create_thread: bool,
+    #[serde(default = "default_thread_archive_minutes")]
+    pub thread_archive_minutes: u16,
+    // What happens to nsfw posts here
+    #[serde(default)]
+    pub nsfw: NsfwPolicy,
+    // This is a forum channel, every sniff becomes its own forum post
+    #[serde(default)]
+    pub forum: bool,
+    // If this is an announcement channel, publish our posts out to the servers following it
+    #[serde(default)]
+    pub publish: bool,
+    // Pin posts here that do well, see AutoPinConfig
+    #[serde(default)]
+    pub pin_high_scores: bool,
+    // Add "Open on Reddit" and "Mute this author" buttons under our posts
+    #[serde(default)]
+    pub buttons: bool,
+    // Reply to our post with what changed when it gets edited
+    #[serde(default)]
+    pub show_edits: bool,
+    // Reply to our post with what it said when it gets deleted or removed
+    #[serde(default)]
+    pub deleted_followup: bool,
+    // Reply to poll posts with the results once they close
+    #[serde(default)]
+    pub poll_results: bool,
+    // Whether stickied posts and mod announcements come here, only come here, or stay out
+    #[serde(default)]
+    pub announcements: AnnouncementPolicy,
+    // Reply to our post when it gets gilded or picks up other awards
+    #[serde(default)]
+    pub award_followups: bool,
+    // Handlebars template for the text of our messages, gets every post field plus reddit_url and discord_timestamp
+    pub template: Option<String>,
+    // Post rich embeds here instead of plain text, [posting] embed_posts if it's not set
+    pub embed: Option<bool>,
+    // What to do with our messages here when their post gets deleted, [posting] deleted_action if it's not set
+    pub deleted_action: Option<DeletedAction>,
+}
+
+// Discord only accepts 60, 1440, 4320 or 10080 here
+fn default_thread_archive_minutes() -> u16 {
+    1440
+}
+
+// Give reddit a minute to tally up after a poll closes
+const POLL_RESULTS_DELAY: u64 = 60;
+
+// Discord caps thread names at 100 characters
+const THREAD_NAME_LENGTH: usize = 100;
+
+impl Destination {
+    /// Just the channel, every option at its default
+    pub fn bare(channel: u64) -> Destination {
+        Destination {
+            channel: channel,
+            subreddits: Vec::new(),
+            filters: None,
+            include_url: false,
+            webhook: None,
+            ping_roles: false,
+            create_thread: false,
+            thread_archive_minutes: default_thread_archive_minutes(),
+            nsfw: NsfwPolicy::default(),
+            forum: false,
+            publish: false,
+            pin_high_scores: false,
+            buttons: false,
+            show_edits: false,
+            deleted_followup: false,
+            poll_results: false,
+            announcements: AnnouncementPolicy::default(),
+            award_followups: false,
+            template: None,
+            embed: None,
+            deleted_action: None,
+        }
+    }
+
+    pub fn embeds(&self) -> bool {
+        self.embed.unwrap_or(false)
+    }
+
+    /// Whether a post has to go behind spoilers here
+    pub fn spoilers(&self, post: &SnifferPost) -> bool {
+        post.nsfw && self.nsfw == NsfwPolicy::Spoiler
+    }
+
+    /// The post as its embed should show it here
+    pub fn embed_post(&self, post: &SnifferPost) -> SnifferPost {
+        match self.spoilers(post) {
+            true => post.spoilered_embed(),
+            false => post.clone(),
+        }
+    }
+
+    /// Archives always get marked, whatever we're set to do everywhere else
+    pub fn on_deleted(&self) -> DeletedAction {
+        match (self.deleted_action.clone().unwrap_or_default(), self.include_url) {
+            (DeletedAction::Ignore, true) => DeletedAction::Annotate,
+            (action, _) => action,
+        }
+    }
+
+    /// Whether this destination is routed posts from the post's subreddit, and of its kind
+    pub fn wants(&self, post: &SnifferPost) -> bool {
+        let kind = match self.announcements {
+            AnnouncementPolicy::Include => true,
+            AnnouncementPolicy::Only => post.is_announcement(),
+            AnnouncementPolicy::Exclude => !post.is_announcement(),
+        };
+        kind && (self.subreddits.is_empty() ||
+            self.subreddits.iter().any(|s| s.trim_start_matches("r/").eq_ignore_ascii_case(&post.subreddit)))
+    }
+
+    /// The plain text version of a post for this destination
+    pub fn format_text(&self, post: &SnifferPost) -> String {
+        if let Some(template) = &self.template {
+            match render_template(template, post) {
+                Ok(t) => return t,
+                Err(e) => error!("Bad template for channel {}, using the default format: {}", self.channel, e),
+            }
+        }
+        let mut message_text = post.discord_string();
+        // Append the post url to this one if we have it and the destination wants it,
+        // videos get their direct link so they play right in discord
+        if self.include_url {
+            if let Some(m) = post.video.as_ref().or(post.url.as_ref()) {
+                message_text.push_str(format!("\n<{}>", m).as_str());
+            }
+            // Archives keep track of everywhere else it went up, and what it got
+            if !post.duplicates.is_empty() {
+                message_text.push_str(format!("\nAlso posted:\n{}", post.duplicate_links()).as_str());
+            }
+            if !post.awards.is_empty() {
+                message_text.push_str(format!("\n🏅 {}", SnifferPost::award_summary(&post.awards)).as_str());
+            }
+        }
+        message_text
+    }
+}
+
+/// Where stickied posts and mod announcements go
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementPolicy {
+    // Along with everything else
+    Include,
+    // Nothing but, for a dedicated announcements channel
+    Only,
+    // Leave them to the announcements channel
+    Exclude,
+}
+
+impl Default for AnnouncementPolicy {
+    fn default() -> Self {
+        AnnouncementPolicy::Include
+    }
+}
+
+/// How a destination deals with posts reddit has marked nsfw
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NsfwPolicy {
+    // Hide the text and attachments behind spoilers
+    Spoiler,
+    // Only post it if the channel is age restricted
+    Skip,
+    // Never post it here, for keeping nsfw posts to a few channels
+    Drop,
+    // Post it like anything else
+    Show,
+}
+
+impl Default for NsfwPolicy {
+    fn default() -> Self {
+        NsfwPolicy::Spoiler
+    }
+}
+
+/// Check back on posts after a while and pin the ones that took off
+#[derive(Deserialize, Debug, Clone)]
+pub struct AutoPinConfig {
+    pub delay_minutes: u64,
+    pub min_score: i64,
+}
+
+// Discord won't let a channel have more pins than this
+const MAX_PINS: usize = 50;
+
+/// Ping a role whenever a post mentions any of its keywords
+#[derive(Deserialize, Debug, Clone)]
+pub struct RolePing {
+    pub role: u64,
+    pub keywords: Vec<String>,
+}
+
+impl RolePing {
+    fn matches(&self, post: &SnifferPost) -> bool {
+        let title = post.title.to_lowercase();
+        let body = post.body.as_ref().map(|b| b.to_lowercase()).unwrap_or_default();
+        self.keywords.iter().any(|k| {
+            let k = k.to_lowercase();
+            title.contains(&k) || body.contains(&k)
+        })
+    }
+}
+
+/// Mention text for some roles, to go at the front of a message
+pub fn mention_roles(roles: &[RoleId]) -> String {
+    roles.iter().map(|r| format!("<@&{}>", r)).collect::<Vec<String>>().join(" ")
+}
+
+// The link back to reddit and the mute button, under a post
+fn add_buttons<'a, 'b>(m: &'b mut CreateMessage<'a>, post: &SnifferPost) -> &'b mut CreateMessage<'a> {
+    m.components(|c| {
+        c.create_action_row(|row| {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Link)
+                    .label("Open on Reddit")
+                    .url(format!("https://www.reddit.com{}", post.permalink))
+            });
+            row.create_button(|b| {
+                b.style(ButtonStyle::Danger)
+                    .label("Mute this author")
+                    .custom_id(format!("{}{}", MUTE_BUTTON_PREFIX, post.author))
+            })
+        })
+    })
+}
+
+/// What we do to our messages when the reddit post they came from gets deleted
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletedAction {
+    Ignore,
+    Delete,
+    Annotate,
+}
+
+impl Default for DeletedAction {
+    fn default() -> Self {
+        DeletedAction::Ignore
+    }
+}
+
+fn render_template(template: &str, post: &SnifferPost) -> Result<String, String> {
+    let mut handlebars = Handlebars::new();
+    // This is going to discord, not a web page
+    handlebars.register_escape_fn(handlebars::no_escape);
+    let mut data = match serde_json::to_value(post) {
+        Ok(d) => d,
+        Err(e) => return Err(String::from(format!("Couldn't serialize post: {}", e))),
+    };
+    data["reddit_url"] = serde_json::Value::from(format!("https://www.reddit.com{}", post.permalink));
+    data["discord_timestamp"] = serde_json::Value::from(post.discord_timestamp());
+    match handlebars.render_template(template, &data) {
+        Ok(t) => Ok(t),
+        Err(e) => Err(String::from(format!("Error rendering template: {}", e))),
+    }
+}
+
+/// How many shards discord thinks we should be running
+async fn recommended_shards(http: &serenity::http::client::Http) -> Result<u64, String> {
+    match http.get_bot_gateway().await {
+        Ok(g) => Ok(g.shards),
+        Err(e) => Err(String::from(format!("Couldn't get the recommended shard count: {}", e))),
+    }
+}
+
+// Ignore little wiggles in the recommendation, restarting shards drops us offline for a bit
+fn shard_count_changed(current: u64, recommended: u64) -> bool {
+    let diff = if recommended > current { recommended - current } else { current - recommended };
+    diff > 0 && diff * 4 >= current
+}
+
+/// Keep checking the recommendation and give back the new count once it's moved enough to matter
+async fn wait_for_new_shard_count(http: &serenity::http::client::Http, current: u64) -> u64 {
+    loop {
+        tokio::time::sleep(SHARD_CHECK_INTERVAL).await;
+        match recommended_shards(http).await {
+            Ok(n) if shard_count_changed(current, n) => return n,
+            Ok(_) => {}
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// What went wrong getting a post out, with the channels that didn't get it and why
+#[derive(Debug)]
+pub enum PostError {
+    // It didn't make it anywhere
+    Undelivered(Vec<(ChannelId, String)>),
+    // Some channels got it, these didn't
+    Partial { delivered: usize, failed: Vec<(ChannelId, String)> },
+}
+
+impl PostError {
+    pub fn failed(&self) -> &[(ChannelId, String)] {
+        match self {
+            PostError::Undelivered(f) => f,
+            PostError::Partial { failed, .. } => failed,
+        }
+    }
+}
+
+impl std::fmt::Display for PostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reasons: Vec<String> = self.failed().iter().map(|(c, e)| format!("{}: {}", c, e)).collect();
+        match self {
+            PostError::Undelivered(_) => write!(f, "Post didn't go out anywhere ({})", reasons.join(", ")),
+            PostError::Partial { delivered, .. } => {
+                write!(f, "Post only went out to {} channels ({})", delivered, reasons.join(", "))
+            }
+        }
+    }
+}
+
+/// A discord message we sent for a reddit post, so we can go back and fix it up later
+#[derive(Debug, Clone)]
+pub struct SentMessage {
+    pub destination: Destination,
+    pub channel: ChannelId,
+    pub message: MessageId,
+}
+
+/// A post that didn't all make it to a destination, and how far it got
+#[derive(Debug)]
+struct SendFailure {
+    // The first message, if that much went out this time
+    sent: Option<SentMessage>,
+    // Pieces of a split post that are out so far, counting any from before
+    chunks: usize,
+    error: String,
+}
+
+impl From<String> for SendFailure {
+    fn from(error: String) -> Self {
+        SendFailure {
+            sent: None,
+            chunks: 0,
+            error: error,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityKind {
+    Playing,
+    Listening,
+    Watching,
+    Competing,
+}
+
+/// Say how the shards are doing every so often, and straight away when one drops
+#[derive(Deserialize, Debug, Clone)]
+pub struct ShardReportConfig {
+    // Otherwise the test channel, or the admin's DMs without one
+    pub channel: Option<u64>,
+    #[serde(default = "default_shard_report_interval")]
+    pub interval_minutes: u64,
+}
+
+fn default_shard_report_interval() -> u64 {
+    6 * 60
+}
+
+/// What shows up under the bot's name
+#[derive(Deserialize, Debug, Clone)]
+pub struct Presence {
+    pub kind: ActivityKind,
+    pub text: String,
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Presence {
+            kind: ActivityKind::Watching,
+            text: String::from("the sniffer"),
+        }
+    }
+}
+
+impl Presence {
+    // Let everyone know when we're not actually sniffing
+    fn activity(&self, paused: bool) -> Activity {
+        let text = match paused {
+            true => format!("{} (paused)", self.text),
+            false => self.text.clone(),
+        };
+        match self.kind {
+            ActivityKind::Playing => Activity::playing(&text),
+            ActivityKind::Listening => Activity::listening(&text),
+            ActivityKind::Watching => Activity::watching(&text),
+            ActivityKind::Competing => Activity::competing(&text),
+        }
+    }
+}
+
+// The reset presence and activity action for both ready and result
+async fn set_status(ctx: &Context, presence: &RwLock<Presence>, paused: &AtomicBool) {
+    ctx.reset_presence().await;
+    ctx.set_activity(presence.read().await.activity(paused.load(Ordering::Relaxed))).await;
+}
+
+// Pause or resume sniffing and show it on every shard, not just whichever one asked. Gives back
+// whether that changed anything
+async fn update_paused(shard_manager: &Mutex<ShardManager>, presence: &RwLock<Presence>, paused: &AtomicBool, pause: bool) -> bool {
+    if paused.swap(pause, Ordering::Relaxed) == pause {
+        return false;
+    }
+    let activity = presence.read().await.activity(pause);
+    let lock = shard_manager.lock().await;
+    let shard_runners = lock.runners.lock().await;
+    for (_, runner) in shard_runners.iter() {
+        runner.runner_tx.set_activity(Some(activity.clone()));
+    }
+    true
+}
+
+fn react_success(ctx: &Context, message: &Message) {
+    tokio::task::block_in_place(move || {
+        tokio::runtime::Handle::current().block_on(async move {
+            message.react(ctx.http.clone(), ReactionType::Custom{
+                animated: false,
+                id: EmojiId(801166698610294895),
+                name: Some(String::from(":guthchamp:")),
+            }).await.expect("Failed to react to post");
+        })
+    });
+}
+
+fn react_fail(ctx: &Context, message: &Message) {
+    tokio::task::block_in_place(move || {
+        tokio::runtime::Handle::current().block_on(async move {
+            message.react(ctx.http.clone(), ReactionType::Custom{
+                animated: false,
+                id: EmojiId(886356280934006844),
+                name: Some(String::from(":final_pepe:")),
+            }).await.expect("Failed to react to post");
+        })
+    });
+}
+
+struct BotEventHandler {
+    listen_channel: ChannelId,
+    parser: Parser,
+    slash_commands: SlashCommands,
+    moderator: ReactionModerator,
+    presence: Arc<RwLock<Presence>>,
+    paused: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl EventHandler for BotEventHandler {
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        warn!("Connected as {}, setting bot to online", ready.user.name);
+        set_status(&ctx, &self.presence, &self.paused).await;
+        if let Err(e) = self.slash_commands.register(&ctx).await {
+            error!("{}", e);
+        }
+    }
+
+    async fn resume(&self, ctx: Context, _: ResumedEvent) {
+        warn!("Resumed (reconnected)");
+        set_status(&ctx, &self.presence, &self.paused).await;
+    }
+
+    async fn message(&self, ctx: Context, new_message: Message) {
+        // Make sure we're listening in our designated channel, and we ignore messages from ourselves
+        if (new_message.channel_id == self.listen_channel) && !new_message.author.bot {
+
+            match self.parser.process(&ctx, &new_message).await {
+                Ok(()) => {
+                    react_success(&ctx, &new_message);
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    react_fail(&ctx, &new_message);
+                }
+            }
+        }
+    }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        if let Err(e) = self.moderator.process(&ctx, &reaction).await {
+            error!("{}", e);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let result = match interaction {
+            Interaction::ApplicationCommand(command) => self.slash_commands.process(&ctx, &command).await,
+            Interaction::MessageComponent(component) => self.moderator.process_button(&ctx, &component).await,
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            error!("{}", e);
+            metrics::error("interaction");
+        }
+    }
+}
+
+
+
+pub struct DiscordBot {
+    serenity_bot: Arc<RwLock<Client>>,
+    bot_token: String,
+    bot_http: Arc<serenity::http::client::Http>,
+    shard_handle: Option<futures_locks::Mutex<tokio::task::JoinHandle<()>>>,
+    shard_cancel_token: CancellationToken,
+    shard_manager: Arc<Mutex<ShardManager>>,
+    destinations: Vec<Destination>,
+    // Swapped out whole when the config gets reloaded
+    filters: Arc<RwLock<Arc<Filters>>>,
+    test_channel: Option<ChannelId>,
+    role_pings: Vec<RolePing>,
+    webhook_poster: WebhookPoster,
+    send_queue: SendQueue,
+    http_client: reqwest::Client,
+    // Our own reddit client, for looking back in on posts we sent
+    reddit_api: Arc<RedditApi>,
+    presence: Arc<RwLock<Presence>>,
+    // Admins can stop us polling reddit for a while
+    paused: Arc<AtomicBool>,
+    admin_user: Option<UserId>,
+    alerts: Alerts,
+    auto_pin: Option<AutoPinConfig>,
+    post_history: Arc<RwLock<VecDeque<SnifferPost>>>,
+    health: Arc<RwLock<BotHealth>>,
+    // reddit post id -> every message we sent for it, for the recent ones. The store has the rest
+    sent_messages: Arc<RwLock<HashMap<String, Vec<SentMessage>>>>,
+    store: Arc<dyn Store>,
+    audio_player: Arc<Mutex<AudioPlayer>>,
+    command_parser: Parser,
+    // Log what we'd send instead of sending it
+    dry_run: bool,
+}
+
+impl DiscordBot {
+    pub async fn new(
+        config: Config,
+        muted_authors: MutedAuthors,
+        disabled_users: DisabledUsers,
+        poll_requests: mpsc::Sender<PollRequest>,
+        reload_requests: mpsc::Sender<ReloadRequest>,
+        store: Arc<dyn Store>,
+    ) -> DiscordBot {
+        info!("Created the discord bot");
+        // Grab this before we start pulling the config apart
+        let watched_users = config.watched_users();
+        // Configure the client with your Discord bot token in the environment.
+        let token = config.discord.bot_token;
+        let audio_channel = ChannelId(config.channels.audio);
+
+        // Create an audio player in a mutex and its serenity callback listener
+        let audio_player_lock = AudioPlayer::new(
+            config.channels.audio, 
+            10,
+            std::time::Duration::from_secs(60),
+        ).await;
+        warn!("Created audio player instance");
+
+        // Create our command parser
+        let parser = Parser::new(audio_player_lock.clone()); // Give it the lock as it'll need to run audio commands
+
+        let destinations = config.destinations();
+        warn!("Posting to {} destination channels", destinations.len());
+        let filters = Filters::new(&config.filters, &destinations, &config.rules).expect("Error setting up filters");
+
+        // Recently relayed posts, shared with our slash commands
+        let post_history = Arc::new(RwLock::new(VecDeque::with_capacity(HISTORY_SIZE)));
+        let health = Arc::new(RwLock::new(BotHealth::default()));
+
+        // Shared with the handler so reconnects keep whatever we last set
+        let presence = Arc::new(RwLock::new(config.discord.presence.clone()));
+        let paused = Arc::new(AtomicBool::new(false));
+        // The slash commands can't get at the bot itself, dead letter retries get handed back here
+        let (redrive_tx, mut redrive_rx) = tokio::sync::mpsc::channel::<RedriveRequest>(1);
+        // Same for the reaction moderation's archiving
+        let (archive_tx, mut archive_rx) = tokio::sync::mpsc::channel::<moderation::ArchiveRequest>(1);
+
+        let slash_commands = SlashCommands::new(
+            config.discord.guild_id,
+            config.discord.moderation.admin_role,
+            post_history.clone(),
+            health.clone(),
+            presence.clone(),
+            paused.clone(),
+            poll_requests,
+            reload_requests,
+            redrive_tx,
+            watched_users,
+            disabled_users,
+            store.clone(),
+        );
+
+        // What we've sent, shared with the reaction moderation
+        let sent_messages = Arc::new(RwLock::new(HashMap::new()));
+        let moderator = ReactionModerator::new(
+            config.discord.moderation.clone(),
+            archive_tx,
+            sent_messages.clone(),
+            post_history.clone(),
+            muted_authors,
+            store.clone(),
+        );
+
+        // Create a new instance of the Client, logging in as a bot. This will
+        // automatically prepend your bot token with "Bot ", which is a requirement
+        // by Discord for bot users.
+        let mut audioplayer = audio_player_lock.lock().await; // Lock the player so we can do some work
+        let serenity_bot = Client::builder(&token)
+            .application_id(config.discord.application_id) // needed for slash commands
+            .event_handler(BotEventHandler{
+                listen_channel: audio_channel,
+                parser: parser.clone(),
+                slash_commands: slash_commands,
+                moderator: moderator,
+                presence: presence.clone(),
+                paused: paused.clone(),
+            })
+            .register_songbird_with(audioplayer.get_songbird())
+            .await
+            .expect("Error creating client");
+        // Initialize songbird with it
+        audioplayer.init_player(serenity_bot.cache_and_http.clone(), 1, config.discord.guild_id).await;
+        drop(audioplayer); // drop the lock so we can pass it off to our bot struct
+        // The status command wants shard latencies
+        serenity_bot.data.write().await.insert::<ShardManagerContainer>(serenity_bot.shard_manager.clone());
+
+        // Get a shared ref of our http cache so we can use it to send messages in an async fashion
+        let http = serenity_bot.cache_and_http.http.clone();
+        // And for shard manager too
+        let manager_clone = serenity_bot.shard_manager.clone();
+        let bot = DiscordBot {
+                serenity_bot: Arc::new(RwLock::new(serenity_bot)),
+                bot_token: token.clone(),
+                bot_http: http.clone(),
+                shard_handle: None,
+                shard_cancel_token: CancellationToken::new(),
+                shard_manager: manager_clone,
+                destinations: destinations,
+                filters: Arc::new(RwLock::new(Arc::new(filters))),
+                test_channel: config.channels.test.map(ChannelId),
+                role_pings: config.posting.role_pings.clone(),
+                webhook_poster: WebhookPoster::new(http.clone()),
+                send_queue: SendQueue::new(SEND_QUEUE_SIZE),
+                http_client: reqwest::Client::builder()
+                    .user_agent(config.reddit.client.user_agent(None)) // reddit hates the default one
+                    .timeout(config.reddit.client.timeout())
+                    .build().expect("Error building reqwest client"),
+                reddit_api: Arc::new(RedditApi::new(config.reddit.auth.clone(), &config.reddit.client)),
+                presence: presence,
+                paused: paused,
+                admin_user: config.discord.admin_user.map(UserId),
+                alerts: Alerts::start(config.alerts.clone(), http.clone(), config.discord.admin_user.map(UserId), config.dry_run),
+                auto_pin: config.posting.auto_pin.clone(),
+                post_history: post_history,
+                health: health,
+                sent_messages: sent_messages,
+                store: store,
+                audio_player: audio_player_lock.clone(),
+                command_parser: parser,
+                dry_run: config.dry_run,
+            };
+
+        let redriver = bot.clone();
+        tokio::spawn(async move {
+            while let Some((post_id, reply)) = redrive_rx.recv().await {
+                let _ = reply.send(redriver.redrive(post_id.as_deref()).await);
+            }
+        });
+        let archiver = bot.clone();
+        tokio::spawn(async move {
+            while let Some((post, reply)) = archive_rx.recv().await {
+                let _ = reply.send(archiver.archive_post(&post).await.map_err(|e| e.to_string()));
+            }
+        });
+
+        return bot;
+    }
+
+    pub async fn start_shards(&mut self, num_shards: u64) {
+        self.run_shards(num_shards, false);
+    }
+
+    /// Use however many shards discord recommends, and keep following it if it changes
+    pub async fn start_autosharded(&mut self) {
+        let num_shards = match recommended_shards(&self.bot_http).await {
+            Ok(n) => n,
+            Err(e) => {
+                error!("{}, starting with 1 shard", e);
+                1
+            }
+        };
+        warn!("Discord recommends {} shards", num_shards);
+        self.run_shards(num_shards, true);
+    }
+
+    fn run_shards(&mut self, num_shards: u64, follow_recommendation: bool) {
+        let bot = self.serenity_bot.clone();
+        let cloned_token = self.shard_cancel_token.clone();
+        let alerter = self.clone();
+        let http = self.bot_http.clone();
+        let manager = self.shard_manager.clone();
+        // Kept between restarts, so they come back with however many discord wanted last
+        let shard_count = Arc::new(AtomicU64::new(num_shards));
+        // We didn't ask for them to stop, so the supervisor starts them again and somebody hears about it
+        let supervised = crate::supervisor::supervise(
+            "discord shards",
+            self.shard_cancel_token.clone(),
+            move || {
+                let bot = bot.clone();
+                let cloned_token = cloned_token.clone();
+                let http = http.clone();
+                let manager = manager.clone();
+                let shard_count = shard_count.clone();
+                async move {
+                    let mut lock = bot.write().await;
+                    let mut num_shards = shard_count.load(Ordering::Relaxed);
+                    loop {
+                        select! {
+                            _ = lock.start_shards(num_shards) => {
+                                warn!("Shard threads stopped");
+                                break;
+                            }
+                            _ = cloned_token.cancelled() => {
+                                warn!("Cancelled our shards");
+                                break;
+                            }
+                            new_count = wait_for_new_shard_count(&http, num_shards), if follow_recommendation => {
+                                warn!("Discord now recommends {} shards instead of {}, restarting them", new_count, num_shards);
+                                manager.lock().await.shutdown_all().await;
+                                num_shards = new_count;
+                                shard_count.store(new_count, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                }
+            },
+            move |text| {
+                let alerter = alerter.clone();
+                async move { alerter.alert_admin(text).await }
+            },
+        );
+        self.shard_handle = Some(futures_locks::Mutex::new(tokio::spawn(supervised)));
+        warn!("Started {} shards", num_shards);
+    }
+
+    /// Where errors go to get noticed
+    pub fn alerts(&self) -> &Alerts {
+        &self.alerts
+    }
+
+    /// Whether an admin has told us to stop sniffing
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Pause or resume sniffing from outside discord, gives back whether that changed anything
+    pub async fn set_paused(&self, paused: bool) -> bool {
+        update_paused(&self.shard_manager, &self.presence, &self.paused, paused).await
+    }
+
+    /// Reddit answered, remember when for the status command
+    pub async fn record_poll(&self) {
+        self.health.write().await.last_poll = Some(std::time::Instant::now());
+    }
+
+    /// Whether we're fit to do our job, everything that's wrong if not
+    pub async fn readiness(&self, max_poll_age: Option<Duration>) -> Result<(), Vec<String>> {
+        let mut problems = Vec::<String>::new();
+        {
+            let lock = self.shard_manager.lock().await;
+            let shard_runners = lock.runners.lock().await;
+            if shard_runners.is_empty() {
+                problems.push(String::from("No shards running"));
+            }
+            for (id, runner) in shard_runners.iter() {
+                if runner.stage != ConnectionStage::Connected {
+                    problems.push(format!("Shard {} is {}", id, runner.stage));
+                }
+            }
+        }
+        if let Some(max) = max_poll_age {
+            match self.health.read().await.last_poll {
+                Some(t) if t.elapsed() <= max => {}
+                Some(t) => problems.push(format!("Last good reddit poll was {}s ago", t.elapsed().as_secs())),
+                None => problems.push(String::from("Haven't heard from reddit yet")),
+            }
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems),
+        }
+    }
+
+    // Every shard's stage and latency, in shard order
+    pub async fn shard_states(&self) -> Vec<(ShardId, ConnectionStage, Option<Duration>)> {
+        let lock = self.shard_manager.lock().await;
+        let shard_runners = lock.runners.lock().await;
+        let mut states: Vec<(ShardId, ConnectionStage, Option<Duration>)> = shard_runners.iter()
+            .map(|(id, runner)| (*id, runner.stage, runner.latency))
+            .collect();
+        states.sort_by_key(|(id, ..)| id.0);
+        states
+    }
+
+    fn shard_summary(states: &[(ShardId, ConnectionStage, Option<Duration>)]) -> String {
+        if states.is_empty() {
+            return String::from("No shards running");
+        }
+        states.iter()
+            .map(|(id, stage, latency)| match latency {
+                Some(l) => format!("Shard {} is {}, {}ms", id, stage, l.as_millis()),
+                None => format!("Shard {} is {}", id, stage),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub async fn print_shard_info(&self) {
+        for line in Self::shard_summary(&self.shard_states().await).lines() {
+            warn!("{}", line);
+        }
+        self.record_shard_latency().await;
+    }
+
+    async fn send_shard_report(&self, config: &ShardReportConfig, text: String) {
+        match config.channel.map(ChannelId).or(self.test_channel) {
+            Some(channel) => {
+                if let Err(e) = self.post_notice(channel, text).await {
+                    error!("Couldn't post the shard report: {}", e);
+                }
+            }
+            None => self.alert_admin(text).await,
+        }
+    }
+
+    /// Report on the shards every interval, and whenever one drops out of connected. Never returns
+    pub async fn report_shards(&self, config: ShardReportConfig) {
+        let interval = Duration::from_secs(config.interval_minutes * 60);
+        let mut next_report = tokio::time::Instant::now() + interval;
+        let mut last = HashMap::<ShardId, ConnectionStage>::new();
+        loop {
+            tokio::time::sleep(SHARD_WATCH_INTERVAL).await;
+            let states = self.shard_states().await;
+            let mut dropped = Vec::<String>::new();
+            for (id, stage, _) in &states {
+                // Shards coming up for the first time go through all sorts, only count ones that were fine
+                if last.insert(*id, *stage) == Some(ConnectionStage::Connected) && *stage != ConnectionStage::Connected {
+                    dropped.push(format!("Shard {} dropped to {}", id, stage));
+                }
+            }
+            if !dropped.is_empty() {
+                warn!("{}", dropped.join(", "));
+                self.send_shard_report(&config, format!("⚠️ {}\n{}", dropped.join("\n"), Self::shard_summary(&states))).await;
+            }
+            if tokio::time::Instant::now() >= next_report {
+                next_report += interval;
+                self.send_shard_report(&config, format!("Shard report\n{}", Self::shard_summary(&states))).await;
+            }
+        }
+    }
+
+    /// Update the metrics with every shard's latency, shards that haven't heartbeat yet get left out
+    pub async fn record_shard_latency(&self) {
+        let lock = self.shard_manager.lock().await;
+        let shard_runners = lock.runners.lock().await;
+        for (id, runner) in shard_runners.iter() {
+            if let Some(latency) = runner.latency {
+                metrics::SHARD_LATENCY_SECONDS.with_label_values(&[&id.0.to_string()]).set(latency.as_secs_f64());
+            }
+        }
+    }
+
+    /// Make sure we can actually post everywhere we're set up to, and say exactly what's missing where.
+    /// The rest of the channels we're given just have to exist
+    pub async fn preflight(&self, other_channels: &[(String, u64)]) -> Vec<String> {
+        let mut problems = Vec::<String>::new();
+        let me = match self.bot_http.get_current_user().await {
+            Ok(u) => u.id,
+            Err(e) => {
+                problems.push(format!("Couldn't get our own user: {}", e));
+                return problems;
+            }
+        };
+        for destination in &self.destinations {
+            // Webhooks carry their own permissions
+            if destination.webhook.is_some() {
+                continue;
+            }
+            match self.missing_permissions(destination, me).await {
+                Ok(missing) if missing.is_empty() => {
+                    warn!("Permissions look good in channel {}", destination.channel);
+                }
+                Ok(missing) => problems.push(format!("Channel {} is missing {:?}", destination.channel, missing)),
+                Err(e) => problems.push(format!("Channel {}: {}", destination.channel, e)),
+            }
+        }
+        // Everything else just has to be there
+        let mut others = other_channels.to_vec();
+        if let Some(c) = self.test_channel {
+            others.push((String::from("channels.test"), c.0));
+        }
+        for (name, id) in others {
+            if let Err(e) = ChannelId(id).to_channel(&self.bot_http).await {
+                problems.push(format!("{} {} couldn't be found: {}", name, id, e));
+            }
+        }
+        problems
+    }
+
+    async fn missing_permissions(&self, destination: &Destination, me: UserId) -> Result<Permissions, String> {
+        let channel = match ChannelId(destination.channel).to_channel(&self.bot_http).await {
+            Ok(Channel::Guild(c)) => c,
+            Ok(_) => return Err(String::from("not a guild channel")),
+            Err(e) => return Err(String::from(format!("couldn't look it up: {}", e))),
+        };
+        let guild = match channel.guild_id.to_partial_guild(&self.bot_http).await {
+            Ok(g) => g,
+            Err(e) => return Err(String::from(format!("couldn't look up its guild: {}", e))),
+        };
+        let member = match guild.id.member(&self.bot_http, me).await {
+            Ok(m) => m,
+            Err(e) => return Err(String::from(format!("couldn't find ourselves in the guild: {}", e))),
+        };
+        let have = match guild.user_permissions_in(&channel, &member) {
+            Ok(p) => p,
+            Err(e) => return Err(String::from(format!("couldn't work out our permissions: {}", e))),
+        };
+        let mut need = Permissions::READ_MESSAGES | Permissions::SEND_MESSAGES
+            | Permissions::EMBED_LINKS | Permissions::ATTACH_FILES;
+        if destination.pin_high_scores || destination.publish {
+            need |= Permissions::MANAGE_MESSAGES;
+        }
+        Ok(need - have)
+    }
+
+    /// DM the admin about something that needs a human, if we have one configured
+    pub async fn alert_admin(&self, text: String) {
+        error!("Admin alert: {}", text);
+        if self.alerts.enabled() {
+            self.alerts.send("admin", text);
+            return;
+        }
+        if self.dry_run {
+            return;
+        }
+        let admin = match self.admin_user {
+            Some(a) => a,
+            None => return,
+        };
+        let result = match admin.create_dm_channel(&self.bot_http).await {
+            Ok(dm) => dm.say(&self.bot_http, &text).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Couldn't DM the admin: {}", e);
+        }
+    }
+
+    /// Change what the bot is up to on every shard, sticks around through reconnects
+    pub async fn set_presence(&self, presence: Presence) {
+        warn!("Setting presence to {:?} {}", presence.kind, presence.text);
+        let activity = presence.activity(self.is_paused());
+        *self.presence.write().await = presence;
+        let lock = self.shard_manager.lock().await;
+        let shard_runners = lock.runners.lock().await;
+        for (_, runner) in shard_runners.iter() {
+            runner.runner_tx.set_activity(Some(activity.clone()));
+        }
+    }
+
+    /// Let whatever's queued go out, then hang up and disconnect every shard
+    pub async fn shutdown(self) {
+        match self.send_queue.flush(SHUTDOWN_FLUSH_TIMEOUT).await {
+            Ok(_) => warn!("Send queue's empty"),
+            Err(waiting) => error!("Gave up on {} messages still waiting to go out", waiting),
+        }
+        self.stop_audio().await;
+        self.stop_shards().await; // we hold a write lock on serenity here, it's its run future
+    }
+
+    //TODO: Find a way to make sure we can get the same instance of our original audio player
+    async fn stop_audio(&self) {
+        // If we have a player, hang up
+        //if let Some(player_lock) = &self.audio_player {
+            let mut player = self.audio_player.lock().await;
+            if let Err(x) = player.shutdown() {
+                error!("Error shutting down player: {}", x);
+            }
+            // This is dumb as hell, but if we don't wait a little bit we'll remove the shards
+            // before it has a chance to leave, they should really have a leave_blocking function
+            // There's nothing we can poll to check to see if we've fully left either, the
+            // associated structs reflect the state immediately
+
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        //}
+    }
+
+    async fn stop_shards(&self) {
+        // Start the cancel
+        self.shard_cancel_token.cancel();
+        // Wait on our handle
+        match &self.shard_handle{
+            Some(x) => {
+                let handle_lock = x.lock();
+                handle_lock.await;
+                warn!("Successfully waited on future");
+            }
+            None => {
+                error!("We don't have a shard handle")
+            }
+        }
+    }
+
+    /// Send a sniffed post everywhere it goes, the error says which channels didn't get it
+    pub async fn post_message(&self, message: SnifferPost) -> Result<usize, PostError> {
+        info!("Trying to send message: {}", message);
+        let filters = self.filters.read().await.clone();
+        if self.dry_run {
+            let count = self.destinations.iter().filter(|d| d.wants(&message) && filters.allows(d, &message)).count();
+            warn!("Dry run, would have sent post {} to {} channels", message.id, count);
+            return Ok(count);
+        }
+
+        // Remember it for the slash commands, dropping the oldest if we're full
+        {
+            let mut history = self.post_history.write().await;
+            if history.len() >= HISTORY_SIZE {
+                if let Some(old) = history.pop_front() {
+                    // We won't be editing anything that old either
+                    self.sent_messages.write().await.remove(&old.id);
+                }
+            }
+            history.push_back(message.clone());
+        }
+
+        // Grab any images or video once up front, instead of once per channel
+        let media = media::download_all(&self.http_client, &message).await;
+
+        // Fan out to everywhere we're configured to post
+        let mut sent = Vec::<SentMessage>::new();
+        let mut delivered = 0;
+        let mut failed = Vec::<(ChannelId, String)>::new();
+        for destination in self.destinations.iter().filter(|d| d.wants(&message) && filters.allows(d, &message)) {
+            match self.queue_post(destination, &message, &media, 0).await {
+                Ok(m) => {
+                    delivered += 1;
+                    if let Some(m) = m {
+                        sent.push(m);
+                    }
+                }
+                Err(f) => {
+                    error!("Giving up on posting {} to {}: {}", message.id, destination.channel, f.error);
+                    metrics::error("discord_post");
+                    self.alerts.send("discord_post", format!("Couldn't post {} to channel {}: {}", message.id, destination.channel, f.error));
+                    // Whatever did go out is still ours to edit and delete
+                    if let Some(m) = f.sent {
+                        sent.push(m);
+                    }
+                    // Kept for /deadletters retry once whatever's wrong is fixed, from where it stopped
+                    self.store.dead_letter(&message, destination.channel, &f.error, f.chunks);
+                    failed.push((ChannelId(destination.channel), f.error));
+                }
+            }
+        }
+        if delivered > 0 {
+            self.health.write().await.relayed += 1;
+            metrics::POSTS_RELAYED.inc();
+        }
+        let records: Vec<MessageRecord> = sent.iter().map(|s| MessageRecord {
+            destination: s.destination.channel,
+            channel: s.channel.0,
+            message: s.message.0,
+        }).collect();
+        self.store.save_messages(&message.id, &records);
+        self.sent_messages.write().await.insert(message.id.clone(), sent);
+
+        // Come back later and see how it did
+        if let Some(config) = self.auto_pin.clone() {
+            let bot = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(config.delay_minutes * 60)).await;
+                if let Err(e) = bot.check_auto_pin(&config, &message.id, &message.fullname()).await {
+                    error!("Auto pin check for {} failed: {}", message.id, e);
+                }
+            });
+        }
+
+        // And polls get their results once they're done
+        if let Some(poll) = &message.poll {
+            if !poll.is_over() && self.destinations.iter().any(|d| d.poll_results) {
+                let bot = self.clone();
+                let (post_id, fullname, ends) = (message.id.clone(), message.fullname(), poll.ends);
+                tokio::spawn(async move {
+                    let wait = ends.saturating_sub(chrono::Utc::now().timestamp() as u64) + POLL_RESULTS_DELAY;
+                    tokio::time::sleep(Duration::from_secs(wait)).await;
+                    if let Err(e) = bot.post_poll_results(&post_id, &fullname).await {
+                        error!("Couldn't post poll results for {}: {}", post_id, e);
+                    }
+                });
+            }
+        }
+
+        match (delivered, failed.is_empty()) {
+            (_, true) => Ok(delivered),
+            (0, false) => Err(PostError::Undelivered(failed)),
+            (_, false) => Err(PostError::Partial { delivered: delivered, failed: failed }),
+        }
+    }
+
+    /// Try dead lettered posts again, all of them or just one post's. Whatever goes out gets
+    /// forgotten, whatever doesn't stays for next time
+    pub async fn redrive(&self, post_id: Option<&str>) -> Result<String, String> {
+        let letters: Vec<DeadLetter> = self.store.dead_letters()?.into_iter()
+            .filter(|l| post_id.map_or(true, |p| l.post.id == p))
+            .collect();
+        if letters.is_empty() {
+            return Ok(match post_id {
+                Some(p) => format!("Nothing dead lettered for {}", p),
+                None => String::from("Nothing dead lettered"),
+            });
+        }
+        if self.dry_run {
+            return Ok(format!("Dry run, would have retried {} dead letters", letters.len()));
+        }
+        let (mut sent, mut failed, mut dropped) = (0, 0, 0);
+        for letter in letters {
+            let destination = match self.destinations.iter().find(|d| d.channel == letter.destination) {
+                Some(d) => d,
+                None => {
+                    warn!("Dropping the dead letter for {}, we don't post to {} anymore", letter.post.id, letter.destination);
+                    self.store.clear_dead_letter(&letter.post.id, letter.destination);
+                    dropped += 1;
+                    continue;
+                }
+            };
+            let media = media::download_all(&self.http_client, &letter.post).await;
+            let (first, result) = match self.queue_post(destination, &letter.post, &media, letter.sent_chunks).await {
+                Ok(m) => (m, Ok(())),
+                Err(f) => (f.sent, Err((f.error, f.chunks))),
+            };
+            // A post that got partway before already has its first message on record, the rest
+            // of it is just more pieces
+            if let (Some(m), 0) = (first, letter.sent_chunks) {
+                self.store.save_messages(&letter.post.id, &[MessageRecord {
+                    destination: m.destination.channel,
+                    channel: m.channel.0,
+                    message: m.message.0,
+                }]);
+                if let Some(existing) = self.sent_messages.write().await.get_mut(&letter.post.id) {
+                    existing.push(m);
+                }
+            }
+            match result {
+                Ok(_) => {
+                    warn!("Dead letter for {} made it to {} after {} attempts", letter.post.id, letter.destination, letter.attempts);
+                    self.store.clear_dead_letter(&letter.post.id, letter.destination);
+                    sent += 1;
+                }
+                Err((e, chunks)) => {
+                    error!("Dead letter for {} still can't get to {}: {}", letter.post.id, letter.destination, e);
+                    self.store.dead_letter(&letter.post, letter.destination, &e, chunks);
+                    failed += 1;
+                }
+            }
+        }
+        let mut reply = format!("{} went out, {} failed again", sent, failed);
+        if dropped > 0 {
+            reply.push_str(&format!(", {} dropped for channels we don't post to anymore", dropped));
+        }
+        Ok(reply)
+    }
+
+    /// Every message we sent for a post, from memory if it's recent and the store if it isn't.
+    /// Messages for destinations that aren't in the config anymore get left out
+    async fn sent_for(&self, post_id: &str) -> Option<Vec<SentMessage>> {
+        if let Some(s) = self.sent_messages.read().await.get(post_id) {
+            return Some(s.clone());
+        }
+        let records = self.store.messages_for(post_id);
+        if records.is_empty() {
+            return None;
+        }
+        let sent: Vec<SentMessage> = records.iter().filter_map(|r| {
+            self.destinations.iter().find(|d| d.channel == r.destination).map(|d| SentMessage {
+                destination: d.clone(),
+                channel: ChannelId(r.channel),
+                message: MessageId(r.message),
+            })
+        }).collect();
+        debug!("Found {} messages for post {} in the store", sent.len(), post_id);
+        Some(sent)
+    }
+
+    async fn check_auto_pin(&self, config: &AutoPinConfig, post_id: &str, fullname: &str) -> Result<(), String> {
+        let post = match crate::reddit::fetch_post(&self.reddit_api, fullname).await {
+            Ok(Some(p)) => p,
+            Ok(None) => return Err(String::from("Post is gone from reddit")),
+            Err(e) => return Err(String::from(format!("Couldn't fetch post: {}", e))),
+        };
+        if post.score < config.min_score {
+            warn!("Post {} only got to {}, not pinning", post_id, post.score);
+            return Ok(());
+        }
+        let sent = match self.sent_for(post_id).await {
+            Some(s) => s,
+            None => return Err(String::from("We don't have any messages for it anymore")),
+        };
+        for s in sent.iter().filter(|s| s.destination.pin_high_scores) {
+            self.make_room_for_pin(s.channel).await?;
+            if let Err(e) = s.channel.pin(&self.bot_http, s.message).await {
+                return Err(String::from(format!("Couldn't pin message: {}", e)));
+            }
+            warn!("Pinned post {} with a score of {}", post_id, post.score);
+        }
+        Ok(())
+    }
+
+    async fn post_poll_results(&self, post_id: &str, fullname: &str) -> Result<(), String> {
+        let post = match crate::reddit::fetch_post(&self.reddit_api, fullname).await {
+            Ok(Some(p)) => p,
+            Ok(None) => return Err(String::from("Post is gone from reddit")),
+            Err(e) => return Err(String::from(format!("Couldn't fetch post: {}", e))),
+        };
+        let poll = match &post.poll {
+            Some(p) => p,
+            None => return Err(String::from("Reddit didn't give us the poll")),
+        };
+        let sent = match self.sent_for(post_id).await {
+            Some(s) => s,
+            None => return Err(String::from("We don't have any messages for it anymore")),
+        };
+        let text = format!("Poll results for **{}**\n{}", post.title, poll.discord_string());
+        let text = split_message(&text, MESSAGE_LENGTH).into_iter().next().unwrap_or_default();
+        for s in sent.iter().filter(|s| s.destination.poll_results) {
+            let http = self.bot_http.clone();
+            let (channel, message_id, text) = (s.channel, s.message, text.clone());
+            self.send_queue.send(channel, async move {
+                with_backoff("Poll results", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                    channel.send_message(&http, |m| {
+                        m.content(&text)
+                            .reference_message((channel, message_id))
+                            .allowed_mentions(|am| am.empty_parse())
+                    })
+                }).await
+            }).await??;
+        }
+        warn!("Posted poll results for {}", post_id);
+        Ok(())
+    }
+
+    // If a channel's out of pins, unpin the oldest thing we pinned
+    async fn make_room_for_pin(&self, channel: ChannelId) -> Result<(), String> {
+        let pins = match channel.pins(&self.bot_http).await {
+            Ok(p) => p,
+            Err(e) => return Err(String::from(format!("Couldn't get pins: {}", e))),
+        };
+        if pins.len() < MAX_PINS {
+            return Ok(());
+        }
+        let me = match self.bot_http.get_current_user().await {
+            Ok(u) => u.id,
+            Err(e) => return Err(String::from(format!("Couldn't get our own user: {}", e))),
+        };
+        // Pins come back newest first
+        match pins.iter().rev().find(|m| m.author.id == me) {
+            Some(oldest) => {
+                if let Err(e) = channel.unpin(&self.bot_http, oldest.id).await {
+                    return Err(String::from(format!("Couldn't unpin old message: {}", e)));
+                }
+                warn!("Unpinned {} to make room", oldest.id);
+                Ok(())
+            }
+            None => Err(String::from("Channel is out of pins and none of them are ours")),
+        }
+    }
+
+    // Send a post to one destination, every message it takes goes through the send queue on its
+    // own so it respects our rate limits without holding the channel up for the whole post.
+    // Split posts start from the skip'th piece, the ones before it already went out
+    async fn queue_post(&self, destination: &Destination, message: &SnifferPost, media: &[Media], skip: usize) -> Result<Option<SentMessage>, SendFailure> {
+        let _timer = metrics::DISCORD_SEND_SECONDS.start_timer();
+        self.post_to_destination(destination, message, media, skip).await
+    }
+
+    // One send on the queue. It runs somewhere else, so bring the post's span along
+    async fn queued<F, T>(&self, channel: ChannelId, send: F) -> Result<T, String>
+    where
+        F: std::future::Future<Output = Result<T, String>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.send_queue.send(channel, send.in_current_span()).await?
+    }
+
+    // Same deal for edits, they count against the limits too
+    async fn queue_edit(&self, sent: &SentMessage, message: &SnifferPost) -> Result<(), String> {
+        let bot = self.clone();
+        let (sent, message) = (sent.clone(), message.clone());
+        self.send_queue.send(sent.channel, async move {
+            bot.edit_sent_message(&sent, &message).await
+        }.in_current_span()).await?
+    }
+
+    #[instrument(level = "warn", name = "send", skip_all, fields(channel = destination.channel))]
+    async fn post_to_destination(&self, destination: &Destination, message: &SnifferPost, media: &[Media], skip: usize) -> Result<Option<SentMessage>, SendFailure> {
+        let channel = ChannelId(destination.channel);
+
+        // Sort out nsfw posts first, they might not go here at all
+        let (message, media) = match self.apply_nsfw_policy(destination, message, media).await {
+            Some(x) => x,
+            None => {
+                warn!("Not posting nsfw post {} to channel {}", message.id, channel);
+                return Ok(None);
+            }
+        };
+        let (message, media) = (&message, &media);
+
+        // Only the roles we want are allowed to get pinged, nothing in the post itself can @everyone
+        let roles = self.roles_to_ping(destination, message);
+        let mentions = mention_roles(&roles);
+
+        // Spoilered posts show a neutral title in embeds and thread names
+        let shown = destination.embed_post(message);
+
+        if destination.forum {
+            let (embed, chunks) = match destination.embeds() {
+                true => (Some(Embed::fake(|e| shown.discord_embed(e, destination.include_url))), vec![mentions.clone()]),
+                false => (None, self.message_chunks(destination, message, &mentions)),
+            };
+            // The first piece starts the post and the rest go in its thread
+            let started = match skip {
+                0 => {
+                    let (bot, title, content, roles) = (self.clone(), shown.title.clone(), chunks[0].clone(), roles.clone());
+                    let (thread, starter) = self.queued(channel, async move {
+                        with_backoff("Forum post", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                            forum::create_forum_post(&bot.http_client, &bot.bot_token, channel, &title, &content, embed.clone(), &roles)
+                        }).await
+                    }).await?;
+                    // Threads and forum posts are the same thing as far as discord's concerned
+                    SentMessage {
+                        destination: destination.clone(),
+                        channel: thread,
+                        message: starter,
+                    }
+                }
+                // Picking up where a failed one left off, the post is already up
+                _ => match self.store.messages_for(&message.id).into_iter().find(|r| r.destination == destination.channel) {
+                    Some(r) => SentMessage {
+                        destination: destination.clone(),
+                        channel: ChannelId(r.channel),
+                        message: MessageId(r.message),
+                    },
+                    None => return Err(SendFailure::from(format!("Lost track of the forum post for {}, starting it over next time", message.id))),
+                },
+            };
+            let rest = self.send_chunks(started.channel, chunks, skip.max(1), &[], None, &roles).await;
+            return match rest {
+                Ok(_) => Ok(Some(started)),
+                Err((_, chunks, e)) => Err(SendFailure {
+                    sent: match skip {
+                        0 => Some(started),
+                        _ => None,
+                    },
+                    chunks: chunks,
+                    error: e,
+                }),
+            };
+        }
+
+        let sent = if destination.webhook.is_some() {
+            // Webhooks go out a piece at a time too, so a retry doesn't send it all again
+            let mut first_id = None;
+            for (i, piece) in WebhookPost::pieces(destination, message, &roles).into_iter().enumerate().skip(skip) {
+                let (poster, to, post, roles) = (self.webhook_poster.clone(), destination.clone(), message.clone(), roles.clone());
+                let result = self.queued(channel, async move {
+                    with_backoff("Webhook post", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                        poster.execute(&to, &post, &piece, &roles)
+                    }).await
+                }).await;
+                match result {
+                    Ok(id) if first_id.is_none() => first_id = id,
+                    Ok(_) => {}
+                    Err(e) => return Err(SendFailure {
+                        sent: first_id.map(|id| SentMessage {
+                            destination: destination.clone(),
+                            channel: channel,
+                            message: id,
+                        }),
+                        chunks: i,
+                        error: e,
+                    }),
+                }
+            }
+            first_id
+        }
+        else if destination.embeds() {
+            let (http, destination, message, media) = (self.bot_http.clone(), destination.clone(), message.clone(), media.clone());
+            let (shown, roles, mentions) = (shown.clone(), roles.clone(), mentions.clone());
+            let m = self.queued(channel, async move {
+                with_backoff("Embed send", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                    channel.send_message(&http, |m| {
+                        // Embeds don't ping, so mentions go in the content
+                        if !mentions.is_empty() {
+                            m.content(&mentions);
+                        }
+                        m.embed(|e| {
+                            shown.discord_embed(e, destination.include_url);
+                            // Point the embed at our first image so it shows up inline
+                            if let Some(file) = media.iter().find(|f| f.is_image()) {
+                                e.image(format!("attachment://{}", file.filename));
+                            }
+                            e
+                        });
+                        for file in media.iter() {
+                            m.add_file(file.attachment());
+                        }
+                        if destination.buttons {
+                            add_buttons(m, &message);
+                        }
+                        m.allowed_mentions(|am| am.empty_parse().roles(roles.clone()))
+                    })
+                }).await
+            }).await?;
+            Some(m.id)
+        }
+        else {
+            // Plain text fallback, long posts get split over a few messages
+            let chunks = self.message_chunks(destination, message, &mentions);
+            let buttons = match destination.buttons {
+                true => Some(message),
+                false => None,
+            };
+            match self.send_chunks(channel, chunks, skip, media, buttons, &roles).await {
+                Ok(id) => id,
+                Err((first_id, chunks, e)) => return Err(SendFailure {
+                    sent: first_id.map(|id| SentMessage {
+                        destination: destination.clone(),
+                        channel: channel,
+                        message: id,
+                    }),
+                    chunks: chunks,
+                    error: e,
+                }),
+            }
+        };
+        if destination.publish {
+            if let Some(id) = sent {
+                self.publish(channel, id).await;
+            }
+        }
+        if destination.create_thread {
+            if let Some(id) = sent {
+                self.create_thread(destination, channel, id, &shown).await;
+            }
+        }
+        Ok(sent.map(|id| SentMessage {
+            destination: destination.clone(),
+            channel: channel,
+            message: id,
+        }))
+    }
+
+    /// Pieces of a split post from skip on, one message each, with the media and the buttons for
+    /// the post (if it gets them) on the last. Gives back the first one we sent, or that and how
+    /// many pieces are out so far if one doesn't make it
+    async fn send_chunks(&self, channel: ChannelId, chunks: Vec<String>, skip: usize, media: &[Media], buttons: Option<&SnifferPost>, roles: &[RoleId]) -> Result<Option<MessageId>, (Option<MessageId>, usize, String)> {
+        let last = chunks.len().saturating_sub(1);
+        let mut first_id = None;
+        for (i, chunk) in chunks.into_iter().enumerate().skip(skip) {
+            // Every piece is its own job, so other channels can go in between
+            let (http, buttons, roles) = (self.bot_http.clone(), buttons.cloned(), roles.to_vec());
+            // Attachments and buttons go at the bottom, after the whole post
+            let media = match i == last {
+                true => media.to_vec(),
+                false => Vec::new(),
+            };
+            let result = self.queued(channel, async move {
+                with_backoff("Message send", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                    channel.send_message(&http, |m| {
+                        m.content(chunk.clone());
+                        for file in media.iter() {
+                            m.add_file(file.attachment());
+                        }
+                        if let (Some(message), true) = (&buttons, i == last) {
+                            add_buttons(m, message);
+                        }
+                        m.allowed_mentions(|am| am.empty_parse().roles(roles.clone()))
+                    })
+                }).await
+            }).await;
+            // Hang on to what already went out, so a retry picks up from here instead of
+            // sending it all again
+            let m = match result {
+                Ok(m) => m,
+                Err(e) => return Err((first_id, i, e)),
+            };
+            // The first message is the one we track
+            first_id.get_or_insert(m.id);
+        }
+        Ok(first_id)
+    }
+
+    /// What a post (and its media) should look like at this destination, None if it shouldn't go here
+    async fn apply_nsfw_policy(&self, destination: &Destination, message: &SnifferPost, media: &[Media]) -> Option<(SnifferPost, Vec<Media>)> {
+        if !message.nsfw {
+            return Some((message.clone(), media.to_vec()));
+        }
+        match destination.nsfw {
+            NsfwPolicy::Show => Some((message.clone(), media.to_vec())),
+            NsfwPolicy::Drop => None,
+            // The text gets its spoilers when it's split up
+            NsfwPolicy::Spoiler => Some((message.clone(), media.iter().map(|m| m.spoilered()).collect())),
+            NsfwPolicy::Skip => {
+                // Age restricted channels can have it as is
+                match ChannelId(destination.channel).to_channel(&self.bot_http).await {
+                    Ok(Channel::Guild(c)) if c.nsfw => Some((message.clone(), media.to_vec())),
+                    Ok(_) => None,
+                    Err(e) => {
+                        error!("Couldn't check if channel {} is nsfw, skipping it: {}", destination.channel, e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Crosspost a message in an announcement channel, anywhere else we just leave it be
+    async fn publish(&self, channel: ChannelId, message_id: MessageId) {
+        let is_news = match channel.to_channel(&self.bot_http).await {
+            Ok(Channel::Guild(c)) => c.kind == ChannelType::News,
+            Ok(_) => false,
+            Err(e) => {
+                error!("Couldn't look up channel {} to publish: {}", channel, e);
+                return;
+            }
+        };
+        if !is_news {
+            warn!("Channel {} is set to publish, but it isn't an announcement channel", channel);
+            return;
+        }
+        match self.bot_http.crosspost_message(channel.0, message_id.0).await {
+            Ok(_) => warn!("Published message {} in {}", message_id, channel),
+            Err(e) => error!("Couldn't publish message {} in {}: {}", message_id, channel, e),
+        }
+    }
+
+    /// Hang a thread named after the post off of our message, failing this isn't the end of the world
+    async fn create_thread(&self, destination: &Destination, channel: ChannelId, message_id: MessageId, message: &SnifferPost) {
+        let name: String = message.title.chars().take(THREAD_NAME_LENGTH).collect();
+        let result = channel.create_public_thread(&self.bot_http, message_id, |t| {
+            t.name(name).auto_archive_duration(destination.thread_archive_minutes)
+        }).await;
+        match result {
+            Ok(t) => warn!("Created thread {} for post {}", t.id, message.id),
+            Err(e) => error!("Couldn't create a thread for post {}: {}", message.id, e),
+        }
+    }
+
+    /// Roles with a keyword in this post, if the destination does pings
+    fn roles_to_ping(&self, destination: &Destination, message: &SnifferPost) -> Vec<RoleId> {
+        if !destination.ping_roles {
+            return Vec::new();
+        }
+        self.role_pings.iter()
+            .filter(|r| r.matches(message))
+            .map(|r| RoleId(r.role))
+            .collect()
+    }
+
+    fn message_chunks(&self, destination: &Destination, message: &SnifferPost, mentions: &str) -> Vec<String> {
+        message_chunks(&destination.format_text(message), mentions, destination.spoilers(message), MESSAGE_LENGTH)
+    }
+
+    /// Bring the messages we sent for a post up to date after it was edited on reddit
+    pub async fn edit_message(&self, before: SnifferPost, message: SnifferPost) {
+        if self.dry_run {
+            warn!("Dry run, would have edited our messages for post {}", message.id);
+            return;
+        }
+        // Keep our history in line too
+        if let Some(p) = self.post_history.write().await.iter_mut().find(|p| p.id == message.id) {
+            *p = message.clone();
+        }
+        let diff = SnifferPost::diff(&before, &message);
+        let sent = match self.sent_for(&message.id).await {
+            Some(s) => s,
+            None => {
+                warn!("Post {} was edited, but we don't have any messages for it", message.id);
+                return;
+            }
+        };
+        for s in sent {
+            if let Err(e) = self.queue_edit(&s, &message).await {
+                error!("Couldn't edit message {} for post {}: {}", s.message, message.id, e);
+                metrics::error("discord_edit");
+            }
+            if s.destination.show_edits && !diff.is_empty() {
+                if let Err(e) = self.queue_edit_diff(&s, &diff).await {
+                    error!("Couldn't post the edit for post {}: {}", message.id, e);
+                }
+            }
+        }
+    }
+
+    // Reply to one of our messages with what changed in the post
+    async fn queue_edit_diff(&self, sent: &SentMessage, diff: &str) -> Result<(), String> {
+        let http = self.bot_http.clone();
+        let (channel, message_id) = (sent.channel, sent.message);
+        // Leave room for the code block around it
+        let diff = split_message(diff, MESSAGE_LENGTH - 30).into_iter().next().unwrap_or_default();
+        self.send_queue.send(channel, async move {
+            with_backoff("Edit diff", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                channel.send_message(&http, |m| {
+                    m.content(format!("Edited on Reddit:\n```diff\n{}\n```", diff))
+                        .reference_message((channel, message_id))
+                        .allowed_mentions(|am| am.empty_parse())
+                })
+            }).await
+        }).await?.map(|_| ())
+    }
+
+    #[instrument(level = "warn", name = "edit", skip_all, fields(channel = sent.channel.0, message = sent.message.0))]
+    async fn edit_sent_message(&self, sent: &SentMessage, message: &SnifferPost) -> Result<(), String> {
+        let http = &self.bot_http;
+        if sent.destination.webhook.is_some() {
+            // Only the webhook itself can touch these
+            return Err(String::from("Can't edit messages sent through a webhook"));
+        }
+        let include_url = sent.destination.include_url;
+        // Keep the mentions around, edits don't ping again
+        let mentions = mention_roles(&self.roles_to_ping(&sent.destination, message));
+        // Only the first part of a split post gets updated, the rest were never tracked
+        let message_text = self.message_chunks(&sent.destination, message, &mentions)
+            .into_iter().next().unwrap_or_default();
+        let embed_posts = sent.destination.embeds();
+        let shown = sent.destination.embed_post(message);
+        with_backoff("Message edit", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+            sent.channel.edit_message(http, sent.message, |m| {
+                match embed_posts {
+                    true => m.embed(|e| shown.discord_embed(e, include_url)),
+                    false => m.content(message_text.clone()),
+                }
+            })
+        }).await?;
+        warn!("Edited message {} for post {}", sent.message, message.id);
+        Ok(())
+    }
+
+    /// Somebody gave a post awards, archives get them added and destinations that want it get a reply
+    pub async fn handle_awarded(&self, post: SnifferPost, new_awards: Vec<crate::reddit::Award>) {
+        if self.dry_run {
+            warn!("Dry run, would have added {} to post {}", SnifferPost::award_summary(&new_awards), post.id);
+            return;
+        }
+        if let Some(p) = self.post_history.write().await.iter_mut().find(|p| p.id == post.id) {
+            p.awards = post.awards.clone();
+        }
+        let sent = match self.sent_for(&post.id).await {
+            Some(s) => s,
+            None => return,
+        };
+        let text = format!("🏅 This just got {}", SnifferPost::award_summary(&new_awards));
+        for s in sent.iter() {
+            if s.destination.include_url {
+                if let Err(e) = self.queue_edit(s, &post).await {
+                    error!("Couldn't add awards to message {} for post {}: {}", s.message, post.id, e);
+                }
+            }
+            if s.destination.award_followups {
+                let http = self.bot_http.clone();
+                let (channel, message_id, text) = (s.channel, s.message, text.clone());
+                let result = self.send_queue.send(channel, async move {
+                    with_backoff("Award followup", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                        channel.send_message(&http, |m| {
+                            m.content(&text)
+                                .reference_message((channel, message_id))
+                                .allowed_mentions(|am| am.empty_parse())
+                        })
+                    }).await
+                }).await.and_then(|r| r.map(|_| ()));
+                if let Err(e) = result {
+                    error!("Couldn't post the award followup for {}: {}", post.id, e);
+                }
+            }
+        }
+        warn!("Handled new awards on post {}", post.id);
+    }
+
+    /// Note a duplicate on the archive copies of the original, we don't relay it again
+    pub async fn link_duplicate(&self, original: SnifferPost) {
+        if self.dry_run {
+            warn!("Dry run, would have linked duplicates on post {}", original.id);
+            return;
+        }
+        if let Some(p) = self.post_history.write().await.iter_mut().find(|p| p.id == original.id) {
+            p.duplicates = original.duplicates.clone();
+        }
+        let sent = match self.sent_for(&original.id).await {
+            Some(s) => s,
+            None => return,
+        };
+        for s in sent.iter().filter(|s| s.destination.include_url) {
+            if let Err(e) = self.queue_edit(s, &original).await {
+                error!("Couldn't link duplicates on message {} for post {}: {}", s.message, original.id, e);
+            }
+        }
+    }
+
+    /// Deal with our copies of a post that was deleted on reddit, depending on how we're configured
+    pub async fn handle_deleted(&self, message: SnifferPost, removal: Removal) {
+        if self.dry_run {
+            warn!("Dry run, post {} was {}", message.id, removal.describe());
+            return;
+        }
+        // Keep the history honest, it still has the content for the recall commands
+        if let Some(p) = self.post_history.write().await.iter_mut().find(|p| p.id == message.id) {
+            p.removed = Some(removal);
+        }
+        let sent = match self.sent_for(&message.id).await {
+            Some(s) => {
+                // Nothing more we'll do with these
+                self.sent_messages.write().await.remove(&message.id);
+                self.store.forget_messages(&message.id);
+                s
+            }
+            None => {
+                warn!("Post {} was {}, but we don't have any messages for it", message.id, removal.describe());
+                return;
+            }
+        };
+        // Mark it in the title so it shows in both the text and embed versions
+        let mut annotated = message.clone();
+        annotated.title = format!("[{}] {}", removal.describe(), message.title);
+        for s in sent {
+            let action = s.destination.on_deleted();
+            let result = match action {
+                DeletedAction::Delete => {
+                    let http = self.bot_http.clone();
+                    let (channel, message_id) = (s.channel, s.message);
+                    self.send_queue.send(channel, async move {
+                        with_backoff("Message delete", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                            channel.delete_message(&http, message_id)
+                        }).await
+                    }).await.and_then(|r| r)
+                }
+                DeletedAction::Annotate => self.queue_edit(&s, &annotated).await,
+                DeletedAction::Ignore => Ok(()),
+            };
+            if let Err(e) = result {
+                error!("Couldn't deal with message {} for deleted post {}: {}", s.message, message.id, e);
+                metrics::error("discord_delete");
+            }
+            // Nothing to reply to if we just deleted it
+            if s.destination.deleted_followup && action != DeletedAction::Delete {
+                if let Err(e) = self.queue_deleted_followup(&s, &message, removal).await {
+                    error!("Couldn't post the followup for deleted post {}: {}", message.id, e);
+                }
+            }
+        }
+        warn!("Handled deleted post {}", message.id);
+    }
+
+    /// Drop a post into the archives only, for backfills and archive reactions. No history,
+    /// threads or pins, it's already happened
+    pub async fn archive_post(&self, message: &SnifferPost) -> Result<usize, PostError> {
+        if self.dry_run {
+            warn!("Dry run, would have archived post {}", message.id);
+            return Ok(0);
+        }
+        let filters = self.filters.read().await.clone();
+        let mut delivered = 0;
+        let mut failed = Vec::<(ChannelId, String)>::new();
+        // Forum channels turn down plain messages, only new posts start forum threads
+        for destination in self.destinations.iter().filter(|d| d.include_url && !d.forum && d.wants(message) && filters.allows(d, message)) {
+            let channel = ChannelId(destination.channel);
+            // Same nsfw rules as new posts
+            let message = match self.apply_nsfw_policy(destination, message, &[]).await {
+                Some((m, _)) => m,
+                None => {
+                    warn!("Not archiving nsfw post {} to channel {}", message.id, channel);
+                    continue;
+                }
+            };
+            // Every piece on its own, so a long one doesn't get turned away
+            let chunks = self.message_chunks(destination, &message, "");
+            match self.send_chunks(channel, chunks, 0, &[], None, &[]).await {
+                Ok(_) => delivered += 1,
+                Err((_, _, e)) => failed.push((channel, e)),
+            }
+        }
+        match (delivered, failed.is_empty()) {
+            (_, true) => Ok(delivered),
+            (0, false) => Err(PostError::Undelivered(failed)),
+            (_, false) => Err(PostError::Partial { delivered: delivered, failed: failed }),
+        }
+    }
+
+    /// Pick up new filters from a reloaded config, the destinations themselves stay put
+    /// until a restart
+    pub async fn reload_filters(&self, config: &Config) -> Result<(), String> {
+        let filters = Filters::new(&config.filters, &config.destinations(), &config.rules)?;
+        *self.filters.write().await = Arc::new(filters);
+        warn!("Reloaded post filters");
+        Ok(())
+    }
+
+    /// Plain text to one channel, for the stuff that isn't a sniffed post
+    pub async fn post_notice(&self, channel: ChannelId, text: String) -> Result<(), String> {
+        if self.dry_run {
+            warn!("Dry run, would have told channel {}: {}", channel, text);
+            return Ok(());
+        }
+        let http = self.bot_http.clone();
+        let text = split_message(&text, MESSAGE_LENGTH).into_iter().next().unwrap_or_default();
+        self.send_queue.send(channel, async move {
+            with_backoff("Notice", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                channel.send_message(&http, |m| {
+                    m.content(&text).allowed_mentions(|am| am.empty_parse())
+                })
+            }).await
+        }).await?.map(|_| ())
+    }
+
+    // Reply to one of our messages with what the post said before it went away
+    async fn queue_deleted_followup(&self, sent: &SentMessage, message: &SnifferPost, removal: Removal) -> Result<(), String> {
+        let http = self.bot_http.clone();
+        let (channel, message_id) = (sent.channel, sent.message);
+        let text = format!("This was {}, here's what it said:\n{}", removal.describe(), message.discord_string());
+        let text = split_message(&text, MESSAGE_LENGTH).into_iter().next().unwrap_or_default();
+        self.send_queue.send(channel, async move {
+            with_backoff("Deleted followup", SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                channel.send_message(&http, |m| {
+                    m.content(&text)
+                        .reference_message((channel, message_id))
+                        .allowed_mentions(|am| am.empty_parse())
+                })
+            }).await
+        }).await?.map(|_| ())
+    }
+
+    #[allow(dead_code)]
+    pub async fn post_debug_string(&self, message: String) -> Result<(), PostError> {
+        let http = &self.bot_http;
+        warn!("Trying to send debug message");
+        let channel = match self.test_channel {
+            Some(c) => c,
+            None => {
+                warn!("No test channel set up, dropping debug message");
+                return Ok(());
+            }
+        };
+        match channel.say(&http, message).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PostError::Undelivered(vec![(channel, e.to_string())])),
+        }
+    }
+}
+
+
+
+impl Clone for DiscordBot {
+    fn clone(&self) -> Self {
+        DiscordBot {
+            serenity_bot: self.serenity_bot.clone(),
+            bot_token: self.bot_token.clone(),
+            bot_http: self.bot_http.clone(),
+            shard_handle: {
+                match &self.shard_handle {
+                    Some(h) => Some(h.clone()),
+                    None => None,
+                }
+            },
+            shard_cancel_token: self.shard_cancel_token.clone(),
+            shard_manager: self.shard_manager.clone(),
+            destinations: self.destinations.clone(),
+            filters: self.filters.clone(),
+            test_channel: self.test_channel.clone(),
+            role_pings: self.role_pings.clone(),
+            webhook_poster: self.webhook_poster.clone(),
+            send_queue: self.send_queue.clone(),
+            http_client: self.http_client.clone(),
+            reddit_api: self.reddit_api.clone(),
+            presence: self.presence.clone(),
+            paused: self.paused.clone(),
+            admin_user: self.admin_user,
+            alerts: self.alerts.clone(),
+            auto_pin: self.auto_pin.clone(),
+            post_history: self.post_history.clone(),
+            health: self.health.clone(),
+            sent_messages: self.sent_messages.clone(),
+            store: self.store.clone(),
+            audio_player: self.audio_player.clone(),
+            command_parser: self.command_parser.clone(),
+            dry_run: self.dry_run,
+        }
+    }
+}
+
+
+#[async_trait]
+impl PostSink for DiscordBot {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    async fn deliver(&self, event: PostEvent) -> Result<(), String> {
+        match event {
+            PostEvent::New(message) => {
+                warn!("New sniffer message!:\n{}", message);
+                self.post_message(message).await.map(|_| ()).map_err(|e| e.to_string())
+            }
+            PostEvent::Edited { before, after } => {
+                warn!("Sniffer edited a post: {}", after.id);
+                self.edit_message(before, after).await;
+                Ok(())
+            }
+            PostEvent::Duplicate { original, duplicate } => {
+                warn!("Post {} is a duplicate of {}", duplicate.id, original.id);
+                self.link_duplicate(original).await;
+                Ok(())
+            }
+            PostEvent::Awarded { post, new_awards } => {
+                warn!("Post {} got awards", post.id);
+                self.handle_awarded(post, new_awards).await;
+                Ok(())
+            }
+            PostEvent::Deleted(message, removal) => {
+                warn!("Post {} was {}", message.id, removal.describe());
+                self.handle_deleted(message, removal).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn reload(&self, config: &Config) -> Result<(), String> {
+        self.reload_filters(config).await
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serenity::http::client::Http;
+use serenity::model::id::{ChannelId, UserId};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+// The window max_per_hour counts over
+const RATE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+use super::split::{split_message, MESSAGE_LENGTH};
+
+/// Where errors get sent so somebody actually sees them. They're batched up and rate limited,
+/// so a reddit outage is a handful of messages instead of one every poll
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlertConfig {
+    // Either or both, the admin's DMs if neither
+    pub channel: Option<u64>,
+    pub webhook: Option<String>,
+    // How long to gather alerts up before sending them together
+    #[serde(default = "default_batch_seconds")]
+    pub batch_seconds: u64,
+    #[serde(default = "default_max_per_hour")]
+    pub max_per_hour: usize,
+}
+
+fn default_batch_seconds() -> u64 {
+    60
+}
+
+fn default_max_per_hour() -> usize {
+    10
+}
+
+/// Hands alerts to the delivery task, does nothing if alerts aren't set up
+#[derive(Clone, Default)]
+pub struct Alerts {
+    sender: Option<mpsc::UnboundedSender<(String, String)>>,
+}
+
+impl Alerts {
+    /// Start delivering alerts in the background, if there's an [alerts] section
+    pub fn start(config: Option<AlertConfig>, http: Arc<Http>, admin: Option<UserId>, dry_run: bool) -> Alerts {
+        let config = match config {
+            Some(c) => c,
+            None => return Alerts::default(),
+        };
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(deliver_forever(receiver, config, http, admin, dry_run));
+        return Alerts {
+            sender: Some(sender),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Queue up an alert, kind groups them in the batch, like reddit_poll or discord_post
+    pub fn send(&self, kind: &str, text: String) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send((String::from(kind), text));
+        }
+    }
+
+    /// Alert on panics too, wherever they happen. Whatever the hook was before still runs
+    pub fn catch_panics(&self) {
+        if !self.enabled() {
+            return;
+        }
+        let alerts = self.clone();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            alerts.send("panic", info.to_string());
+            previous(info);
+        }));
+    }
+}
+
+// One kind of alert in a batch, how many came in and the most recent one
+struct Group {
+    kind: String,
+    count: usize,
+    last: String,
+}
+
+async fn deliver_forever(
+    mut receiver: mpsc::UnboundedReceiver<(String, String)>,
+    config: AlertConfig,
+    http: Arc<Http>,
+    admin: Option<UserId>,
+    dry_run: bool,
+) {
+    let client = reqwest::Client::new();
+    let mut sent = VecDeque::<Instant>::new();
+    // Alerts that came in while we were over the limit, they go out with the next batch
+    let mut held = Vec::<Group>::new();
+    loop {
+        // With some held back, don't wait on a new alert to send them, just until there's room
+        let first = match held.is_empty() {
+            true => match receiver.recv().await {
+                Some(a) => Some(a),
+                None => break,
+            },
+            false => {
+                let room_at = sent.front().map_or_else(Instant::now, |t| *t + RATE_WINDOW);
+                tokio::select! {
+                    alert = receiver.recv() => match alert {
+                        Some(a) => Some(a),
+                        None => break,
+                    },
+                    _ = tokio::time::sleep_until(room_at) => None,
+                }
+            }
+        };
+        let mut groups = std::mem::take(&mut held);
+        if let Some(first) = first {
+            add(&mut groups, first);
+            let deadline = Instant::now() + Duration::from_secs(config.batch_seconds);
+            while let Ok(Some(alert)) = tokio::time::timeout_at(deadline, receiver.recv()).await {
+                add(&mut groups, alert);
+            }
+        }
+
+        let hour_ago = Instant::now() - RATE_WINDOW;
+        while sent.front().map_or(false, |t| *t <= hour_ago) {
+            sent.pop_front();
+        }
+        if sent.len() >= config.max_per_hour {
+            warn!("Over {} alerts this hour, holding {} kinds back", config.max_per_hour, groups.len());
+            held = groups;
+            continue;
+        }
+        sent.push_back(Instant::now());
+
+        let text = groups.iter()
+            .map(|g| match g.count {
+                1 => format!("**{}**: {}", g.kind, g.last),
+                n => format!("**{}** x{}, last one: {}", g.kind, n, g.last),
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let text = split_message(&format!("🚨 {}", text), MESSAGE_LENGTH).into_iter().next().unwrap_or_default();
+        if dry_run {
+            warn!("Dry run, would have sent alerts: {}", text);
+            continue;
+        }
+        deliver(&config, &client, &http, admin, &text).await;
+    }
+}
+
+fn add(groups: &mut Vec<Group>, (kind, text): (String, String)) {
+    match groups.iter_mut().find(|g| g.kind == kind) {
+        Some(g) => {
+            g.count += 1;
+            g.last = text;
+        }
+        None => groups.push(Group { kind: kind, count: 1, last: text }),
+    }
+}
+
+async fn deliver(config: &AlertConfig, client: &reqwest::Client, http: &Http, admin: Option<UserId>, text: &str) {
+    if let Some(channel) = config.channel {
+        if let Err(e) = ChannelId(channel).say(http, text).await {
+            error!("Couldn't post alerts to channel {}: {}", channel, e);
+        }
+    }
+    if let Some(webhook) = &config.webhook {
+        let result = client.post(webhook)
+            .json(&serde_json::json!({ "content": text }))
+            .send().await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            error!("Couldn't post alerts to the webhook: {}", e);
+        }
+    }
+    if config.channel.is_none() && config.webhook.is_none() {
+        let admin = match admin {
+            Some(a) => a,
+            None => {
+                error!("Nowhere to send alerts, set alerts.channel, alerts.webhook or discord.admin_user");
+                return;
+            }
+        };
+        let result = match admin.create_dm_channel(http).await {
+            Ok(dm) => dm.say(http, text).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Couldn't DM the admin: {}", e);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use serde_json::{json, Value};
+
+use serenity::model::id::{ChannelId, MessageId, RoleId};
+
+// Serenity doesn't know about forum channels yet, so we hit the api ourselves
+const DISCORD_API: &str = "https://discord.com/api/v10";
+
+// Forum post titles have the same limit as thread names
+const FORUM_TITLE_LENGTH: usize = 100;
+
+/// Start a new post in a forum channel. The starter message shares its id with the thread,
+/// so we hand back the thread as the channel and the message
+pub async fn create_forum_post(
+    client: &reqwest::Client,
+    token: &str,
+    forum: ChannelId,
+    title: &str,
+    content: &str,
+    embed: Option<Value>,
+    roles: &[RoleId],
+) -> Result<(ChannelId, MessageId), String> {
+    let name: String = title.chars().take(FORUM_TITLE_LENGTH).collect();
+    let mut message = json!({
+        "content": content,
+        // Same as everywhere else, only our roles get pinged
+        "allowed_mentions": {
+            "parse": [],
+            "roles": roles.iter().map(|r| r.to_string()).collect::<Vec<String>>(),
+        },
+    });
+    if let Some(e) = embed {
+        message["embeds"] = json!([e]);
+    }
+    let body = json!({
+        "name": name,
+        "message": message,
+    });
+
+    let response = client
+        .post(format!("{}/channels/{}/threads", DISCORD_API, forum))
+        .header("Authorization", format!("Bot {}", token))
+        .json(&body)
+        .send().await;
+    let thread = match response {
+        Ok(r) => match r.error_for_status() {
+            Ok(r) => r.json::<Value>().await,
+            Err(e) => return Err(String::from(format!("Discord refused our forum post: {}", e))),
+        },
+        Err(e) => return Err(String::from(format!("Error creating forum post: {}", e))),
+    };
+    let id = match thread {
+        Ok(t) => t["id"].as_str().and_then(|i| i.parse::<u64>().ok()),
+        Err(e) => return Err(String::from(format!("Couldn't read forum post response: {}", e))),
+    };
+    match id {
+        Some(i) => Ok((ChannelId(i), MessageId(i))),
+        None => Err(String::from("Forum post response didn't have an id")),
+    }
+}
@@ -0,0 +1,127 @@
+use std::borrow::Cow;
+
+use serenity::http::AttachmentType;
+
+use crate::reddit::SnifferPost;
+
+// The most discord lets a regular server upload, across everything in one message
+const MAX_ATTACHMENT_SIZE: usize = 8 * 1024 * 1024;
+// And how many files can go on one message
+const MAX_ATTACHMENTS: usize = 10;
+
+const IMAGE_EXTENSIONS: &[&str] = &[".jpg", ".jpeg", ".png", ".gif", ".webp"];
+const VIDEO_EXTENSIONS: &[&str] = &[".mp4", ".webm", ".mov"];
+
+/// An image or video pulled off of a post, ready to attach to a message
+#[derive(Debug, Clone)]
+pub struct Media {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+impl Media {
+    pub fn is_image(&self) -> bool {
+        let name = self.filename.to_lowercase();
+        IMAGE_EXTENSIONS.iter().any(|e| name.ends_with(e))
+    }
+
+    /// Discord blurs anything with this prefix
+    pub fn spoilered(&self) -> Media {
+        Media {
+            filename: format!("SPOILER_{}", self.filename),
+            data: self.data.clone(),
+        }
+    }
+
+    pub fn attachment(&self) -> AttachmentType<'static> {
+        AttachmentType::Bytes {
+            data: Cow::from(self.data.clone()),
+            filename: self.filename.clone(),
+        }
+    }
+}
+
+/// Everything a post has to attach, a whole gallery if it's one, the mp4 if it's a reddit video,
+/// and the link otherwise
+pub fn media_urls(post: &SnifferPost) -> Vec<String> {
+    if !post.gallery.is_empty() {
+        return post.gallery.clone();
+    }
+    if let Some(v) = &post.video {
+        return vec![v.clone()];
+    }
+    post.url.iter().cloned().collect()
+}
+
+/// Grab as much of a post's media as fits on one message, anything that doesn't stays a link
+pub async fn download_all(client: &reqwest::Client, post: &SnifferPost) -> Vec<Media> {
+    let mut media = Vec::<Media>::new();
+    let mut total = 0;
+    for url in media_urls(post).iter().take(MAX_ATTACHMENTS) {
+        match download(client, url).await {
+            Ok(Some(m)) => {
+                if total + m.data.len() > MAX_ATTACHMENT_SIZE {
+                    warn!("No room left to attach {}, leaving the rest as links", url);
+                    break;
+                }
+                total += m.data.len();
+                media.push(m);
+            }
+            Ok(None) => {}
+            Err(e) => error!("{}, leaving it as a link", e),
+        }
+    }
+    media
+}
+
+/// If a url points straight at an image or video, give back the file name it'd have
+pub fn media_filename(url: &str) -> Option<String> {
+    // Drop any query string before checking the extension
+    let path = url.split('?').next().unwrap_or(url);
+    let filename = path.rsplit('/').next()?;
+    let lower = filename.to_lowercase();
+    if IMAGE_EXTENSIONS.iter().chain(VIDEO_EXTENSIONS.iter()).any(|e| lower.ends_with(e)) {
+        return Some(String::from(filename));
+    }
+    None
+}
+
+/// Download the media a post links to so discord doesn't have to unfurl it (which it's bad at for reddit)
+pub async fn download(client: &reqwest::Client, url: &str) -> Result<Option<Media>, String> {
+    let filename = match media_filename(url) {
+        Some(f) => f,
+        None => return Ok(None), // Not media, nothing to do
+    };
+    let response = match client.get(url).send().await.and_then(|r| r.error_for_status()) {
+        Ok(r) => r,
+        Err(e) => return Err(String::from(format!("Error downloading {}: {}", url, e))),
+    };
+    // Error pages and the like don't get passed off as images
+    let kind = response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if !kind.starts_with("image/") && !kind.starts_with("video/") {
+        return Err(String::from(format!("{} isn't media, it came back as {:?}", url, kind)));
+    }
+    if let Some(len) = response.content_length() {
+        if len as usize > MAX_ATTACHMENT_SIZE {
+            warn!("{} is too big to attach ({} bytes), leaving it as a link", url, len);
+            return Ok(None);
+        }
+    }
+    let data = match response.bytes().await {
+        Ok(b) => b.to_vec(),
+        Err(e) => return Err(String::from(format!("Error reading {}: {}", url, e))),
+    };
+    // Servers don't always tell us the length up front
+    if data.len() > MAX_ATTACHMENT_SIZE {
+        warn!("{} is too big to attach ({} bytes), leaving it as a link", url, data.len());
+        return Ok(None);
+    }
+    warn!("Downloaded {} ({} bytes) to attach", filename, data.len());
+    Ok(Some(Media {
+        filename: filename,
+        data: data,
+    }))
+}
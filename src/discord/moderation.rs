@@ -0,0 +1,236 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, RwLock};
+
+use serde::Deserialize;
+use serenity::{
+    prelude::*,
+    model::channel::{Reaction, ReactionType},
+    model::id::{GuildId, RoleId, UserId},
+    model::interactions::{
+        InteractionResponseType,
+        InteractionApplicationCommandCallbackDataFlags,
+        message_component::MessageComponentInteraction,
+    },
+};
+
+use crate::reddit::{SnifferPost, MutedAuthors};
+use crate::store::Store;
+use super::SentMessage;
+
+/// Ask the bot to send a post to the archives the usual way, it answers with how many got it
+pub type ArchiveRequest = (SnifferPost, oneshot::Sender<Result<usize, String>>);
+
+/// Which emojis do what when an admin reacts to one of our posts
+#[derive(Deserialize, Debug, Clone)]
+pub struct ModerationConfig {
+    // Members with this role count as admins, on top of anyone who can manage messages
+    pub admin_role: Option<u64>,
+    #[serde(default = "default_delete_emoji")]
+    pub delete_emoji: String,
+    #[serde(default = "default_pin_emoji")]
+    pub pin_emoji: String,
+    #[serde(default = "default_archive_emoji")]
+    pub archive_emoji: String,
+}
+
+fn default_delete_emoji() -> String {
+    String::from("🗑️")
+}
+fn default_pin_emoji() -> String {
+    String::from("📌")
+}
+fn default_archive_emoji() -> String {
+    String::from("📦")
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        ModerationConfig {
+            admin_role: None,
+            delete_emoji: default_delete_emoji(),
+            pin_emoji: default_pin_emoji(),
+            archive_emoji: default_archive_emoji(),
+        }
+    }
+}
+
+// Some clients send emojis with the variation selector and some don't, so ignore it
+fn same_emoji(a: &str, b: &str) -> bool {
+    a.trim_end_matches('\u{fe0f}') == b.trim_end_matches('\u{fe0f}')
+}
+
+/// Whether someone gets to use admin stuff, either they have the admin role or can manage messages
+pub async fn is_admin(ctx: &Context, admin_role: Option<u64>, guild_id: Option<GuildId>, user_id: Option<UserId>) -> Result<bool, String> {
+    let (guild_id, user_id) = match (guild_id, user_id) {
+        (Some(g), Some(u)) => (g, u),
+        _ => return Ok(false), // DMs and such
+    };
+    let member = match guild_id.member(ctx, user_id).await {
+        Ok(m) => m,
+        Err(e) => return Err(String::from(format!("Couldn't look up member: {}", e))),
+    };
+    if let Some(role) = admin_role {
+        if member.roles.contains(&RoleId(role)) {
+            return Ok(true);
+        }
+    }
+    match member.permissions(ctx).await {
+        Ok(p) => Ok(p.administrator() || p.manage_messages()),
+        Err(e) => Err(String::from(format!("Couldn't get permissions for {}: {}", user_id, e))),
+    }
+}
+
+#[derive(Debug)]
+enum ModAction {
+    Delete,
+    Pin,
+    Archive,
+}
+
+#[derive(Clone)]
+pub struct ReactionModerator {
+    config: ModerationConfig,
+    // We can't get at the bot from in here, archiving gets handed back to it
+    archive_requests: mpsc::Sender<ArchiveRequest>,
+    sent_messages: Arc<RwLock<HashMap<String, Vec<SentMessage>>>>,
+    post_history: Arc<RwLock<VecDeque<SnifferPost>>>,
+    muted_authors: MutedAuthors,
+    // For messages from before the last restart
+    store: Arc<dyn Store>,
+}
+
+// Button ids look like "mute:<author>"
+pub const MUTE_BUTTON_PREFIX: &str = "mute:";
+
+impl ReactionModerator {
+    pub fn new(
+        config: ModerationConfig,
+        archive_requests: mpsc::Sender<ArchiveRequest>,
+        sent_messages: Arc<RwLock<HashMap<String, Vec<SentMessage>>>>,
+        post_history: Arc<RwLock<VecDeque<SnifferPost>>>,
+        muted_authors: MutedAuthors,
+        store: Arc<dyn Store>,
+    ) -> ReactionModerator {
+        return ReactionModerator {
+            config: config,
+            archive_requests: archive_requests,
+            sent_messages: sent_messages,
+            post_history: post_history,
+            muted_authors: muted_authors,
+            store: store,
+        }
+    }
+
+    fn action_for(&self, emoji: &ReactionType) -> Option<ModAction> {
+        let emoji = match emoji {
+            ReactionType::Unicode(e) => e,
+            _ => return None, // We only do plain emojis
+        };
+        if same_emoji(emoji, &self.config.delete_emoji) {
+            Some(ModAction::Delete)
+        }
+        else if same_emoji(emoji, &self.config.pin_emoji) {
+            Some(ModAction::Pin)
+        }
+        else if same_emoji(emoji, &self.config.archive_emoji) {
+            Some(ModAction::Archive)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Find the reddit post one of our discord messages was for
+    async fn post_for_message(&self, reaction: &Reaction) -> Option<String> {
+        let sent = self.sent_messages.read().await;
+        sent.iter()
+            .find(|(_, messages)| messages.iter().any(|m| m.message == reaction.message_id))
+            .map(|(id, _)| id.clone())
+            .or_else(|| self.store.post_for_message(reaction.message_id.0))
+    }
+
+    async fn is_admin(&self, ctx: &Context, guild_id: Option<GuildId>, user_id: Option<UserId>) -> Result<bool, String> {
+        is_admin(ctx, self.config.admin_role, guild_id, user_id).await
+    }
+
+    /// Check a reaction, and if it's an admin doing something to one of our posts, do it
+    pub async fn process(&self, ctx: &Context, reaction: &Reaction) -> Result<(), String> {
+        let action = match self.action_for(&reaction.emoji) {
+            Some(a) => a,
+            None => return Ok(()),
+        };
+        let post_id = match self.post_for_message(reaction).await {
+            Some(id) => id,
+            None => return Ok(()), // Not one of ours
+        };
+        if !self.is_admin(ctx, reaction.guild_id, reaction.user_id).await? {
+            warn!("{:?} reaction on post {} from a non-admin, ignoring", reaction.user_id, post_id);
+            return Ok(());
+        }
+        warn!("Admin reacted with {:?} on post {}", action, post_id);
+
+        match action {
+            ModAction::Delete => {
+                if let Err(e) = reaction.channel_id.delete_message(&ctx.http, reaction.message_id).await {
+                    return Err(String::from(format!("Couldn't delete message: {}", e)));
+                }
+                // Forget about it so we don't try and edit it later
+                if let Some(messages) = self.sent_messages.write().await.get_mut(&post_id) {
+                    messages.retain(|m| m.message != reaction.message_id);
+                }
+                self.store.forget_message(reaction.message_id.0);
+            }
+            ModAction::Pin => {
+                if let Err(e) = reaction.channel_id.pin(&ctx.http, reaction.message_id).await {
+                    return Err(String::from(format!("Couldn't pin message: {}", e)));
+                }
+            }
+            ModAction::Archive => {
+                // From before the last restart it'll only be in the store
+                let recent = self.post_history.read().await.iter().find(|p| p.id == post_id).cloned();
+                let post = match recent.or_else(|| self.store.post(&post_id)) {
+                    Some(p) => p,
+                    None => return Err(String::from(format!("We don't have post {} anymore", post_id))),
+                };
+                let (result_tx, result_rx) = oneshot::channel();
+                if let Err(_) = self.archive_requests.send((post, result_tx)).await {
+                    return Err(String::from("The bot isn't taking archive requests"));
+                }
+                match result_rx.await {
+                    Ok(Ok(n)) => warn!("Force archived post {} to {} channels", post_id, n),
+                    Ok(Err(e)) => return Err(String::from(format!("Couldn't archive post {}: {}", post_id, e))),
+                    Err(_) => return Err(String::from("The bot never answered the archive request")),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle the mute button on one of our posts
+    pub async fn process_button(&self, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), String> {
+        let author = match component.data.custom_id.strip_prefix(MUTE_BUTTON_PREFIX) {
+            Some(a) => String::from(a),
+            None => return Err(String::from(format!("Unknown button: {}", component.data.custom_id))),
+        };
+        let user_id = component.member.as_ref().map(|m| m.user.id);
+        let reply = match self.is_admin(ctx, component.guild_id, user_id).await? {
+            true => {
+                self.muted_authors.mute(&author);
+                format!("Muted /u/{}, their posts won't get relayed anymore", author)
+            }
+            false => String::from("Only admins can mute authors"),
+        };
+        // Only whoever pushed the button needs to see this
+        let response = component.create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.content(reply).flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        }).await;
+        match response {
+            Ok(_) => Ok(()),
+            Err(e) => Err(String::from(format!("Couldn't respond to button press: {}", e))),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::time::Instant;
+
+use serenity::model::id::ChannelId;
+
+// Discord lets us send 5 messages every 5 seconds per channel, stay under it
+const CHANNEL_BURST: usize = 5;
+const CHANNEL_WINDOW: Duration = Duration::from_secs(5);
+
+type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Bounded outbound queue, everything we send goes through here so a pile of posts at once
+/// doesn't get us 429'd. Every channel gets its own worker sending one thing at a time, so a
+/// channel that's waiting on its rate limit doesn't hold up the rest
+#[derive(Clone)]
+pub struct SendQueue {
+    workers: Arc<Mutex<HashMap<ChannelId, mpsc::UnboundedSender<Job>>>>,
+    // Room left in the queue, every send holds one until it's gone out
+    room: Arc<Semaphore>,
+    // Sends waiting to go out, the ones going out right now included
+    queued: Arc<AtomicUsize>,
+}
+
+impl SendQueue {
+    pub fn new(capacity: usize) -> SendQueue {
+        let queued = Arc::new(AtomicUsize::new(0));
+        crate::runtime::STATS.track_queue("discord sends", queued.clone());
+        warn!("Started discord send queue");
+        return SendQueue {
+            workers: Arc::new(Mutex::new(HashMap::new())),
+            room: Arc::new(Semaphore::new(capacity)),
+            queued: queued,
+        }
+    }
+
+    /// Queue up a send to a channel and wait for it to go out. If the queue is full this waits
+    /// for room rather than dropping anything
+    pub async fn send<F, T>(&self, channel: ChannelId, send: F) -> Result<T, String>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = match self.room.clone().acquire_owned().await {
+            Ok(p) => p,
+            Err(_) => return Err(String::from("Send queue is closed")),
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        let job: Job = Box::pin(async move {
+            // Nobody listening is fine, they just didn't care about the result
+            let _ = result_tx.send(send.await);
+            drop(permit);
+        });
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        if let Err(_) = self.worker(channel).send(job) {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            return Err(String::from("Send queue is closed"));
+        }
+        match result_rx.await {
+            Ok(t) => Ok(t),
+            Err(_) => Err(String::from("Send queue dropped our message")),
+        }
+    }
+
+    /// Wait for everything queued so far to go out, gives up after the timeout. Gives back
+    /// how many were still waiting if it had to give up
+    pub async fn flush(&self, timeout: Duration) -> Result<(), usize> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let waiting = self.queued.load(Ordering::Relaxed);
+            if waiting == 0 {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(waiting);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    // The channel's worker, started the first time we send there
+    fn worker(&self, channel: ChannelId) -> mpsc::UnboundedSender<Job> {
+        let mut workers = self.workers.lock().unwrap();
+        workers.entry(channel).or_insert_with(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::spawn(SendQueue::run(channel, receiver, self.queued.clone()));
+            sender
+        }).clone()
+    }
+
+    async fn run(channel: ChannelId, mut receiver: mpsc::UnboundedReceiver<Job>, queued: Arc<AtomicUsize>) {
+        debug!("Started the send worker for channel {}", channel);
+        // When we last sent here, oldest first
+        let mut sends = VecDeque::<Instant>::new();
+        while let Some(job) = receiver.recv().await {
+            // Forget about anything that's out of the window
+            while sends.front().map_or(false, |t| t.elapsed() >= CHANNEL_WINDOW) {
+                sends.pop_front();
+            }
+            if sends.len() >= CHANNEL_BURST {
+                let wait_until = *sends.front().unwrap() + CHANNEL_WINDOW;
+                warn!("Hit our rate limit for channel {}, waiting", channel);
+                tokio::time::sleep_until(wait_until).await;
+                sends.pop_front();
+            }
+            sends.push_back(Instant::now());
+            job.await;
+            queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        debug!("Send worker for channel {} stopped", channel);
+    }
+}
@@ -0,0 +1,815 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
+
+use serenity::{
+    prelude::*,
+    client::bridge::gateway::ShardManager,
+    builder::{CreateApplicationCommand, CreateApplicationCommandOption},
+    model::id::GuildId,
+    model::interactions::{
+        InteractionResponseType,
+        application_command::{
+            ApplicationCommandInteraction,
+            ApplicationCommandInteractionDataOption,
+            ApplicationCommandOptionType,
+        },
+    },
+};
+
+use crate::reddit::{SnifferPost, DisabledUsers};
+use crate::store::{self, ExportFormat, StatsSummary, Store};
+use super::{Presence, update_paused};
+use super::split::{split_message, MESSAGE_LENGTH};
+use super::moderation::is_admin;
+
+// How many relayed posts we keep around for the recall commands
+pub const HISTORY_SIZE: usize = 50;
+// How many /lastposts gives back if you don't say
+const DEFAULT_RECALL_COUNT: usize = 5;
+// Most /history will dig up at once, it all has to fit in one message anyway
+const MAX_HISTORY_COUNT: usize = 25;
+// How many authors and subreddits /stats lists
+const STATS_TOP_COUNT: usize = 5;
+// Most dead letters /deadletters list shows
+const DEAD_LETTER_LIST_COUNT: usize = 20;
+
+/// How the bot's been doing, for the status command
+#[derive(Debug, Default)]
+pub struct BotHealth {
+    // When reddit last answered us properly
+    pub last_poll: Option<Instant>,
+    // Posts we've gotten out to at least one channel
+    pub relayed: u64,
+}
+
+/// Ask the reddit loop to poll right now, it answers with how many new posts it found
+pub type PollRequest = oneshot::Sender<Result<usize, String>>;
+
+/// Ask the reddit loop to re-read the config file, it answers with what changed
+pub type ReloadRequest = oneshot::Sender<Result<String, String>>;
+
+/// Ask the bot to retry dead letters, one post's or all of them, it answers with how it went
+pub type RedriveRequest = (Option<String>, oneshot::Sender<Result<String, String>>);
+
+// So commands can get at shard latencies through the context
+pub struct ShardManagerContainer;
+
+impl TypeMapKey for ShardManagerContainer {
+    type Value = Arc<Mutex<ShardManager>>;
+}
+
+// Static description of a command option, subcommands nest their own options
+struct OptionSpec {
+    name: &'static str,
+    description: &'static str,
+    kind: ApplicationCommandOptionType,
+    required: bool,
+    options: &'static [OptionSpec],
+}
+
+impl OptionSpec {
+    fn build<'a>(&self, o: &'a mut CreateApplicationCommandOption) -> &'a mut CreateApplicationCommandOption {
+        o.name(self.name).description(self.description).kind(self.kind).required(self.required);
+        for sub in self.options {
+            o.create_sub_option(|s| sub.build(s));
+        }
+        o
+    }
+}
+
+// Static description of a top level command
+struct CommandSpec {
+    name: &'static str,
+    description: &'static str,
+    options: &'static [OptionSpec],
+}
+
+impl CommandSpec {
+    fn build<'a>(&self, c: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        c.name(self.name).description(self.description);
+        for option in self.options {
+            c.create_option(|o| option.build(o));
+        }
+        c
+    }
+}
+
+// Our command registry, everything in here gets registered with discord on ready
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "status",
+        description: "Check up on the sniffer bot's health",
+        options: &[],
+    },
+    CommandSpec {
+        name: "botstats",
+        description: "How the bot process itself is doing, memory, tasks and queues",
+        options: &[],
+    },
+    CommandSpec {
+        name: "lastpost",
+        description: "Show the last thing the sniffer posted",
+        options: &[],
+    },
+    CommandSpec {
+        name: "lastposts",
+        description: "Catch up on the last few things the sniffer posted",
+        options: &[
+            OptionSpec {
+                name: "n",
+                description: "How many posts to show",
+                kind: ApplicationCommandOptionType::Integer,
+                required: false,
+                options: &[],
+            },
+        ],
+    },
+    CommandSpec {
+        name: "history",
+        description: "Look through everything we've archived from one author",
+        options: &[
+            OptionSpec {
+                name: "author",
+                description: "Their reddit username",
+                kind: ApplicationCommandOptionType::String,
+                required: true,
+                options: &[],
+            },
+            OptionSpec {
+                name: "limit",
+                description: "How many posts to show",
+                kind: ApplicationCommandOptionType::Integer,
+                required: false,
+                options: &[],
+            },
+        ],
+    },
+    CommandSpec {
+        name: "stats",
+        description: "How much got sniffed, who from and where",
+        options: &[
+            OptionSpec {
+                name: "from",
+                description: "First day to count, like 2024-01-31",
+                kind: ApplicationCommandOptionType::String,
+                required: false,
+                options: &[],
+            },
+            OptionSpec {
+                name: "to",
+                description: "Last day to count, like 2024-02-29",
+                kind: ApplicationCommandOptionType::String,
+                required: false,
+                options: &[],
+            },
+        ],
+    },
+    CommandSpec {
+        name: "pause",
+        description: "Stop relaying reddit posts until resumed (admins only)",
+        options: &[],
+    },
+    CommandSpec {
+        name: "resume",
+        description: "Start relaying reddit posts again (admins only)",
+        options: &[],
+    },
+    CommandSpec {
+        name: "forcepoll",
+        description: "Check reddit right now instead of waiting (admins only)",
+        options: &[],
+    },
+    CommandSpec {
+        name: "reload",
+        description: "Re-read the config file without restarting (admins only)",
+        options: &[],
+    },
+    CommandSpec {
+        name: "loglevel",
+        description: "See or change what gets logged, like sniffer::reddit=debug (admins only to change)",
+        options: &[
+            OptionSpec {
+                name: "filter",
+                description: "A level like debug, or per module like sniffer=warn,sniffer::reddit=debug",
+                kind: ApplicationCommandOptionType::String,
+                required: false,
+                options: &[],
+            },
+        ],
+    },
+    CommandSpec {
+        name: "deadletters",
+        description: "Posts that never made it to a channel",
+        options: &[
+            OptionSpec {
+                name: "list",
+                description: "Show what's waiting to be sent again",
+                kind: ApplicationCommandOptionType::SubCommand,
+                required: false,
+                options: &[],
+            },
+            OptionSpec {
+                name: "retry",
+                description: "Try sending them again (admins only)",
+                kind: ApplicationCommandOptionType::SubCommand,
+                required: false,
+                options: &[
+                    OptionSpec {
+                        name: "post",
+                        description: "Just this post's id, everything if you don't say",
+                        kind: ApplicationCommandOptionType::String,
+                        required: false,
+                        options: &[],
+                    },
+                ],
+            },
+        ],
+    },
+    CommandSpec {
+        name: "users",
+        description: "See and switch which reddit users we follow",
+        options: &[
+            OptionSpec {
+                name: "list",
+                description: "Show every user we follow and if they're on",
+                kind: ApplicationCommandOptionType::SubCommand,
+                required: false,
+                options: &[],
+            },
+            OptionSpec {
+                name: "enable",
+                description: "Start relaying a user again (admins only)",
+                kind: ApplicationCommandOptionType::SubCommand,
+                required: false,
+                options: &[
+                    OptionSpec {
+                        name: "name",
+                        description: "Their reddit username",
+                        kind: ApplicationCommandOptionType::String,
+                        required: true,
+                        options: &[],
+                    },
+                ],
+            },
+            OptionSpec {
+                name: "disable",
+                description: "Stop relaying a user for now (admins only)",
+                kind: ApplicationCommandOptionType::SubCommand,
+                required: false,
+                options: &[
+                    OptionSpec {
+                        name: "name",
+                        description: "Their reddit username",
+                        kind: ApplicationCommandOptionType::String,
+                        required: true,
+                        options: &[],
+                    },
+                ],
+            },
+        ],
+    },
+    CommandSpec {
+        name: "archive",
+        description: "Dig through recently sniffed posts",
+        options: &[
+            OptionSpec {
+                name: "search",
+                description: "Search recent sniffs for some text",
+                kind: ApplicationCommandOptionType::SubCommand,
+                required: false,
+                options: &[
+                    OptionSpec {
+                        name: "query",
+                        description: "Text to look for in the title or body",
+                        kind: ApplicationCommandOptionType::String,
+                        required: true,
+                        options: &[],
+                    },
+                ],
+            },
+            OptionSpec {
+                name: "export",
+                description: "Get everything we've archived as a file (admins only)",
+                kind: ApplicationCommandOptionType::SubCommand,
+                required: false,
+                options: &[
+                    OptionSpec {
+                        name: "from",
+                        description: "First day to include, like 2024-01-31",
+                        kind: ApplicationCommandOptionType::String,
+                        required: false,
+                        options: &[],
+                    },
+                    OptionSpec {
+                        name: "to",
+                        description: "Last day to include, like 2024-02-29",
+                        kind: ApplicationCommandOptionType::String,
+                        required: false,
+                        options: &[],
+                    },
+                    OptionSpec {
+                        name: "format",
+                        description: "json or csv, json if you don't say",
+                        kind: ApplicationCommandOptionType::String,
+                        required: false,
+                        options: &[],
+                    },
+                    OptionSpec {
+                        name: "stats",
+                        description: "Export the daily stats instead of the posts",
+                        kind: ApplicationCommandOptionType::Boolean,
+                        required: false,
+                        options: &[],
+                    },
+                ],
+            },
+        ],
+    },
+];
+
+// Pull a string option out of a list of options by name
+fn string_option(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<String> {
+    options.iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .map(String::from)
+}
+
+// Same for integers
+fn integer_option(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<i64> {
+    options.iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_i64())
+}
+
+// And booleans
+fn boolean_option(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<bool> {
+    options.iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_bool())
+}
+
+#[derive(Clone)]
+pub struct SlashCommands {
+    guild_id: GuildId,
+    admin_role: Option<u64>,
+    started: Instant,
+    post_history: Arc<RwLock<VecDeque<SnifferPost>>>,
+    health: Arc<RwLock<BotHealth>>,
+    presence: Arc<RwLock<Presence>>,
+    paused: Arc<AtomicBool>,
+    poll_requests: mpsc::Sender<PollRequest>,
+    reload_requests: mpsc::Sender<ReloadRequest>,
+    redrive_requests: mpsc::Sender<RedriveRequest>,
+    watched_users: Vec<String>,
+    disabled_users: DisabledUsers,
+    store: Arc<dyn Store>,
+}
+
+impl SlashCommands {
+    pub fn new(
+        guild_id: u64,
+        admin_role: Option<u64>,
+        post_history: Arc<RwLock<VecDeque<SnifferPost>>>,
+        health: Arc<RwLock<BotHealth>>,
+        presence: Arc<RwLock<Presence>>,
+        paused: Arc<AtomicBool>,
+        poll_requests: mpsc::Sender<PollRequest>,
+        reload_requests: mpsc::Sender<ReloadRequest>,
+        redrive_requests: mpsc::Sender<RedriveRequest>,
+        watched_users: Vec<String>,
+        disabled_users: DisabledUsers,
+        store: Arc<dyn Store>,
+    ) -> SlashCommands {
+        return SlashCommands {
+            guild_id: GuildId(guild_id),
+            admin_role: admin_role,
+            started: Instant::now(),
+            post_history: post_history,
+            health: health,
+            presence: presence,
+            paused: paused,
+            poll_requests: poll_requests,
+            reload_requests: reload_requests,
+            redrive_requests: redrive_requests,
+            watched_users: watched_users,
+            disabled_users: disabled_users,
+            store: store,
+        }
+    }
+
+    /// Register everything in our command table with the guild
+    pub async fn register(&self, ctx: &Context) -> Result<(), String> {
+        let result = self.guild_id.set_application_commands(&ctx.http, |cmds| {
+            for spec in COMMANDS {
+                cmds.create_application_command(|c| spec.build(c));
+            }
+            cmds
+        }).await;
+        match result {
+            Ok(c) => {
+                warn!("Registered {} slash commands", c.len());
+                Ok(())
+            }
+            Err(e) => Err(String::from(format!("Error registering slash commands: {}", e))),
+        }
+    }
+
+    /// Route a command interaction to its handler and reply with the result
+    pub async fn process(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), String> {
+        warn!("Got slash command {}", command.data.name);
+        // Discord only gives us 3 seconds to answer, some of these take longer so say we're thinking first
+        let deferred = command.create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+        }).await;
+        if let Err(e) = deferred {
+            return Err(String::from(format!("Failed to acknowledge slash command: {}", e)));
+        }
+        let reply = match command.data.name.as_str() {
+            "status" => self.status(ctx).await,
+            "botstats" => Ok(crate::runtime::STATS.report()),
+            "lastpost" => self.last_post().await,
+            "lastposts" => self.last_posts(&command.data.options).await,
+            "history" => self.history(&command.data.options),
+            "stats" => self.stats(&command.data.options),
+            "pause" => self.set_paused(ctx, command, true).await,
+            "resume" => self.set_paused(ctx, command, false).await,
+            "forcepoll" => self.force_poll(ctx, command).await,
+            "reload" => self.reload(ctx, command).await,
+            "loglevel" => self.log_level(ctx, command).await,
+            "deadletters" => self.dead_letters(ctx, command).await,
+            "users" => self.users(ctx, command).await,
+            "archive" => self.archive(ctx, command).await,
+            _ => Err(String::from(format!("Unknown slash command: {}", command.data.name))),
+        };
+        // Errors get sent back to the user too, so they know what went wrong
+        let text = match &reply {
+            Ok(t) => t,
+            Err(e) => e,
+        };
+        // Replies can quote post titles and users, so keep them to one message and don't let them ping anyone
+        let text = split_message(text, MESSAGE_LENGTH).into_iter().next().unwrap_or_default();
+        let response = command.edit_original_interaction_response(&ctx.http, |r| {
+            r.content(text).allowed_mentions(|am| am.empty_parse())
+        }).await;
+        if let Err(e) = response {
+            return Err(String::from(format!("Failed to respond to slash command: {}", e)));
+        }
+        reply.map(|_| ())
+    }
+
+    async fn status(&self, ctx: &Context) -> Result<String, String> {
+        let uptime = self.started.elapsed().as_secs();
+        let state = match self.paused.load(Ordering::Relaxed) {
+            true => "Paused",
+            false => "Sniffing away",
+        };
+        let mut lines = vec![format!("{}, up for {}h {}m", state, uptime / 3600, (uptime % 3600) / 60)];
+
+        // Same thing print_shard_info logs
+        let manager = ctx.data.read().await.get::<ShardManagerContainer>().cloned();
+        match manager {
+            Some(manager) => {
+                let lock = manager.lock().await;
+                let runners = lock.runners.lock().await;
+                for (id, runner) in runners.iter() {
+                    let latency = match runner.latency {
+                        Some(l) => format!("{}ms", l.as_millis()),
+                        None => String::from("unknown"),
+                    };
+                    lines.push(format!("Shard {} is {} with a latency of {}", id, runner.stage, latency));
+                }
+            }
+            None => lines.push(String::from("No shard info available")),
+        }
+
+        let health = self.health.read().await;
+        match health.last_poll {
+            Some(t) => lines.push(format!("Last heard from reddit {}s ago", t.elapsed().as_secs())),
+            None => lines.push(String::from("Haven't heard from reddit yet")),
+        }
+        let recent = self.post_history.read().await.len();
+        lines.push(format!("Relayed {} posts since startup, {} recent ones in memory", health.relayed, recent));
+        Ok(lines.join("\n"))
+    }
+
+    async fn set_paused(&self, ctx: &Context, command: &ApplicationCommandInteraction, paused: bool) -> Result<String, String> {
+        let user_id = command.member.as_ref().map(|m| m.user.id);
+        if !is_admin(ctx, self.admin_role, command.guild_id, user_id).await? {
+            return Err(String::from("Only admins can pause or resume the sniffer"));
+        }
+        let manager = match ctx.data.read().await.get::<ShardManagerContainer>().cloned() {
+            Some(m) => m,
+            None => return Err(String::from("Can't get at the shards to update our status")),
+        };
+        if !update_paused(&manager, &self.presence, &self.paused, paused).await {
+            return Ok(String::from(match paused {
+                true => "Already paused",
+                false => "Not paused",
+            }));
+        }
+        warn!("Sniffing {} by {:?}", if paused { "paused" } else { "resumed" }, user_id);
+        Ok(String::from(match paused {
+            true => "Paused, no more posts until someone runs /resume",
+            false => "Resumed, back to sniffing",
+        }))
+    }
+
+    async fn force_poll(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<String, String> {
+        let user_id = command.member.as_ref().map(|m| m.user.id);
+        if !is_admin(ctx, self.admin_role, command.guild_id, user_id).await? {
+            return Err(String::from("Only admins can force a poll"));
+        }
+        let (result_tx, result_rx) = oneshot::channel();
+        if let Err(_) = self.poll_requests.send(result_tx).await {
+            return Err(String::from("The reddit loop isn't running"));
+        }
+        match result_rx.await {
+            Ok(Ok(found)) => Ok(format!("Polled reddit, found {} new posts", found)),
+            Ok(Err(e)) => Err(String::from(format!("Poll failed: {}", e))),
+            Err(_) => Err(String::from("The reddit loop never answered")),
+        }
+    }
+
+    async fn reload(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<String, String> {
+        let user_id = command.member.as_ref().map(|m| m.user.id);
+        if !is_admin(ctx, self.admin_role, command.guild_id, user_id).await? {
+            return Err(String::from("Only admins can reload the config"));
+        }
+        warn!("Config reload asked for by {:?}", user_id);
+        let (result_tx, result_rx) = oneshot::channel();
+        if let Err(_) = self.reload_requests.send(result_tx).await {
+            return Err(String::from("The reddit loop isn't running"));
+        }
+        match result_rx.await {
+            Ok(result) => result,
+            Err(_) => Err(String::from("The reddit loop never answered")),
+        }
+    }
+
+    async fn log_level(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<String, String> {
+        let level = match string_option(&command.data.options, "filter") {
+            Some(l) => l,
+            None => return match crate::logging::level() {
+                Some(l) => Ok(format!("Logging at {}", l)),
+                None => Err(String::from("Logging isn't set up")),
+            },
+        };
+        let user_id = command.member.as_ref().map(|m| m.user.id);
+        if !is_admin(ctx, self.admin_role, command.guild_id, user_id).await? {
+            return Err(String::from("Only admins can change the log level"));
+        }
+        warn!("Log level change to {} asked for by {:?}", level, user_id);
+        crate::logging::set_level(&level)?;
+        Ok(format!("Logging at {} now, until a restart or a reload", level))
+    }
+
+    async fn dead_letters(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<String, String> {
+        let subcommand = match command.data.options.first() {
+            Some(o) => o,
+            None => return Err(String::from("No deadletters subcommand given")),
+        };
+        if subcommand.name == "list" {
+            let letters = self.store.dead_letters()?;
+            if letters.is_empty() {
+                return Ok(String::from("Nothing dead lettered, everything got where it was going"));
+            }
+            let mut lines = vec![format!("{} dead letters:", letters.len())];
+            for letter in letters.iter().take(DEAD_LETTER_LIST_COUNT) {
+                lines.push(format!(
+                    "`{}` to <#{}>, {} attempts, last <t:{}:R>: {}",
+                    letter.post.id, letter.destination, letter.attempts, letter.failed_at, letter.error
+                ));
+            }
+            if letters.len() > DEAD_LETTER_LIST_COUNT {
+                lines.push(format!("...and {} more", letters.len() - DEAD_LETTER_LIST_COUNT));
+            }
+            return Ok(split_message(&lines.join("\n"), MESSAGE_LENGTH).into_iter().next().unwrap_or_default());
+        }
+        let user_id = command.member.as_ref().map(|m| m.user.id);
+        if !is_admin(ctx, self.admin_role, command.guild_id, user_id).await? {
+            return Err(String::from("Only admins can retry dead letters"));
+        }
+        let post_id = string_option(&subcommand.options, "post");
+        warn!("Dead letter retry for {:?} asked for by {:?}", post_id, user_id);
+        let (result_tx, result_rx) = oneshot::channel();
+        if let Err(_) = self.redrive_requests.send((post_id, result_tx)).await {
+            return Err(String::from("The bot isn't taking retries"));
+        }
+        match result_rx.await {
+            Ok(result) => result,
+            Err(_) => Err(String::from("The bot never answered")),
+        }
+    }
+
+    async fn users(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<String, String> {
+        let subcommand = match command.data.options.first() {
+            Some(o) => o,
+            None => return Err(String::from("No users subcommand given")),
+        };
+        if subcommand.name == "list" {
+            let lines: Vec<String> = self.watched_users.iter()
+                .map(|u| match self.disabled_users.is_disabled(u) {
+                    true => format!("/u/{} (disabled)", u),
+                    false => format!("/u/{}", u),
+                })
+                .collect();
+            return Ok(lines.join("\n"));
+        }
+
+        let user_id = command.member.as_ref().map(|m| m.user.id);
+        if !is_admin(ctx, self.admin_role, command.guild_id, user_id).await? {
+            return Err(String::from("Only admins can switch users on and off"));
+        }
+        let name = match string_option(&subcommand.options, "name") {
+            Some(n) => String::from(n.trim_start_matches("/u/").trim_start_matches("u/")),
+            None => return Err(String::from("No username given")),
+        };
+        let user = match self.watched_users.iter().find(|u| u.eq_ignore_ascii_case(&name)) {
+            Some(u) => u,
+            None => return Err(String::from(format!("We don't follow /u/{}", name))),
+        };
+        match subcommand.name.as_str() {
+            "enable" => {
+                self.disabled_users.enable(user);
+                Ok(format!("Following /u/{} again", user))
+            }
+            "disable" => {
+                self.disabled_users.disable(user);
+                Ok(format!("Not relaying /u/{} until it's enabled again", user))
+            }
+            _ => Err(String::from(format!("Unknown users subcommand: {}", subcommand.name))),
+        }
+    }
+
+    async fn last_post(&self) -> Result<String, String> {
+        let history = self.post_history.read().await;
+        match history.back() {
+            Some(p) => Ok(p.discord_string()),
+            None => Err(String::from("Haven't sniffed anything yet")),
+        }
+    }
+
+    async fn last_posts(&self, options: &[ApplicationCommandInteractionDataOption]) -> Result<String, String> {
+        let count = match integer_option(options, "n") {
+            Some(n) if n < 1 => return Err(String::from("Need to ask for at least 1 post")),
+            Some(n) => (n as usize).min(HISTORY_SIZE),
+            None => DEFAULT_RECALL_COUNT,
+        };
+        let history = self.post_history.read().await;
+        if history.is_empty() {
+            return Err(String::from("Haven't sniffed anything yet"));
+        }
+        // Oldest first so it reads like the channel would
+        let skip = history.len().saturating_sub(count);
+        let mut reply = String::new();
+        let mut shown = 0;
+        for post in history.iter().skip(skip).rev() {
+            let line = format!("**{}** - /r/{} <https://www.reddit.com{}>\n", post.title, post.subreddit, post.permalink);
+            // Everything has to fit in one reply, keep the newest ones if it doesn't
+            if reply.chars().count() + line.chars().count() > MESSAGE_LENGTH {
+                break;
+            }
+            reply.insert_str(0, &line);
+            shown += 1;
+        }
+        if shown < count.min(history.len()) {
+            warn!("Only had room for {} of the last {} posts", shown, count);
+        }
+        Ok(reply)
+    }
+
+    fn history(&self, options: &[ApplicationCommandInteractionDataOption]) -> Result<String, String> {
+        let author = match string_option(options, "author") {
+            Some(a) => String::from(a.trim().trim_start_matches('/').trim_start_matches("u/")),
+            None => return Err(String::from("No author given")),
+        };
+        let count = match integer_option(options, "limit") {
+            Some(n) if n < 1 => return Err(String::from("Need to ask for at least 1 post")),
+            Some(n) => (n as usize).min(MAX_HISTORY_COUNT),
+            None => DEFAULT_RECALL_COUNT,
+        };
+        let posts = self.store.posts_by_author(&author, count)?;
+        if posts.is_empty() {
+            return Err(String::from(format!("Haven't archived anything from /u/{}", author)));
+        }
+        let mut reply = format!("Last {} from /u/{}:\n", posts.len(), author);
+        let mut shown = 0;
+        for post in posts.iter() {
+            let line = format!("<t:{}:d> **{}** - /r/{} <https://www.reddit.com{}>\n", post.created_at, post.title, post.subreddit, post.permalink);
+            // Newest first, so whatever doesn't fit is the oldest
+            if reply.chars().count() + line.chars().count() > MESSAGE_LENGTH {
+                break;
+            }
+            reply.push_str(&line);
+            shown += 1;
+        }
+        if shown < posts.len() {
+            warn!("Only had room for {} of {} posts from /u/{}", shown, posts.len(), author);
+        }
+        Ok(reply)
+    }
+
+    fn stats(&self, options: &[ApplicationCommandInteractionDataOption]) -> Result<String, String> {
+        let from = string_option(options, "from");
+        let to = string_option(options, "to");
+        let (start, end) = store::date_range(from.as_deref(), to.as_deref())?;
+        let summary = StatsSummary::new(&self.store.stats_between(start, end)?);
+        if summary.posts == 0 {
+            return Err(String::from("Nothing sniffed in that time"));
+        }
+        let top = |ranked: &[(String, i64, f64)], prefix: &str| -> String {
+            ranked.iter().take(STATS_TOP_COUNT)
+                .map(|(name, posts, score)| format!("{}{} ({}, avg {:.0})", prefix, name, posts, score))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let mut reply = format!(
+            "**{}** posts from {} to {}, averaging {:.1} points\n",
+            summary.posts,
+            from.as_deref().unwrap_or("the start"),
+            to.as_deref().unwrap_or("now"),
+            summary.average_score,
+        );
+        if let Some((hour, posts)) = summary.busiest_hour {
+            reply.push_str(&format!("Busiest hour: {:02}:00 UTC with {}\n", hour, posts));
+        }
+        if let Some((day, posts)) = &summary.busiest_day {
+            reply.push_str(&format!("Busiest day: {} with {}\n", day, posts));
+        }
+        reply.push_str(&format!("Top authors: {}\n", top(&summary.authors, "/u/")));
+        reply.push_str(&format!("Top subreddits: {}", top(&summary.subreddits, "/r/")));
+        // Only so many authors' names fit
+        Ok(split_message(&reply, MESSAGE_LENGTH).into_iter().next().unwrap_or_default())
+    }
+
+    async fn archive(&self, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<String, String> {
+        let subcommand = match command.data.options.first() {
+            Some(o) => o,
+            None => return Err(String::from("No archive subcommand given")),
+        };
+        match subcommand.name.as_str() {
+            "search" => {
+                let query = match string_option(&subcommand.options, "query") {
+                    Some(q) => q.to_lowercase(),
+                    None => return Err(String::from("No search query given")),
+                };
+                let history = self.post_history.read().await;
+                let found: Vec<String> = history.iter().rev()
+                    .filter(|p| {
+                        p.title.to_lowercase().contains(&query) ||
+                        p.body.as_ref().map_or(false, |b| b.to_lowercase().contains(&query))
+                    })
+                    .take(5)
+                    .map(|p| format!("**{}** - /r/{} <https://www.reddit.com{}>", p.title, p.subreddit, p.permalink))
+                    .collect();
+                if found.is_empty() {
+                    return Err(String::from(format!("Nothing found for \"{}\"", query)));
+                }
+                Ok(found.join("\n"))
+            }
+            "export" => {
+                let user_id = command.member.as_ref().map(|m| m.user.id);
+                if !is_admin(ctx, self.admin_role, command.guild_id, user_id).await? {
+                    return Err(String::from("Only admins can export the archive"));
+                }
+                let format = match string_option(&subcommand.options, "format") {
+                    Some(f) => match ExportFormat::parse(&f) {
+                        Some(f) => f,
+                        None => return Err(String::from(format!("Can't export as {}, only json or csv", f))),
+                    },
+                    None => ExportFormat::Json,
+                };
+                let from = string_option(&subcommand.options, "from");
+                let to = string_option(&subcommand.options, "to");
+                let (start, end) = store::date_range(from.as_deref(), to.as_deref())?;
+                let (file, filename, what) = match boolean_option(&subcommand.options, "stats").unwrap_or(false) {
+                    true => {
+                        let rows = self.store.stats_between(start, end)?;
+                        (store::export_stats(&rows, format)?, "sniffer_stats", format!("{} stats rows", rows.len()))
+                    }
+                    false => {
+                        let posts = self.store.posts_between(start, end)?;
+                        (store::export(&posts, format)?, "sniffer_archive", format!("{} posts", posts.len()))
+                    }
+                };
+                let filename = format!("{}.{}", filename, format.extension());
+                warn!("Exporting {} for {:?}", what, user_id);
+                let sent = command.create_followup_message(&ctx.http, |f| {
+                    f.add_file((file.as_slice(), filename.as_str()))
+                }).await;
+                match sent {
+                    Ok(_) => Ok(format!("Exported {}", what)),
+                    Err(e) => Err(String::from(format!("Couldn't upload the export: {}", e))),
+                }
+            }
+            _ => Err(String::from(format!("Unknown archive subcommand: {}", subcommand.name))),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Most discord allows in a single message
+pub const MESSAGE_LENGTH: usize = 2000;
+
+// Places we'd rather break a message, best first
+const SEPARATORS: &[&str] = &["\n\n", "\n", ". ", " "];
+const FENCE: &str = "```";
+// Longest code block language we'll carry over to the next piece
+const LANGUAGE_LENGTH: usize = 16;
+// Closing a code block on the end of a piece and opening it again on the next
+const FENCE_ROOM: usize = 2 * FENCE.len() + LANGUAGE_LENGTH + 2;
+
+/// Break text up into pieces that fit in a message, splitting on paragraphs if we can,
+/// then lines, sentences, words, and as a last resort wherever the limit lands. Code blocks
+/// that get split are closed and opened again, so the rest doesn't come out as plain text
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let fenced = text.contains(FENCE);
+    let limit = match fenced {
+        true => limit.saturating_sub(FENCE_ROOM).max(1),
+        false => limit,
+    };
+    let mut chunks = Vec::<String>::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for piece in pieces(text, limit, 0) {
+        let piece_len = piece.chars().count();
+        if current_len + piece_len > limit && !current.is_empty() {
+            chunks.push(String::from(current.trim_end()));
+            current.clear();
+            current_len = 0;
+        }
+        current.push_str(&piece);
+        current_len += piece_len;
+    }
+    if !current.trim().is_empty() {
+        chunks.push(String::from(current.trim_end()));
+    }
+    match fenced {
+        true => close_fences(chunks),
+        false => chunks,
+    }
+}
+
+// Close any code block a piece ends in the middle of, and open it again with the same
+// language at the start of the next one
+fn close_fences(chunks: Vec<String>) -> Vec<String> {
+    let mut open: Option<String> = None;
+    let mut fixed = Vec::<String>::new();
+    for chunk in chunks {
+        let mut text = match &open {
+            Some(language) => format!("{}{}\n{}", FENCE, language, chunk),
+            None => chunk.clone(),
+        };
+        for (i, _) in chunk.match_indices(FENCE) {
+            open = match open {
+                Some(_) => None,
+                None => Some(chunk[i + FENCE.len()..].chars()
+                    .take_while(|c| c.is_ascii_alphanumeric() || "+-#".contains(*c))
+                    .take(LANGUAGE_LENGTH)
+                    .collect()),
+            };
+        }
+        if open.is_some() {
+            text.push('\n');
+            text.push_str(FENCE);
+        }
+        fixed.push(text);
+    }
+    fixed
+}
+
+/// Split a post up into messages with the mentions at the front of the first one. Spoilers can't run
+/// across messages, so when they're wanted every piece gets its own
+pub fn message_chunks(text: &str, mentions: &str, spoiler: bool, limit: usize) -> Vec<String> {
+    // Leave room for the mentions and the spoiler bars
+    let mut room = limit;
+    if !mentions.is_empty() {
+        room = room.saturating_sub(mentions.chars().count() + 1);
+    }
+    let mut chunks = match spoiler {
+        true => split_message(&escape_spoilers(text), room.saturating_sub(4).max(1)).into_iter()
+            .map(|c| format!("||{}||", c))
+            .collect(),
+        false => split_message(text, room.max(1)),
+    };
+    if !mentions.is_empty() {
+        match chunks.first_mut() {
+            Some(c) => *c = format!("{}\n{}", mentions, c),
+            None => chunks.push(String::from(mentions)),
+        }
+    }
+    chunks
+}
+
+/// Break up any bars in the text so they can't close a spoiler early
+pub fn escape_spoilers(text: &str) -> String {
+    text.replace('|', "|\u{200b}")
+}
+
+// Cut text into bits no longer than the limit, keeping whatever separator we split on
+fn pieces(text: &str, limit: usize, level: usize) -> Vec<String> {
+    if text.chars().count() <= limit {
+        return vec![String::from(text)];
+    }
+    match SEPARATORS.get(level) {
+        Some(sep) => text.split_inclusive(sep)
+            .flat_map(|p| pieces(p, limit, level + 1))
+            .collect(),
+        None => {
+            // One giant word, just chop it
+            let chars: Vec<char> = text.chars().collect();
+            chars.chunks(limit).map(|c| c.iter().collect()).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lengths(chunks: &[String]) -> Vec<usize> {
+        chunks.iter().map(|c| c.chars().count()).collect()
+    }
+
+    #[test]
+    fn short_text_stays_whole() {
+        assert_eq!(split_message("hello there", 20), vec!["hello there"]);
+    }
+
+    #[test]
+    fn exactly_the_limit_is_one_message() {
+        let text = "a".repeat(20);
+        assert_eq!(split_message(&text, 20), vec![text.clone()]);
+        let text = format!("{} b", "a".repeat(19));
+        assert_eq!(split_message(&text, 20), vec!["a".repeat(19), String::from("b")]);
+    }
+
+    #[test]
+    fn prefers_paragraphs_then_words() {
+        let text = "first bit here\n\nsecond bit here";
+        assert_eq!(split_message(text, 20), vec!["first bit here", "second bit here"]);
+        assert_eq!(split_message("one two three four", 9), vec!["one two", "three", "four"]);
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        let text = "é".repeat(30);
+        let chunks = split_message(&text, 10);
+        assert_eq!(lengths(&chunks), vec![10, 10, 10]);
+        assert_eq!(chunks.concat(), text);
+        let text = "日本語 ".repeat(10);
+        assert!(lengths(&split_message(&text, 8)).iter().all(|l| *l <= 8));
+    }
+
+    #[test]
+    fn chops_one_giant_word() {
+        let chunks = split_message(&"x".repeat(25), 10);
+        assert_eq!(lengths(&chunks), vec![10, 10, 5]);
+    }
+
+    #[test]
+    fn code_blocks_are_closed_and_reopened() {
+        let code: Vec<String> = (0..40).map(|i| format!("let x{} = {};", i, i)).collect();
+        let text = format!("Look at this\n```rust\n{}\n```\nNeat", code.join("\n"));
+        let chunks = split_message(&text, 200);
+        assert!(chunks.len() > 1);
+        for chunk in chunks.iter() {
+            assert!(chunk.chars().count() <= 200, "{} is too long", chunk);
+            assert_eq!(chunk.matches(FENCE).count() % 2, 0, "{} leaves a code block open", chunk);
+        }
+        assert!(chunks[1].starts_with("```rust\n"));
+        assert!(chunks.last().unwrap().ends_with("Neat"));
+    }
+
+    #[test]
+    fn spoilers_go_on_every_piece() {
+        let chunks = message_chunks(&"secret word ".repeat(20), "", true, 50);
+        assert!(chunks.len() > 1);
+        for chunk in chunks.iter() {
+            assert!(chunk.starts_with("||") && chunk.ends_with("||"));
+            assert!(chunk.chars().count() <= 50);
+        }
+    }
+
+    #[test]
+    fn bars_in_the_text_cant_end_a_spoiler() {
+        let chunks = message_chunks("this || that", "", true, 50);
+        assert_eq!(chunks.len(), 1);
+        assert!(!chunks[0][2..chunks[0].len() - 2].contains("||"));
+    }
+
+    #[test]
+    fn mentions_go_up_front_outside_the_spoiler() {
+        let chunks = message_chunks(&"word ".repeat(30), "<@&1>", true, 60);
+        assert!(chunks[0].starts_with("<@&1>\n||"));
+        assert!(chunks.iter().all(|c| c.chars().count() <= 60));
+        assert!(!chunks[1].contains("<@&1>"));
+    }
+}
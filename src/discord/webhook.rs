@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use serenity::{
+    http::client::Http,
+    model::channel::Embed,
+    model::id::{MessageId, RoleId},
+    model::webhook::Webhook,
+};
+use tokio::sync::RwLock;
+
+use crate::reddit::{SnifferPost, PostEvent};
+use crate::filter::Filters;
+use crate::pipeline::PostSink;
+use super::{Destination, mention_roles};
+use super::split::{message_chunks, MESSAGE_LENGTH};
+
+/// Just the webhooks, for webhook only mode. New posts are all it does, anything after that
+/// would need the webhook messages' tokens kept around
+pub struct WebhookSink {
+    poster: WebhookPoster,
+    destinations: Vec<Destination>,
+    filters: Filters,
+    dry_run: bool,
+}
+
+impl WebhookSink {
+    pub fn new(destinations: Vec<Destination>, filters: Filters, dry_run: bool) -> WebhookSink {
+        return WebhookSink {
+            poster: WebhookPoster::standalone(),
+            destinations: destinations,
+            filters: filters,
+            dry_run: dry_run,
+        }
+    }
+}
+
+#[serenity::async_trait]
+impl PostSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhooks"
+    }
+
+    async fn deliver(&self, event: PostEvent) -> Result<(), String> {
+        match event {
+            PostEvent::New(message) => {
+                warn!("New sniffer message!:\n{}", message);
+                if self.dry_run {
+                    warn!("Dry run, not posting {} through webhooks", message.id);
+                    return Ok(());
+                }
+                self.poster.post_message(&self.destinations, &self.filters, &message).await;
+            }
+            PostEvent::Edited { after, .. } => {
+                warn!("Sniffer edited post {}, not updating webhook messages", after.id);
+            }
+            PostEvent::Duplicate { original, duplicate } => {
+                warn!("Post {} is a duplicate of {}, not relaying it", duplicate.id, original.id);
+            }
+            PostEvent::Awarded { post, .. } => {
+                warn!("Post {} got awards, not touching webhook messages", post.id);
+            }
+            PostEvent::Deleted(message, removal) => {
+                warn!("Post {} was {}, not touching webhook messages", message.id, removal.describe());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Posts sniffs through discord webhooks, doesn't need a gateway connection or a bot token,
+/// and lets us dress every message up as the reddit author
+#[derive(Clone)]
+pub struct WebhookPoster {
+    http: Arc<Http>,
+    // Looked up once per url, not on every post
+    webhooks: Arc<RwLock<HashMap<String, Webhook>>>,
+}
+
+impl WebhookPoster {
+    pub fn new(http: Arc<Http>) -> WebhookPoster {
+        return WebhookPoster {
+            http: http,
+            webhooks: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Webhook executes are authenticated by the url itself, so we get away with no token
+    pub fn standalone() -> WebhookPoster {
+        WebhookPoster::new(Arc::new(Http::new_with_token("")))
+    }
+
+    /// Send the post to every destination that has a webhook
+    pub async fn post_message(&self, destinations: &[Destination], filters: &Filters, message: &SnifferPost) {
+        for destination in destinations {
+            if destination.webhook.is_none() || !destination.wants(message) || !filters.allows(destination, message) {
+                continue;
+            }
+            if let Err(e) = self.post(destination, message, &[]).await {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// The whole post, one piece after another. Gives back the id of the first message we sent
+    pub async fn post(&self, destination: &Destination, message: &SnifferPost, roles: &[RoleId]) -> Result<Option<MessageId>, String> {
+        let mut first = None;
+        for (i, piece) in WebhookPost::pieces(destination, message, roles).iter().enumerate() {
+            let id = self.execute(destination, message, piece, roles).await?;
+            if i == 0 {
+                first = id;
+            }
+        }
+        Ok(first)
+    }
+
+    /// Send one piece of a post, gives back the id of the message
+    pub async fn execute(&self, destination: &Destination, message: &SnifferPost, piece: &WebhookPost, roles: &[RoleId]) -> Result<Option<MessageId>, String> {
+        let url = match &destination.webhook {
+            Some(u) => u,
+            None => return Err(String::from("Destination doesn't have a webhook")),
+        };
+        let webhook = self.webhook(url).await?;
+
+        // Wait on the message so we know what we sent
+        let result = webhook.execute(&self.http, true, |w| {
+            // Pretend to be the sniffer
+            w.username(format!("/u/{}", message.author));
+            if let Some(icon) = &message.author_icon {
+                w.avatar_url(icon);
+            }
+            w.allowed_mentions(|am| am.empty_parse().roles(roles.to_vec()));
+            if !piece.content.is_empty() {
+                w.content(&piece.content);
+            }
+            if let Some(e) = &piece.embed {
+                w.embeds(vec![e.clone()]);
+            }
+            w
+        }).await;
+        match result {
+            Ok(m) => {
+                warn!("Sent post {} through webhook", message.id);
+                Ok(m.map(|m| m.id))
+            }
+            Err(e) => {
+                // It might've been deleted or had its token reset, look it up fresh next time
+                self.webhooks.write().await.remove(url);
+                Err(String::from(format!("Error executing webhook: {}", e)))
+            }
+        }
+    }
+
+    async fn webhook(&self, url: &str) -> Result<Webhook, String> {
+        if let Some(w) = self.webhooks.read().await.get(url) {
+            return Ok(w.clone());
+        }
+        let webhook = match self.http.get_webhook_from_url(url).await {
+            Ok(w) => w,
+            Err(e) => return Err(String::from(format!("Couldn't get webhook: {}", e))),
+        };
+        self.webhooks.write().await.insert(url.to_string(), webhook.clone());
+        Ok(webhook)
+    }
+}
+
+/// One webhook message's worth of a post
+#[derive(Debug, Clone)]
+pub struct WebhookPost {
+    content: String,
+    embed: Option<Value>,
+}
+
+impl WebhookPost {
+    /// Embeds go out in one go with any mentions, text gets split up like everywhere else
+    pub fn pieces(destination: &Destination, message: &SnifferPost, roles: &[RoleId]) -> Vec<WebhookPost> {
+        let mentions = mention_roles(roles);
+        if destination.embeds() {
+            return vec![WebhookPost {
+                content: mentions,
+                embed: Some(Embed::fake(|e| destination.embed_post(message).discord_embed(e, destination.include_url))),
+            }];
+        }
+        message_chunks(&destination.format_text(message), &mentions, destination.spoilers(message), MESSAGE_LENGTH)
+            .into_iter()
+            .map(|c| WebhookPost {
+                content: c,
+                embed: None,
+            })
+            .collect()
+    }
+}
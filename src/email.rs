@@ -0,0 +1,261 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Deserialize;
+use serenity::async_trait;
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::filter::{FilterConfig, Filters, PostFilter};
+use crate::pipeline::PostSink;
+use crate::reddit::{PostEvent, SnifferPost};
+use crate::retry::with_backoff;
+
+// What rules call it
+pub const SINK_NAME: &str = "email";
+const SEND_ATTEMPTS: u32 = 3;
+const SEND_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Email sniffs out, one at a time or gathered up into a digest
+#[derive(Deserialize, Debug, Clone)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Like "Sniffer <sniffer@example.com>"
+    pub from: String,
+    pub to: Vec<String>,
+    // Send everything since the last one this often, instead of an email per post. The first one goes out
+    // a full interval after startup, and whatever's waiting goes out when we shut down
+    pub digest_minutes: Option<u64>,
+    // These instead of the global filters
+    pub filters: Option<FilterConfig>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // Upgrade a plain connection, what port 587 wants
+    Starttls,
+    // TLS from the start, port 465
+    Tls,
+    // Nothing at all, only for a relay on the same machine
+    None,
+}
+
+impl Default for SmtpTls {
+    fn default() -> Self {
+        SmtpTls::Starttls
+    }
+}
+
+// Everything needed to get an email out, shared with the digest task
+struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Mailer {
+    async fn send(&self, subject: &str, plain: String, html: String) -> Result<(), String> {
+        let mut builder = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let message = builder.multipart(MultiPart::alternative_plain_html(plain, html))
+            .map_err(|e| String::from(format!("Couldn't put the email together: {}", e)))?;
+        with_backoff(&format!("Emailing {}", subject), SEND_ATTEMPTS, SEND_RETRY_DELAY, || self.transport.send(message.clone()))
+            .await
+            .map(|_| ())
+    }
+}
+
+pub struct EmailSink {
+    mailer: Arc<Mailer>,
+    own_filter: Option<PostFilter>,
+    filters: RwLock<Arc<Filters>>,
+    // Posts waiting for the next digest, if we're doing digests
+    pending: Option<Arc<Mutex<Vec<SnifferPost>>>>,
+    dry_run: bool,
+}
+
+impl EmailSink {
+    /// Nothing to do without an [email] section. Starts the digest going if there is one
+    pub fn new(config: &Config) -> Result<Option<EmailSink>, String> {
+        let email = match &config.email {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+        let builder = match email.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&email.smtp_host),
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&email.smtp_host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&email.smtp_host)),
+        };
+        let mut builder = match builder {
+            Ok(b) => b.port(email.smtp_port),
+            Err(e) => return Err(String::from(format!("Couldn't set up smtp to {}: {}", email.smtp_host, e))),
+        };
+        if let (Some(user), Some(password)) = (&email.username, &email.password) {
+            builder = builder.credentials(Credentials::new(user.clone(), password.clone()));
+        }
+        let parse = |address: &str| address.parse::<Mailbox>().map_err(|e| String::from(format!("{} isn't an email address: {}", address, e)));
+        let mailer = Arc::new(Mailer {
+            transport: builder.build(),
+            from: parse(&email.from)?,
+            to: email.to.iter().map(|t| parse(t)).collect::<Result<Vec<Mailbox>, String>>()?,
+        });
+        let own_filter = match &email.filters {
+            Some(f) => Some(PostFilter::new(f)?),
+            None => None,
+        };
+        let pending = match email.digest_minutes {
+            Some(minutes) => {
+                let pending = Arc::new(Mutex::new(Vec::new()));
+                tokio::spawn(send_digests(mailer.clone(), pending.clone(), Duration::from_secs(minutes * 60), config.dry_run));
+                warn!("Emailing a digest to {} addresses every {} minutes", email.to.len(), minutes);
+                Some(pending)
+            }
+            None => {
+                warn!("Emailing posts to {} addresses", email.to.len());
+                None
+            }
+        };
+        return Ok(Some(EmailSink {
+            mailer: mailer,
+            own_filter: own_filter,
+            filters: RwLock::new(Arc::new(Filters::new(&config.filters, &[], &config.rules)?)),
+            pending: pending,
+            dry_run: config.dry_run,
+        }))
+    }
+}
+
+#[async_trait]
+impl PostSink for EmailSink {
+    fn name(&self) -> &str {
+        SINK_NAME
+    }
+
+    // Only new posts, there's no taking an email back
+    async fn deliver(&self, event: PostEvent) -> Result<(), String> {
+        let post = match event {
+            PostEvent::New(p) => p,
+            _ => return Ok(()),
+        };
+        let filters = self.filters.read().await.clone();
+        if !filters.allows_sink(SINK_NAME, self.own_filter.as_ref(), &post) {
+            return Ok(());
+        }
+        if let Some(pending) = &self.pending {
+            debug!("Holding {} for the next digest", post.id);
+            pending.lock().unwrap().push(post);
+            return Ok(());
+        }
+        if self.dry_run {
+            warn!("Dry run, would have emailed {}", post.id);
+            return Ok(());
+        }
+        let subject = format!("{} by /u/{}", post.title, post.author);
+        match self.mailer.send(&subject, plain_text(&post), format!("<html><body>{}</body></html>", html(&post))).await {
+            Ok(_) => {
+                warn!("Emailed {}", post.id);
+                Ok(())
+            }
+            Err(e) => {
+                crate::metrics::error("email_post");
+                Err(e)
+            }
+        }
+    }
+
+    async fn reload(&self, config: &Config) -> Result<(), String> {
+        *self.filters.write().await = Arc::new(Filters::new(&config.filters, &[], &config.rules)?);
+        Ok(())
+    }
+
+    // Don't lose whatever's waiting for the next digest
+    async fn flush(&self) {
+        if let Some(pending) = &self.pending {
+            send_digest(&self.mailer, pending, self.dry_run).await;
+        }
+    }
+}
+
+// Everything that piled up, every so often. This sleeps first, so nothing goes out until a full
+// interval after startup
+async fn send_digests(mailer: Arc<Mailer>, pending: Arc<Mutex<Vec<SnifferPost>>>, every: Duration, dry_run: bool) {
+    loop {
+        tokio::time::sleep(every).await;
+        send_digest(&mailer, &pending, dry_run).await;
+    }
+}
+
+// One digest of whatever's pending. Anything that doesn't go out goes back for the next one
+async fn send_digest(mailer: &Mailer, pending: &Mutex<Vec<SnifferPost>>, dry_run: bool) {
+    let posts = std::mem::take(&mut *pending.lock().unwrap());
+    if posts.is_empty() {
+        return;
+    }
+    if dry_run {
+        warn!("Dry run, would have emailed a digest of {} posts", posts.len());
+        return;
+    }
+    let subject = format!("{} new sniffs", posts.len());
+    let plain = posts.iter().map(plain_text).collect::<Vec<String>>().join("\n\n---\n\n");
+    let html = posts.iter().map(html).collect::<Vec<String>>().join("<hr>");
+    match mailer.send(&subject, plain, format!("<html><body>{}</body></html>", html)).await {
+        Ok(_) => warn!("Emailed a digest of {} posts", posts.len()),
+        Err(e) => {
+            error!("Couldn't email the digest, trying again next time: {}", e);
+            crate::metrics::error("email_post");
+            let mut pending = pending.lock().unwrap();
+            let newer = std::mem::replace(&mut *pending, posts);
+            pending.extend(newer);
+        }
+    }
+}
+
+fn plain_text(post: &SnifferPost) -> String {
+    let mut text = format!("{}\n/u/{} in /r/{}\nhttps://www.reddit.com{}", post.title, post.author, post.subreddit, post.permalink);
+    if let Some(body) = &post.body {
+        text.push_str(&format!("\n\n{}", body));
+    }
+    if let Some(url) = &post.url {
+        text.push_str(&format!("\n\n{}", url));
+    }
+    text
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// One post's worth, the caller wraps it in a page
+fn html(post: &SnifferPost) -> String {
+    let mut html = format!(
+        "<h2><a href=\"https://www.reddit.com{}\">{}</a></h2><p><small>/u/{} in /r/{}{}</small></p>",
+        escape(&post.permalink), escape(&post.title), escape(&post.author), escape(&post.subreddit),
+        if post.nsfw { " · NSFW" } else { "" },
+    );
+    if let Some(body) = &post.body {
+        let paragraphs: Vec<String> = body.split("\n\n").map(|p| format!("<p>{}</p>", escape(p).replace('\n', "<br>"))).collect();
+        html.push_str(&paragraphs.join(""));
+    }
+    if let Some(url) = &post.url {
+        html.push_str(&format!("<p><a href=\"{0}\">{0}</a></p>", escape(url)));
+    }
+    html
+}
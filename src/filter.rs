@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+use crate::discord::Destination;
+use crate::reddit::{SnifferPost, Source};
+
+/// What posts get through, keywords are case insensitive and match anywhere in the title or body.
+/// If there's anything in the include lists a post has to hit one of them, and anything matching
+/// the exclude lists gets dropped no matter what. Flairs match on the flair text or its template id
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FilterConfig {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub include_regex: Vec<String>,
+    #[serde(default)]
+    pub exclude_regex: Vec<String>,
+    // Only posts with one of these link flairs
+    #[serde(default)]
+    pub flairs: Vec<String>,
+    // Only posts by authors with one of these flairs
+    #[serde(default)]
+    pub author_flairs: Vec<String>,
+    // Drop posts with any of these link flairs
+    #[serde(default)]
+    pub exclude_flairs: Vec<String>,
+    // Drop nsfw posts entirely, destinations' nsfw setting decides what happens otherwise
+    #[serde(default)]
+    pub drop_nsfw: bool,
+}
+
+/// Where posts from some sources go. Once there are any rules, a post only goes to a
+/// destination some rule covering it sends it to, whichever of our sources saw it first
+#[derive(Deserialize, Debug, Clone)]
+pub struct WatchRule {
+    // Just for the logs
+    pub name: Option<String>,
+    // Like u/someone, u/someone/comments, r/something, search:query or someone/m/multi
+    pub sources: Vec<String>,
+    // These instead of the destination's filters
+    pub filters: Option<FilterConfig>,
+    // Channels the posts go to, they get a destination with the defaults if they don't have one
+    #[serde(default)]
+    pub destinations: Vec<u64>,
+    // Anywhere else they go that isn't a discord channel, like slack:general
+    #[serde(default)]
+    pub sinks: Vec<String>,
+}
+
+impl WatchRule {
+    pub fn parsed_sources(&self) -> Result<Vec<Source>, String> {
+        let mut sources = Vec::<Source>::new();
+        for s in &self.sources {
+            match Source::parse(s) {
+                Some(source) => sources.push(source),
+                None => return Err(String::from(format!("Rule {} has a source we can't read: {}", self.describe(), s))),
+            }
+        }
+        Ok(sources)
+    }
+
+    fn describe(&self) -> String {
+        match &self.name {
+            Some(n) => n.clone(),
+            None => self.sources.join(", "),
+        }
+    }
+}
+
+// A rule ready to route with
+struct CompiledRule {
+    name: String,
+    sources: Vec<Source>,
+    filter: Option<PostFilter>,
+    destinations: Vec<u64>,
+    sinks: Vec<String>,
+}
+
+impl CompiledRule {
+    fn covers(&self, post: &SnifferPost) -> bool {
+        self.sources.iter().any(|s| s.covers(post))
+    }
+}
+
+pub struct PostFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    include_regex: Vec<Regex>,
+    exclude_regex: Vec<Regex>,
+    flairs: Vec<String>,
+    author_flairs: Vec<String>,
+    exclude_flairs: Vec<String>,
+    drop_nsfw: bool,
+}
+
+// Whether a flair's text or id is in the list
+fn flair_in(list: &[String], text: &Option<String>, id: &Option<String>) -> bool {
+    list.iter().any(|f| {
+        text.as_ref().map_or(false, |t| t.trim().eq_ignore_ascii_case(f)) ||
+            id.as_ref().map_or(false, |i| i == f)
+    })
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    let mut compiled = Vec::<Regex>::new();
+    for p in patterns {
+        match RegexBuilder::new(p).case_insensitive(true).build() {
+            Ok(r) => compiled.push(r),
+            Err(e) => return Err(String::from(format!("Bad filter regex \"{}\": {}", p, e))),
+        }
+    }
+    Ok(compiled)
+}
+
+impl PostFilter {
+    pub fn new(config: &FilterConfig) -> Result<PostFilter, String> {
+        return Ok(PostFilter {
+            include: config.include.iter().map(|k| k.to_lowercase()).collect(),
+            exclude: config.exclude.iter().map(|k| k.to_lowercase()).collect(),
+            include_regex: compile(&config.include_regex)?,
+            exclude_regex: compile(&config.exclude_regex)?,
+            flairs: config.flairs.clone(),
+            author_flairs: config.author_flairs.clone(),
+            exclude_flairs: config.exclude_flairs.clone(),
+            drop_nsfw: config.drop_nsfw,
+        })
+    }
+
+    pub fn allows(&self, post: &SnifferPost) -> bool {
+        if self.drop_nsfw && post.nsfw {
+            return false;
+        }
+        if flair_in(&self.exclude_flairs, &post.flair, &post.flair_id) {
+            return false;
+        }
+        if !self.flairs.is_empty() && !flair_in(&self.flairs, &post.flair, &post.flair_id) {
+            return false;
+        }
+        if !self.author_flairs.is_empty() && !flair_in(&self.author_flairs, &post.author_flair, &post.author_flair_id) {
+            return false;
+        }
+
+        let text = match &post.body {
+            Some(b) => format!("{}\n{}", post.title, b),
+            None => post.title.clone(),
+        };
+        let lower = text.to_lowercase();
+
+        if self.exclude.iter().any(|k| lower.contains(k)) || self.exclude_regex.iter().any(|r| r.is_match(&text)) {
+            return false;
+        }
+        // Nothing to include means everything is
+        if self.include.is_empty() && self.include_regex.is_empty() {
+            return true;
+        }
+        self.include.iter().any(|k| lower.contains(k)) || self.include_regex.iter().any(|r| r.is_match(&text))
+    }
+}
+
+/// The global filter, plus whatever destinations have instead of it
+pub struct Filters {
+    global: PostFilter,
+    per_channel: HashMap<u64, PostFilter>,
+    rules: Vec<CompiledRule>,
+}
+
+impl Filters {
+    pub fn new(global: &FilterConfig, destinations: &[Destination], rules: &[WatchRule]) -> Result<Filters, String> {
+        let mut per_channel = HashMap::new();
+        for destination in destinations {
+            if let Some(config) = &destination.filters {
+                per_channel.insert(destination.channel, PostFilter::new(config)?);
+            }
+        }
+        let mut compiled = Vec::<CompiledRule>::new();
+        for rule in rules {
+            compiled.push(CompiledRule {
+                name: rule.describe(),
+                sources: rule.parsed_sources()?,
+                filter: match &rule.filters {
+                    Some(f) => Some(PostFilter::new(f)?),
+                    None => None,
+                },
+                destinations: rule.destinations.clone(),
+                sinks: rule.sinks.clone(),
+            });
+        }
+        return Ok(Filters {
+            global: PostFilter::new(global)?,
+            per_channel: per_channel,
+            rules: compiled,
+        })
+    }
+
+    /// Whether a post should go out to this destination
+    pub fn allows(&self, destination: &Destination, post: &SnifferPost) -> bool {
+        let filter = self.per_channel.get(&destination.channel).unwrap_or(&self.global);
+        if !self.rules.is_empty() {
+            // Any rule covering this post and channel will do
+            let rule = self.rules.iter()
+                .filter(|r| r.destinations.contains(&destination.channel) && r.covers(post))
+                .find(|r| r.filter.as_ref().unwrap_or(filter).allows(post));
+            match rule {
+                Some(r) => debug!("Post {} goes to channel {} by rule {}", post.id, destination.channel, r.name),
+                None => debug!("No rule sends post {} to channel {}", post.id, destination.channel),
+            }
+            return rule.is_some();
+        }
+        let allowed = filter.allows(post);
+        if !allowed {
+            debug!("Post {} filtered out of channel {}", post.id, destination.channel);
+        }
+        allowed
+    }
+
+    /// Same thing for anything that isn't a discord channel, named like slack:general. Their own
+    /// filter goes instead of the global one, like a destination's does
+    pub fn allows_sink(&self, sink: &str, own: Option<&PostFilter>, post: &SnifferPost) -> bool {
+        let filter = own.unwrap_or(&self.global);
+        if !self.rules.is_empty() {
+            let rule = self.rules.iter()
+                .filter(|r| r.sinks.iter().any(|s| s == sink) && r.covers(post))
+                .find(|r| r.filter.as_ref().unwrap_or(filter).allows(post));
+            match rule {
+                Some(r) => debug!("Post {} goes to {} by rule {}", post.id, sink, r.name),
+                None => debug!("No rule sends post {} to {}", post.id, sink),
+            }
+            return rule.is_some();
+        }
+        let allowed = filter.allows(post);
+        if !allowed {
+            debug!("Post {} filtered out of {}", post.id, sink);
+        }
+        allowed
+    }
+}
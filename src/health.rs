@@ -0,0 +1,66 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use serde::Deserialize;
+
+use crate::discord::DiscordBot;
+
+/// Liveness and readiness checks over http, for whatever's keeping the bot running
+#[derive(Deserialize, Debug, Clone)]
+pub struct HealthConfig {
+    #[serde(default = "default_listen")]
+    pub listen: String,
+}
+
+fn default_listen() -> String {
+    String::from("0.0.0.0:8080")
+}
+
+/// Answer /healthz and /readyz until we're stopped. Ready means every shard is connected and,
+/// if we're polling, reddit answered within max_poll_age
+pub async fn serve(config: HealthConfig, bot: DiscordBot, max_poll_age: Option<Duration>) {
+    let address = match config.listen.parse::<SocketAddr>() {
+        Ok(a) => a,
+        Err(e) => {
+            error!("Can't serve health checks on {}: {}", config.listen, e);
+            return;
+        }
+    };
+    let server = match Server::try_bind(&address) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Can't serve health checks on {}: {}", address, e);
+            return;
+        }
+    };
+    warn!("Serving health checks on http://{}/healthz and /readyz", address);
+    let make_service = make_service_fn(move |_| {
+        let bot = bot.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| respond(request, bot.clone(), max_poll_age)))
+        }
+    });
+    if let Err(e) = server.serve(make_service).await {
+        error!("Health check server fell over: {}", e);
+    }
+}
+
+async fn respond(request: Request<Body>, bot: DiscordBot, max_poll_age: Option<Duration>) -> Result<Response<Body>, Infallible> {
+    let (status, text) = match request.uri().path() {
+        // If we can answer at all we're alive
+        "/healthz" => (StatusCode::OK, String::from("ok")),
+        "/readyz" => match bot.readiness(max_poll_age).await {
+            Ok(_) => (StatusCode::OK, String::from("ready")),
+            Err(problems) => (StatusCode::SERVICE_UNAVAILABLE, problems.join("\n")),
+        },
+        _ => (StatusCode::NOT_FOUND, String::from("Try /healthz or /readyz")),
+    };
+    let mut response = Response::new(Body::from(text));
+    *response.status_mut() = status;
+    Ok(response)
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serenity::async_trait;
+use sha2::Sha256;
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::filter::{FilterConfig, Filters, PostFilter};
+use crate::pipeline::PostSink;
+use crate::reddit::PostEvent;
+use crate::retry::with_backoff;
+
+const SEND_ATTEMPTS: u32 = 4;
+const SEND_RETRY_DELAY: Duration = Duration::from_secs(2);
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+// What the receiving end checks, the signature is over "<timestamp>.<body>" so old
+// requests can't be replayed as new ones
+pub const SIGNATURE_HEADER: &str = "X-Sniffer-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Sniffer-Timestamp";
+pub const EVENT_HEADER: &str = "X-Sniffer-Event";
+
+/// Somewhere of the user's own that gets every post POSTed to it as json
+#[derive(Deserialize, Debug, Clone)]
+pub struct HookConfig {
+    // What rules send posts to, as hook:<name>
+    pub name: String,
+    pub url: String,
+    // Signs every request with HMAC-SHA256 when it's set
+    pub secret: Option<String>,
+    // Anything else the other end wants, like an Authorization header
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // Edits, deletions and the rest as well as new posts
+    #[serde(default)]
+    pub all_events: bool,
+    // These instead of the global filters
+    pub filters: Option<FilterConfig>,
+}
+
+impl HookConfig {
+    pub fn sink_name(&self) -> String {
+        format!("hook:{}", self.name)
+    }
+}
+
+pub struct HookSink {
+    client: reqwest::Client,
+    hooks: Vec<(HookConfig, Option<PostFilter>)>,
+    filters: RwLock<Arc<Filters>>,
+    dry_run: bool,
+}
+
+impl HookSink {
+    /// Nothing to do without any [[hooks]]
+    pub fn new(config: &Config) -> Result<Option<HookSink>, String> {
+        if config.hooks.is_empty() {
+            return Ok(None);
+        }
+        let mut hooks = Vec::new();
+        for h in &config.hooks {
+            let filter = match &h.filters {
+                Some(f) => Some(PostFilter::new(f)?),
+                None => None,
+            };
+            hooks.push((h.clone(), filter));
+        }
+        let client = reqwest::Client::builder()
+            .user_agent(crate::reddit::APP_USER_AGENT)
+            .timeout(SEND_TIMEOUT)
+            .build()
+            .map_err(|e| String::from(format!("Couldn't make an http client: {}", e)))?;
+        warn!("Posting to {} hooks", hooks.len());
+        return Ok(Some(HookSink {
+            client: client,
+            hooks: hooks,
+            filters: RwLock::new(Arc::new(Filters::new(&config.filters, &[], &config.rules)?)),
+            dry_run: config.dry_run,
+        }))
+    }
+
+    async fn send(&self, hook: &HookConfig, kind: &str, body: &str) -> Result<(), String> {
+        let mut request = self.client.post(&hook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, kind);
+        for (name, value) in &hook.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(secret) = &hook.secret {
+            // Fresh every attempt, so a retry doesn't look stale
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, timestamp, body)));
+        }
+        match request.body(String::from(body)).send().await.and_then(|r| r.error_for_status()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(String::from(format!("it said no: {}", e))),
+        }
+    }
+}
+
+#[async_trait]
+impl PostSink for HookSink {
+    fn name(&self) -> &str {
+        "hooks"
+    }
+
+    async fn deliver(&self, event: PostEvent) -> Result<(), String> {
+        let (kind, payload) = payload(&event);
+        let body = payload.to_string();
+        let post = event.post();
+        let filters = self.filters.read().await.clone();
+        let mut failed = Vec::<String>::new();
+        for (hook, own) in &self.hooks {
+            let name = hook.sink_name();
+            if (kind != "new" && !hook.all_events) || !filters.allows_sink(&name, own.as_ref(), post) {
+                continue;
+            }
+            if self.dry_run {
+                warn!("Dry run, would have sent {} {} to {}", kind, post.id, name);
+                continue;
+            }
+            let result = with_backoff(&format!("Sending {} to {}", post.id, name), SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                self.send(hook, kind, &body)
+            }).await;
+            match result {
+                Ok(_) => debug!("Sent {} {} to {}", kind, post.id, name),
+                Err(e) => {
+                    crate::metrics::error("hook_post");
+                    failed.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+        match (failed.is_empty(), kind) {
+            (true, _) => Ok(()),
+            (false, "new") => Err(failed.join(", ")),
+            (false, _) => {
+                error!("Couldn't send {} {} everywhere: {}", kind, post.id, failed.join(", "));
+                Ok(())
+            }
+        }
+    }
+
+    async fn reload(&self, config: &Config) -> Result<(), String> {
+        *self.filters.write().await = Arc::new(Filters::new(&config.filters, &[], &config.rules)?);
+        Ok(())
+    }
+}
+
+// Hex HMAC-SHA256 of the timestamp and body
+fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    // Keys of any length are fine for HMAC, this can't fail
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// What kind of event it was and the json that goes out for it, the post is a SnifferPost
+/// exactly as we have it
+fn payload(event: &PostEvent) -> (&'static str, serde_json::Value) {
+    match event {
+        PostEvent::New(post) => ("new", serde_json::json!({ "event": "new", "post": post })),
+        PostEvent::Edited { before, after } => ("edited", serde_json::json!({ "event": "edited", "post": after, "before": before })),
+        PostEvent::Awarded { post, new_awards } => ("awarded", serde_json::json!({ "event": "awarded", "post": post, "new_awards": new_awards })),
+        PostEvent::Duplicate { original, duplicate } => ("duplicate", serde_json::json!({ "event": "duplicate", "post": original, "duplicate": duplicate })),
+        PostEvent::Deleted(post, removal) => ("deleted", serde_json::json!({ "event": "deleted", "post": post, "removal": removal })),
+    }
+}
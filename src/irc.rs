@@ -0,0 +1,390 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use serde::Deserialize;
+use serenity::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, RwLock};
+
+use crate::config::Config;
+use crate::filter::{FilterConfig, Filters, PostFilter};
+use crate::pipeline::PostSink;
+use crate::reddit::{PostEvent, SnifferPost};
+
+// What rules call it
+pub const SINK_NAME: &str = "irc";
+// Lines are 512 bytes with the CRLF, and the server sticks our nick!user@host on the front of
+// what everyone else sees. This leaves plenty of room for that and the PRIVMSG #channel :
+const LINE_BYTES: usize = 400;
+// Most servers kick anyone sending much faster than this
+const LINE_DELAY: Duration = Duration::from_millis(700);
+// Posts waiting to go out, more than this and new ones get dropped while we're disconnected
+const QUEUE_SIZE: usize = 100;
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Relay to an IRC channel, over TLS and with SASL if the network wants it
+#[derive(Deserialize, Debug, Clone)]
+pub struct IrcConfig {
+    // Like irc.libera.chat
+    pub server: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+    pub nick: String,
+    // Like #sniffer
+    pub channel: String,
+    // SASL PLAIN, leave them out if the network doesn't need it
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    // Body lines a post gets at most, they go out slow so don't make it too many
+    #[serde(default = "default_max_lines")]
+    pub max_lines: usize,
+    // These instead of the global filters
+    pub filters: Option<FilterConfig>,
+}
+
+fn default_port() -> u16 {
+    6697
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+fn default_max_lines() -> usize {
+    4
+}
+
+pub struct IrcSink {
+    sender: mpsc::Sender<Vec<String>>,
+    max_lines: usize,
+    own_filter: Option<PostFilter>,
+    filters: RwLock<Arc<Filters>>,
+    dry_run: bool,
+}
+
+impl IrcSink {
+    /// Nothing to do without an [irc] section. Starts connecting in the background if there is one
+    pub fn new(config: &Config) -> Result<Option<IrcSink>, String> {
+        let irc = match &config.irc {
+            Some(i) => i.clone(),
+            None => return Ok(None),
+        };
+        let own_filter = match &irc.filters {
+            Some(f) => Some(PostFilter::new(f)?),
+            None => None,
+        };
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        let max_lines = irc.max_lines;
+        if !config.dry_run {
+            tokio::spawn(connect_forever(irc, receiver));
+        }
+        return Ok(Some(IrcSink {
+            sender: sender,
+            max_lines: max_lines,
+            own_filter: own_filter,
+            filters: RwLock::new(Arc::new(Filters::new(&config.filters, &[], &config.rules)?)),
+            dry_run: config.dry_run,
+        }))
+    }
+}
+
+#[async_trait]
+impl PostSink for IrcSink {
+    fn name(&self) -> &str {
+        SINK_NAME
+    }
+
+    // Only new posts, there's no editing what's been said on IRC
+    async fn deliver(&self, event: PostEvent) -> Result<(), String> {
+        let post = match event {
+            PostEvent::New(p) => p,
+            _ => return Ok(()),
+        };
+        let filters = self.filters.read().await.clone();
+        if !filters.allows_sink(SINK_NAME, self.own_filter.as_ref(), &post) {
+            return Ok(());
+        }
+        if self.dry_run {
+            warn!("Dry run, would have sent {} to IRC", post.id);
+            return Ok(());
+        }
+        // Don't hold the loop up while we're disconnected, it goes out once we're back
+        self.sender.try_send(lines(&post, self.max_lines)).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => String::from("Too much waiting to go out to IRC, dropped it"),
+            mpsc::error::TrySendError::Closed(_) => String::from("The IRC connection isn't running"),
+        })
+    }
+
+    async fn reload(&self, config: &Config) -> Result<(), String> {
+        *self.filters.write().await = Arc::new(Filters::new(&config.filters, &[], &config.rules)?);
+        Ok(())
+    }
+}
+
+// TLS or not, it's all the same to us once it's connected
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+struct Connection {
+    reader: Lines<BufReader<ReadHalf<Box<dyn Stream>>>>,
+    writer: WriteHalf<Box<dyn Stream>>,
+    // Whatever the server let us have, it might have underscores on it
+    nick: String,
+}
+
+impl Connection {
+    async fn send(&mut self, line: &str) -> Result<(), String> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes()).await
+            .map_err(|e| String::from(format!("Couldn't write to the server: {}", e)))
+    }
+
+    async fn line(&mut self) -> Result<String, String> {
+        match self.reader.next_line().await {
+            Ok(Some(l)) => Ok(l),
+            Ok(None) => Err(String::from("The server hung up")),
+            Err(e) => Err(String::from(format!("Couldn't read from the server: {}", e))),
+        }
+    }
+}
+
+// Stay connected for good, backing off between tries when the network's having a bad time
+async fn connect_forever(config: IrcConfig, mut posts: mpsc::Receiver<Vec<String>>) {
+    let mut delay = Duration::from_secs(5);
+    loop {
+        match connect(&config).await {
+            Ok(mut conn) => {
+                warn!("Joined {} on {}", config.channel, config.server);
+                delay = Duration::from_secs(5);
+                match relay(&config, &mut conn, &mut posts).await {
+                    Ok(_) => return,
+                    Err(e) => error!("Lost IRC: {}", e),
+                }
+            }
+            Err(e) => error!("Couldn't connect to {}: {}", config.server, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = std::cmp::min(delay * 2, MAX_RECONNECT_DELAY);
+    }
+}
+
+async fn connect(config: &IrcConfig) -> Result<Connection, String> {
+    let tcp = TcpStream::connect((config.server.as_str(), config.port)).await
+        .map_err(|e| String::from(format!("Couldn't reach {}:{}: {}", config.server, config.port, e)))?;
+    let stream: Box<dyn Stream> = match config.tls {
+        true => {
+            let connector = tokio_native_tls::native_tls::TlsConnector::new()
+                .map_err(|e| String::from(format!("Couldn't set up TLS: {}", e)))?;
+            let tls = tokio_native_tls::TlsConnector::from(connector).connect(&config.server, tcp).await
+                .map_err(|e| String::from(format!("TLS with {} failed: {}", config.server, e)))?;
+            Box::new(tls)
+        }
+        false => Box::new(tcp),
+    };
+    let (reader, writer) = tokio::io::split(stream);
+    let mut conn = Connection {
+        reader: BufReader::new(reader).lines(),
+        writer: writer,
+        nick: config.nick.clone(),
+    };
+    conn.nick = match tokio::time::timeout(REGISTER_TIMEOUT, register(config, &mut conn)).await {
+        Ok(result) => result?,
+        Err(_) => return Err(String::from("The server never let us in")),
+    };
+    conn.send(&format!("JOIN {}", config.channel)).await?;
+    Ok(conn)
+}
+
+// Get through CAP, SASL and NICK/USER until the server says welcome, giving back the nick we got
+async fn register(config: &IrcConfig, conn: &mut Connection) -> Result<String, String> {
+    let sasl = match (&config.sasl_username, &config.sasl_password) {
+        (Some(u), Some(p)) => Some((u, p)),
+        _ => None,
+    };
+    if sasl.is_some() {
+        conn.send("CAP REQ :sasl").await?;
+    }
+    let mut nick = config.nick.clone();
+    conn.send(&format!("NICK {}", nick)).await?;
+    conn.send(&format!("USER {} 0 * :sniffer", config.nick)).await?;
+    loop {
+        let line = conn.line().await?;
+        let (command, params) = parse(&line);
+        match (command, sasl) {
+            ("PING", _) => conn.send(&format!("PONG :{}", params.last().unwrap_or(&""))).await?,
+            ("CAP", Some(_)) if params.get(1) == Some(&"ACK") => conn.send("AUTHENTICATE PLAIN").await?,
+            ("CAP", Some(_)) if params.get(1) == Some(&"NAK") => return Err(String::from("The server doesn't do SASL")),
+            ("AUTHENTICATE", Some((user, password))) if params.first() == Some(&"+") => {
+                let token = base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", user, password));
+                conn.send(&format!("AUTHENTICATE {}", token)).await?;
+            }
+            // Logged in
+            ("903", _) => conn.send("CAP END").await?,
+            ("904", _) | ("905", _) | ("906", _) => return Err(String::from(format!("SASL login failed: {}", line))),
+            // Nick's taken, try with an underscore on the end
+            ("433", _) => {
+                nick.push('_');
+                conn.send(&format!("NICK {}", nick)).await?;
+            }
+            ("001", _) => return Ok(nick),
+            ("ERROR", _) => return Err(String::from(format!("The server refused us: {}", line))),
+            _ => {}
+        }
+    }
+}
+
+// Send posts as they come in, and answer pings so we don't get dropped. Gives back Ok once
+// nobody's sending us posts anymore
+async fn relay(config: &IrcConfig, conn: &mut Connection, posts: &mut mpsc::Receiver<Vec<String>>) -> Result<(), String> {
+    loop {
+        tokio::select! {
+            post = posts.recv() => {
+                let post = match post {
+                    Some(p) => p,
+                    None => {
+                        let _ = conn.send("QUIT :bye").await;
+                        return Ok(());
+                    }
+                };
+                for line in post {
+                    conn.send(&format!("PRIVMSG {} :{}", config.channel, line)).await?;
+                    tokio::time::sleep(LINE_DELAY).await;
+                }
+            }
+            line = conn.line() => {
+                let line = line?;
+                let (command, params) = parse(&line);
+                match command {
+                    "PING" => conn.send(&format!("PONG :{}", params.last().unwrap_or(&""))).await?,
+                    // Kicked, go back in. Anyone else getting kicked is none of our business
+                    "KICK" if params.first() == Some(&config.channel.as_str()) && params.get(1).map_or(false, |n| n.eq_ignore_ascii_case(&conn.nick)) => {
+                        warn!("Kicked from {}: {}", config.channel, line);
+                        conn.send(&format!("JOIN {}", config.channel)).await?;
+                    }
+                    "ERROR" => return Err(String::from(format!("The server dropped us: {}", line))),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+// The command and its parameters, the trailing one with spaces and all. Whoever sent it and
+// any tags don't matter to us
+fn parse(line: &str) -> (&str, Vec<&str>) {
+    let mut rest = line.trim_end();
+    if rest.starts_with('@') {
+        rest = rest.split_once(' ').map_or("", |(_, r)| r);
+    }
+    if rest.starts_with(':') {
+        rest = rest.split_once(' ').map_or("", |(_, r)| r);
+    }
+    let (middle, trailing) = match rest.split_once(" :") {
+        Some((m, t)) => (m, Some(t)),
+        None => (rest, None),
+    };
+    let mut words = middle.split(' ').filter(|w| !w.is_empty());
+    let command = words.next().unwrap_or("");
+    let mut params: Vec<&str> = words.collect();
+    if let Some(t) = trailing {
+        params.push(t);
+    }
+    (command, params)
+}
+
+/// How a post goes out, bold title first, a few lines of body and the link last
+fn lines(post: &SnifferPost, max_lines: usize) -> Vec<String> {
+    let nsfw = if post.nsfw { " [NSFW]" } else { "" };
+    let title = format!("\x02{}\x02{} · /u/{} in /r/{}", clean(&post.title), nsfw, clean(&post.author), clean(&post.subreddit));
+    let mut out = split(&title, LINE_BYTES);
+    if let Some(body) = &post.body {
+        let body: Vec<String> = body.lines()
+            .map(clean)
+            .filter(|l| !l.trim().is_empty())
+            .flat_map(|l| split(l.trim(), LINE_BYTES))
+            .collect();
+        let cut = body.len() > max_lines;
+        out.extend(body.into_iter().take(max_lines));
+        if cut {
+            out.push(String::from("…"));
+        }
+    }
+    out.push(format!("https://www.reddit.com{}", post.permalink));
+    out
+}
+
+// No line breaks sneaking in extra commands, and no colours or bold of the post's own
+fn clean(text: &str) -> String {
+    text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+// Break text into lines that fit, on spaces where we can and mid-word where we can't,
+// never in the middle of a character
+fn split(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut out = Vec::<String>::new();
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut end = max_bytes;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let cut = match rest[..end].rfind(' ') {
+            Some(space) if space > 0 => space,
+            _ => end,
+        };
+        out.push(String::from(&rest[..cut]));
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        out.push(String::from(rest));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_prefixes_tags_and_trailing() {
+        assert_eq!(parse("PING :irc.example.com"), ("PING", vec!["irc.example.com"]));
+        assert_eq!(
+            parse(":op!op@host KICK #sniffer sniffer :go away now\r"),
+            ("KICK", vec!["#sniffer", "sniffer", "go away now"]),
+        );
+        assert_eq!(
+            parse("@time=2024-01-01T00:00:00Z :server 433 * sniffer :Nickname is already in use"),
+            ("433", vec!["*", "sniffer", "Nickname is already in use"]),
+        );
+        assert_eq!(parse("CAP * ACK sasl"), ("CAP", vec!["*", "ACK", "sasl"]));
+        assert_eq!(parse(""), ("", vec![]));
+    }
+
+    #[test]
+    fn short_lines_stay_whole() {
+        assert_eq!(split("hello there", 11), vec!["hello there"]);
+        assert_eq!(split("hello there", 10), vec!["hello", "there"]);
+    }
+
+    #[test]
+    fn never_splits_a_character() {
+        let text = "é".repeat(10);
+        let lines = split(&text, 5);
+        assert!(lines.iter().all(|l| l.len() <= 5));
+        assert_eq!(lines.concat(), text);
+    }
+
+    #[test]
+    fn chops_one_giant_word() {
+        assert_eq!(split(&"x".repeat(25), 10), vec!["x".repeat(10), "x".repeat(10), "x".repeat(5)]);
+    }
+
+    #[test]
+    fn control_characters_dont_get_through() {
+        assert_eq!(clean("title\r\nQUIT :bye\0\x03"), "title  QUIT :bye  ");
+    }
+}
@@ -0,0 +1,200 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+
+use crate::config::LoggingConfig;
+use crate::reporting;
+
+lazy_static! {
+    // Lets the filter get swapped out while we're running, and what it's set to now
+    static ref FILTER: Mutex<Option<(reload::Handle<EnvFilter, Registry>, String)>> = Mutex::new(None);
+}
+
+/// Start a fresh log file every so often, on top of rolling when it gets too big
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    fn seconds(&self) -> u64 {
+        match self {
+            LogRotation::Hourly => 60 * 60,
+            LogRotation::Daily => 24 * 60 * 60,
+        }
+    }
+
+    // Which hour or day since the epoch a time falls in, in UTC
+    fn period(&self, time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / self.seconds())
+    }
+}
+
+/// A log file that rolls over to .1, .2 and so on once it gets too big or too old, the
+/// oldest get dropped
+struct RollingFile {
+    path: String,
+    // 0 for no limit
+    max_bytes: u64,
+    keep: u32,
+    rotation: Option<LogRotation>,
+    max_age: Option<Duration>,
+    file: File,
+    written: u64,
+    period: u64,
+}
+
+impl RollingFile {
+    fn open(path: &str, max_bytes: u64, keep: u32, rotation: Option<LogRotation>, max_age: Option<Duration>) -> io::Result<RollingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        // Going off when it was last written, so a restart the next day still rolls yesterday's log
+        let period = match rotation {
+            Some(r) => r.period(metadata.modified().unwrap_or_else(|_| SystemTime::now())),
+            None => 0,
+        };
+        let mut rolling = RollingFile {
+            path: String::from(path),
+            max_bytes: max_bytes,
+            keep: keep,
+            rotation: rotation,
+            max_age: max_age,
+            file: file,
+            written: metadata.len(),
+            period: period,
+        };
+        rolling.clean_up();
+        return Ok(rolling)
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for i in (1..self.keep).rev() {
+            // Gaps are fine, not every number has been used yet
+            let _ = fs::rename(format!("{}.{}", self.path, i), format!("{}.{}", self.path, i + 1));
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.written = 0;
+        self.clean_up();
+        Ok(())
+    }
+
+    // Drop rolled logs past the retention, and any left over from a bigger roll_count
+    fn clean_up(&self) {
+        let mut i = 1;
+        loop {
+            let old = format!("{}.{}", self.path, i);
+            let modified = match fs::metadata(&old).and_then(|m| m.modified()) {
+                Ok(m) => m,
+                // Not quite every number has been used, look a little past the end
+                Err(_) if i <= self.keep => {
+                    i += 1;
+                    continue;
+                }
+                Err(_) => break,
+            };
+            let expired = self.max_age.map_or(false, |age| modified.elapsed().map_or(false, |e| e > age));
+            if i > self.keep || expired {
+                // Nowhere to log this to, we're the log
+                let _ = fs::remove_file(&old);
+            }
+            i += 1;
+        }
+    }
+
+    fn due(&self, incoming: usize) -> bool {
+        if self.written == 0 {
+            return false;
+        }
+        if self.max_bytes > 0 && self.written + incoming as u64 > self.max_bytes {
+            return true;
+        }
+        match self.rotation {
+            Some(r) => r.period(SystemTime::now()) != self.period,
+            None => false,
+        }
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.due(buf.len()) {
+            self.roll()?;
+        }
+        if let Some(r) = self.rotation {
+            self.period = r.period(SystemTime::now());
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Log to the console and, unless it's turned off, a rolling file. Hang on to what this gives
+/// back, the file stops getting written once it's dropped
+pub fn init(config: &LoggingConfig) -> Result<Option<WorkerGuard>, String> {
+    let (filter, handle) = reload::Layer::new(filter(&config.level)?);
+    *FILTER.lock().unwrap() = Some((handle, config.level.clone()));
+    if !config.file {
+        return match tracing_subscriber::registry().with(filter).with(fmt::layer()).with(reporting::layer()).try_init() {
+            Ok(_) => Ok(None),
+            Err(e) => Err(String::from(format!("Couldn't set up logging: {}", e))),
+        };
+    }
+    let max_age = config.max_age_days.map(|d| Duration::from_secs(d * 24 * 60 * 60));
+    let file = match RollingFile::open(&config.path, config.size * 1024 * 1024, config.roll_count, config.rotate, max_age) {
+        Ok(f) => f,
+        Err(e) => return Err(String::from(format!("Couldn't open the log file {}: {}", config.path, e))),
+    };
+    let (writer, guard) = tracing_appender::non_blocking(file);
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(fmt::layer().with_ansi(false).with_writer(writer))
+        .with(reporting::layer())
+        .try_init();
+    match result {
+        Ok(_) => Ok(Some(guard)),
+        Err(e) => Err(String::from(format!("Couldn't set up logging: {}", e))),
+    }
+}
+
+/// Either a plain level like warn, or per module like sniffer=debug,serenity=warn
+pub fn filter(level: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(level).map_err(|e| String::from(format!("{} isn't a log level: {}", level, e)))
+}
+
+/// The filter we're logging with right now
+pub fn level() -> Option<String> {
+    FILTER.lock().unwrap().as_ref().map(|(_, level)| level.clone())
+}
+
+/// Log with a different filter from now on, like sniffer::reddit=debug while chasing a missed post
+pub fn set_level(level: &str) -> Result<(), String> {
+    let new = filter(level)?;
+    let mut current = FILTER.lock().unwrap();
+    let (handle, old) = match current.as_mut() {
+        Some(c) => c,
+        None => return Err(String::from("Logging isn't set up yet")),
+    };
+    if let Err(e) = handle.reload(new) {
+        return Err(String::from(format!("Couldn't change the log level: {}", e)));
+    }
+    warn!("Log level changed from {} to {}", old, level);
+    *old = String::from(level);
+    Ok(())
+}
@@ -1,160 +1,1052 @@
-#[macro_use]
-extern crate lazy_static;
-
-use tokio::{
-    signal,
-    time::sleep,
-    select,
-};
-
-use std::env;
-use std::time::Duration;
-
-#[macro_use]
-extern crate log;
-use simple_log::LogConfigBuilder;
-use serde::Deserialize;
-use std::fs::OpenOptions;
-
-mod reddit;
-mod discord;
-mod audio;
-mod commands;
-
-#[derive(Deserialize, Debug, Clone)]
-pub struct Secrets {
-    bot_token: String,
-    guild_id: u64,
-    main_channel: u64,
-    audio_channel: u64,
-    test_channel: u64,
-    archive_channel: u64,
-    sniffer: String
-}
-
-#[tokio::main]
-async fn main() {
-
-    let args: Vec<String> = env::args().collect();
-    let mut will_sniff = true;
-    println!("args len {}", args.len());
-    if args.len() > 1 { // 1 is the invocation
-        will_sniff = false;
-    }
-
-    // Create our log file
-    let config = LogConfigBuilder::builder()
-        .path("./sniffer_log.txt")
-        .size(500)
-        .roll_count(10)
-        .level("warn")
-        .output_file()
-        .output_console()
-        .build();
-
-    simple_log::new(config).expect("Error building log file");
-
-    // Load our secrets
-    let file = OpenOptions::new().read(true).open("secrets.yaml").expect("Couldn't load secrets file, exiting");
-    warn!("Secrets loaded");
-
-    let secrets: Secrets = serde_yaml::from_reader(file).expect("Serde error deserializing secrets");
-    debug!("{:?}", secrets.clone());
-
-
-    let mut discord_bot = discord::DiscordBot::new(secrets.clone()).await;
-    discord_bot.start_shards(1).await;
-    
-
-    // Clone discord bot to use in a thread
-    let discord_bot_clone = discord_bot.clone();
-    // Run in a loop to wait for the sniffer to strike again
-    let mut run_token = None;
-    if will_sniff {
-        // Create our api interfaces
-        let mut reddit = reddit::RedditScraper::new(secrets.sniffer.clone());
-        run_token = Some(tokio::spawn(async move {
-            warn!("Starting scraper thread");
-            loop {
-                // Check every X seconds
-                sleep(Duration::from_secs(45)).await;
-                match reddit.update() {
-                    Ok(message_opt) => {
-                        match message_opt {
-                            Some(messages) => {
-                                warn!("Got {} new messages", messages.len());
-                                //let lock = discord_bot_clone.read().await;
-                                for message in messages {
-                                    warn!("New sniffer message!:\n{}", message);
-                                    //lock.post_message(message).await;
-                                    discord_bot_clone.post_message(message).await;
-                                }    
-                            },
-                            None => {
-                                debug!("No new sniffer message");
-                            },
-                        }
-                    }
-                    Err(error) => {
-                        error!("Encountered an error\n{}\nskipping this loop", error);
-                    }
-                }
-            }
-        }));
-    }
-    
-
-    // Clone discord bot to use in a thread
-    let discord_bot_clone = discord_bot.clone();
-    // uggo but whatevs
-    let mut future_wait = None;
-    if let Some(token) = run_token {
-        future_wait = Some(tokio::spawn(async move {
-            select! {
-                _ = wait_token(token) => {
-                    warn!("Bot thread stopped")
-                }
-                _ = wait_sigint() => {
-                    warn!("Got SIGINT");
-                    // Kill our shards
-                    //discord_bot_clone.write().await.stop_shards().await;
-                    discord_bot_clone.shutdown().await;
-                }
-            };
-        }));
-    }
-    else {
-        future_wait = Some(tokio::spawn(async move {
-            select! {
-                _ = wait_sigint() => {
-                    warn!("Got SIGINT");
-                    // Kill our shards
-                    //discord_bot_clone.write().await.stop_shards().await;
-                    discord_bot_clone.shutdown().await;
-                }
-            }
-        }));
-    }
-    
-
-
-    //discord_bot.clone().read().await.print_shard_info().await;
-    discord_bot.print_shard_info().await;
-
-    println!("Ctrl-C to exit...");
-    
-    
-    if let Some(future) = future_wait {
-        future.await.expect("failed to wait for run token");
-    }
-    
-    println!("Gooby!");
-
-}
-
-async fn wait_token<T>(handle: tokio::task::JoinHandle<T>) {
-    handle.await.unwrap();
-}
-async fn wait_sigint() {
-    signal::ctrl_c().await.unwrap()
+#[macro_use]
+extern crate lazy_static;
+
+use tokio::{
+    signal,
+    time::{sleep, sleep_until, Instant},
+    select,
+    sync::mpsc,
+};
+
+use rand::Rng;
+use tokio_util::sync::CancellationToken;
+
+use std::time::Duration;
+
+#[macro_use]
+extern crate tracing;
+use tracing::{instrument, Instrument};
+mod config;
+mod reddit;
+mod discord;
+mod audio;
+mod commands;
+mod retry;
+mod filter;
+mod store;
+mod backup;
+mod metrics;
+mod logging;
+mod health;
+mod api;
+mod reporting;
+mod runtime;
+mod pipeline;
+mod slack;
+mod mastodon;
+mod email;
+mod irc;
+mod hooks;
+mod systemd;
+mod supervisor;
+
+// Consecutive failed reddit polls before we call it degraded and tell the admin
+const REDDIT_FAILURE_ALERT: u32 = 3;
+// Longest we'll back off between polls when reddit's having a bad time
+const MAX_POLL_BACKOFF: u64 = 30 * 60;
+// Posts in a row we can fail to get out before we tell the admin
+const POST_FAILURE_ALERT: u32 = 5;
+// Longest the scraper gets to finish what it's sending when we're stopping
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+use std::sync::Arc;
+
+use reddit::PostEvent;
+use pipeline::{PostSink, PostSource, SourceError};
+use config::{Config, MIN_POLL_INTERVAL};
+use clap::Parser;
+
+/// Sniffs reddit posts and relays them to discord
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// Config file to load
+    #[clap(long, global = true, default_value = config::DEFAULT_CONFIG_PATH)]
+    config: String,
+    /// Go through the motions but don't send anything to discord
+    #[clap(long, global = true)]
+    dry_run: bool,
+    /// Log at this level instead of what the config says, like debug or warn
+    #[clap(long, global = true)]
+    log_level: Option<String>,
+    /// Use this [profiles.<name>] section of the config on top of the rest, like dev or prod
+    #[clap(long, global = true, env = "SNIFFER_PROFILE")]
+    profile: Option<String>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run the bot, what happens with no command
+    Run {
+        /// Just the discord side, don't poll reddit
+        #[clap(long)]
+        no_sniff: bool,
+    },
+    /// Replay everything a source has posted into the archives, like u/someone or r/something
+    Backfill {
+        source: String,
+    },
+    /// Load the config, say what's wrong with it and quit
+    CheckConfig,
+    /// Dump the post archive to a file
+    Export {
+        /// First day to include, like 2024-01-31
+        #[clap(long)]
+        from: Option<String>,
+        /// Last day to include
+        #[clap(long)]
+        to: Option<String>,
+        /// json or csv
+        #[clap(long, default_value = "json")]
+        format: String,
+        /// Where to write it, stdout if not given
+        #[clap(long)]
+        output: Option<String>,
+        /// Upload it to this discord channel as well
+        #[clap(long)]
+        upload: Option<u64>,
+        /// Export the daily stats instead of the posts
+        #[clap(long)]
+        stats: bool,
+    },
+    /// Bundle the database and config into one file, for moving to another host
+    Backup {
+        /// Where to write it, sniffer_backup_<date>.tar.gz if not given
+        #[clap(long)]
+        output: Option<String>,
+    },
+    /// Unpack a backup made with backup, stop the bot first
+    Restore {
+        archive: String,
+        /// Write over a config or database that's already there
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+/// How long to wait between reddit polls
+#[derive(Debug, Clone, Copy)]
+struct PollTiming {
+    interval: u64,
+    jitter: u64,
+}
+
+impl PollTiming {
+    fn new(config: &Config) -> PollTiming {
+        if config.polling.interval < MIN_POLL_INTERVAL {
+            warn!("Poll interval of {}s is too fast, using {}s", config.polling.interval, MIN_POLL_INTERVAL);
+        }
+        return PollTiming {
+            interval: config.polling.interval.max(MIN_POLL_INTERVAL),
+            jitter: config.polling.jitter,
+        }
+    }
+
+    /// Every failure in a row doubles the wait, up to MAX_POLL_BACKOFF
+    fn next_delay(&self, failures: u32) -> Duration {
+        let backoff = self.interval.saturating_mul(1 << failures.min(16)).min(MAX_POLL_BACKOFF.max(self.interval));
+        let jitter = rand::thread_rng().gen_range(0..=self.jitter);
+        Duration::from_secs(backoff + jitter)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+
+    let cli = Cli::parse();
+
+    // Restoring is how you get a config in the first place, so it can't need one
+    if let Some(Command::Restore { archive, force }) = &cli.command {
+        match backup::restore(archive, &cli.config, cli.profile.as_deref(), *force) {
+            Ok(done) => println!("{}", done),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Load our config
+    let mut config = match Config::load(&cli.config, cli.profile.as_deref()) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}, exiting", e);
+            return;
+        }
+    };
+    if let Some(level) = &cli.log_level {
+        config.logging.level = level.clone();
+    }
+    config.dry_run = cli.dry_run;
+    if let Err(e) = config::secrets::resolve(&mut config).await {
+        eprintln!("{}, exiting", e);
+        std::process::exit(1);
+    }
+
+    // Everything that's wrong, not just the first thing
+    if let Err(problems) = config.check() {
+        eprintln!("{} has {} problems:", cli.config, problems.len());
+        for problem in problems {
+            eprintln!("  {}", problem);
+        }
+        std::process::exit(1);
+    }
+    let command = cli.command.unwrap_or(Command::Run { no_sniff: false });
+    if let Command::CheckConfig = command {
+        println!("{} looks good", cli.config);
+        return;
+    }
+
+    // Create our log file, this has to stick around until we're done
+    let _log_guard = match logging::init(&config.logging) {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("{}, exiting", e);
+            std::process::exit(1);
+        }
+    };
+    // Same again for sentry, when it's on
+    let _sentry_guard = reporting::init(config.sentry.as_ref(), config.profile.as_deref(), &config.watch.sniffer);
+    match &config.profile {
+        Some(p) => warn!("Config loaded from {} with the {} profile", cli.config, p),
+        None => warn!("Config loaded from {}", cli.config),
+    }
+    debug!("{:?}", config.clone());
+    if config.dry_run {
+        warn!("Dry run, nothing's getting sent to discord");
+    }
+
+    // Replays a source's history into the archives and quits
+    let will_sniff = match command {
+        Command::Backfill { source } => {
+            match reddit::Source::parse(&source) {
+                Some(source) => run_backfill(config, source).await,
+                None => error!("Backfill needs something to walk, like u/someone or r/something"),
+            }
+            println!("Gooby!");
+            return;
+        }
+        Command::Export { from, to, format, output, upload, stats } => {
+            if let Err(e) = run_export(&config, from, to, &format, output, upload, stats).await {
+                error!("{}", e);
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Command::Backup { output } => {
+            let output = output.unwrap_or_else(|| format!("sniffer_backup_{}.tar.gz", chrono::Utc::now().format("%Y-%m-%d")));
+            match backup::create(&config, &cli.config, &output) {
+                Ok(done) => {
+                    warn!("{}", done);
+                    println!("{}", done);
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Command::Run { no_sniff } => !no_sniff,
+        Command::CheckConfig | Command::Restore { .. } => unreachable!(),
+    };
+
+    if config.discord.webhook_only {
+        run_webhook_only(config).await;
+        println!("Gooby!");
+        return;
+    }
+
+    // Shared between discord (who mutes) and reddit (who filters)
+    let muted_authors = reddit::MutedAuthors::default();
+    // Same deal for which users we're following right now
+    let disabled_users = reddit::DisabledUsers::default();
+
+    // Admins can kick the reddit loop into polling early
+    let (poll_tx, mut poll_rx) = mpsc::channel::<discord::slash::PollRequest>(1);
+    // Or into re-reading the config, so can SIGHUP
+    let (reload_tx, mut reload_rx) = mpsc::channel::<discord::slash::ReloadRequest>(1);
+    let config_path = cli.config.clone();
+    let profile = config.profile.clone();
+
+    let store = open_store(&config);
+    tokio::spawn(store::prune_forever(store.clone(), config.store.clone()));
+    if let Some(metrics_config) = config.metrics.clone() {
+        tokio::spawn(metrics::serve(metrics_config));
+    }
+
+    let api_poll_tx = poll_tx.clone();
+    let mut discord_bot = discord::DiscordBot::new(
+        config.clone(), muted_authors.clone(), disabled_users.clone(), poll_tx, reload_tx.clone(), store.clone(),
+    ).await;
+    tokio::spawn(reload_on_sighup(reload_tx));
+    discord_bot.alerts().catch_panics();
+
+    // Find out now if we can't post somewhere, rather than when the sniffer strikes
+    let mut other_channels = config.side_channels();
+    other_channels.push((String::from("channels.audio"), config.channels.audio));
+    let problems = discord_bot.preflight(&other_channels).await;
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("Channel problem: {}", problem);
+        }
+        discord_bot.alert_admin(format!("Channel problems on startup:\n{}", problems.join("\n"))).await;
+    }
+    if config.discord.auto_shards {
+        discord_bot.start_autosharded().await;
+    }
+    else {
+        discord_bot.start_shards(1).await;
+    }
+    if let Some(reports) = config.discord.shard_reports.clone() {
+        let bot = discord_bot.clone();
+        tokio::spawn(async move { bot.report_shards(reports).await });
+    }
+    // Systemd hears we're up once every shard's connected
+    if systemd::enabled() {
+        let bot = discord_bot.clone();
+        tokio::spawn(async move {
+            while bot.readiness(None).await.is_err() {
+                sleep(Duration::from_secs(1)).await;
+            }
+            systemd::ready();
+        });
+    }
+    
+
+    // Orchestrators want to know if we're alive and up to the job, two missed polls and we're not
+    let timing = PollTiming::new(&config);
+    let max_poll_age = match will_sniff {
+        true => Some(Duration::from_secs(2 * (timing.interval + timing.jitter))),
+        false => None,
+    };
+    if let Some(health_config) = config.health.clone() {
+        tokio::spawn(health::serve(health_config, discord_bot.clone(), max_poll_age));
+    }
+    if let Some(api_config) = config.api.clone() {
+        let state = api::ApiState::new(
+            &api_config, discord_bot.clone(), store.clone(), api_poll_tx, config.watched_users(), disabled_users.clone(), max_poll_age,
+        );
+        tokio::spawn(api::serve(api_config, state));
+    }
+
+    // Cancelled on ^C or SIGTERM, the scraper finishes what it's doing and stops, the other loops
+    // just stop
+    let shutdown = CancellationToken::new();
+
+    // Keep the shard latency gauges fresh for whoever's scraping us
+    if config.metrics.is_some() {
+        let bot = discord_bot.clone();
+        let task_shutdown = shutdown.clone();
+        tokio::spawn(supervisor::supervise("shard metrics", shutdown.clone(), move || {
+            let (bot, shutdown) = (bot.clone(), task_shutdown.clone());
+            async move {
+                loop {
+                    select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = sleep(metrics::SHARD_METRICS_INTERVAL) => {}
+                    }
+                    bot.record_shard_latency().await;
+                }
+            }
+        }, restart_alerts(discord_bot.clone())));
+    }
+
+    // Clone discord bot to use in a thread
+    let discord_bot_clone = discord_bot.clone();
+    // Run in a loop to wait for the sniffer to strike again
+    let mut run_token = None;
+    if will_sniff {
+        // Everywhere posts go, these outlive the scraper so a restart doesn't set them all up again
+        let mut sinks: Vec<Arc<dyn PostSink>> = vec![Arc::new(discord_bot.clone())];
+        sinks.extend(other_sinks(&config));
+        // Same for anyone asking it to poll or reload
+        let poll_rx = Arc::new(tokio::sync::Mutex::new(poll_rx));
+        let reload_rx = Arc::new(tokio::sync::Mutex::new(reload_rx));
+        let dry_run = config.dry_run;
+        let scraper_config = config.clone();
+        let scraper_shutdown = shutdown.clone();
+        let mut starts = 0;
+        // The supervisor starts it back up if it panics
+        run_token = Some(tokio::spawn(supervisor::supervise("scraper", shutdown.clone(), move || {
+            starts += 1;
+            let restarted = starts > 1;
+            let config = scraper_config.clone();
+            let config_path = config_path.clone();
+            let profile = profile.clone();
+            let sinks = sinks.clone();
+            let poll_rx = poll_rx.clone();
+            let reload_rx = reload_rx.clone();
+            let shutdown = scraper_shutdown.clone();
+            let discord_bot_clone = discord_bot_clone.clone();
+            let muted_authors = muted_authors.clone();
+            let disabled_users = disabled_users.clone();
+            let store = store.clone();
+            async move {
+                warn!("Starting scraper thread");
+                let mut poll_rx = poll_rx.lock().await;
+                let mut reload_rx = reload_rx.lock().await;
+                // Sources start fresh every time, they pick up where they were out of the store
+                let reddit = reddit::RedditScraper::new(
+                    reddit::sources(&config),
+                    config.reddit.auth.clone(),
+                    muted_authors,
+                    disabled_users,
+                    config.posting.score_gate.clone(),
+                    &config.reddit.anchor_file,
+                    &config.reddit.client,
+                    config.polling.duplicate_window_minutes,
+                    config.has_webhooks(),
+                    store,
+                ).await;
+                let mut sources: Vec<Box<dyn PostSource>> = vec![Box::new(reddit)];
+                let mut timing = PollTiming::new(&config);
+                // What we started with might be out of date, get back to whatever the file says now
+                if restarted {
+                    match reload_config(&config_path, profile.as_deref(), dry_run, &mut sources, &sinks, &mut timing).await {
+                        Ok(r) => warn!("{}", r),
+                        Err(e) => error!("Couldn't reload the config after restarting, using what we started with: {}", e),
+                    }
+                }
+                // How many polls in a row have failed, so we only bug the admin when it's not just a blip
+                let mut poll_failures = 0;
+                // Same for posts we couldn't get out everywhere
+                let mut post_failures = 0;
+                // Numbers each trip round the loop, so the logs for one can be picked out
+                let mut cycles: u64 = 0;
+                // Systemd restarts us if this loop ever stops coming round
+                let mut watchdog = systemd::watchdog_timer();
+                let mut next_poll = Instant::now() + timing.next_delay(poll_failures);
+                loop {
+                    // Check every X seconds, or whenever someone forces it
+                    let forced = select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = sleep_until(next_poll) => None,
+                        _ = systemd::tick(&mut watchdog) => {
+                            systemd::watchdog();
+                            continue;
+                        }
+                        Some(request) = poll_rx.recv() => Some(request),
+                        Some(request) = reload_rx.recv() => {
+                            let result = reload_config(&config_path, profile.as_deref(), dry_run, &mut sources, &sinks, &mut timing).await;
+                            match &result {
+                                Ok(r) => warn!("{}", r),
+                                Err(e) => error!("Config reload failed, keeping the old one: {}", e),
+                            }
+                            let _ = request.send(result);
+                            continue;
+                        }
+                    };
+                    // Somebody asking for it specifically gets it even when paused
+                    if forced.is_none() && discord_bot_clone.is_paused() {
+                        debug!("Sniffing is paused, skipping this loop");
+                        next_poll = Instant::now() + timing.next_delay(poll_failures);
+                        continue;
+                    }
+                    cycles += 1;
+                    let cycle = warn_span!("cycle", n = cycles);
+                    let (events, alerts, failure) = poll_sources(&mut sources).instrument(cycle.clone()).await;
+                    for alert in alerts {
+                        discord_bot_clone.alert_admin(alert).await;
+                    }
+                    match failure {
+                        None => {
+                            if poll_failures >= REDDIT_FAILURE_ALERT {
+                                warn!("Polls are back after {} failures", poll_failures);
+                                discord_bot_clone.alert_admin(format!("Polls are working again after {} failures", poll_failures)).await;
+                            }
+                            poll_failures = 0;
+                            discord_bot_clone.record_poll().await;
+                            let found = events.iter().filter(|e| matches!(e, PostEvent::New(_))).count();
+                            runtime::STATS.poll_finished(Ok(found));
+                            if let Some(request) = forced {
+                                let _ = request.send(Ok(found));
+                            }
+                        }
+                        Some((name, e)) => {
+                            if let Some(request) = forced {
+                                let _ = request.send(Err(format!("{}: {}", name, e)));
+                            }
+                            discord_bot_clone.alerts().send(&format!("{}_poll", name), e.to_string());
+                            runtime::STATS.poll_finished(Err(format!("{}: {}", name, e)));
+                            poll_failures += 1;
+                            if poll_failures == 1 && e.refused {
+                                discord_bot_clone.alert_admin(format!("{} is refusing us, we might be banned: {}", name, e)).await;
+                            }
+                            else if poll_failures == REDDIT_FAILURE_ALERT {
+                                error!("Polls are degraded, backing off to {:?}", timing.next_delay(poll_failures));
+                                discord_bot_clone.alert_admin(format!("{} polls have failed {} times in a row, backing off. Last error: {}", name, poll_failures, e)).await;
+                            }
+                        }
+                    }
+                    for event in events {
+                        runtime::STATS.post_processed();
+                        // Everything that happens to this post from here on gets tagged with it
+                        let span = warn_span!(parent: &cycle, "post", id = %event.post().id, source = %event.post().source);
+                        let new = matches!(event, PostEvent::New(_));
+                        let failed = deliver(&sinks, event).instrument(span).await;
+                        match failed {
+                            None if new => post_failures = 0,
+                            None => {}
+                            Some(e) => {
+                                post_failures += 1;
+                                if post_failures >= POST_FAILURE_ALERT {
+                                    post_failures = 0;
+                                    discord_bot_clone.alert_admin(format!("Failed to send {} posts in a row, last error: {}", POST_FAILURE_ALERT, e)).await;
+                                }
+                            }
+                        }
+                    }
+                    next_poll = Instant::now() + timing.next_delay(poll_failures);
+                }
+                for source in sources.iter_mut() {
+                    source.shutdown().await;
+                }
+                for sink in sinks.iter() {
+                    sink.flush().await;
+                }
+                warn!("Scraper stopped");
+            }
+        }, restart_alerts(discord_bot.clone()))));
+    }
+    else {
+        // Nobody's polling, so force polls should fail right away instead of hanging
+        drop(poll_rx);
+        drop(reload_rx);
+        // There's no loop to keep the watchdog happy, this has to do instead
+        if let Some(mut watchdog) = systemd::watchdog_timer() {
+            tokio::spawn(async move {
+                loop {
+                    watchdog.tick().await;
+                    systemd::watchdog();
+                }
+            });
+        }
+    }
+    
+
+    // Mods of the subreddit get its reports and removals too, on their own loop
+    if let (true, Some(modqueue)) = (will_sniff, config.watch.modqueue.clone()) {
+        match config.reddit.auth.clone() {
+            Some(credentials) => {
+                let timing = PollTiming::new(&config);
+                let client_config = config.reddit.client.clone();
+                let bot = discord_bot.clone();
+                let task_shutdown = shutdown.clone();
+                // A restart starts the watcher over, it primes itself again instead of reposting
+                tokio::spawn(supervisor::supervise("modqueue", shutdown.clone(), move || {
+                    let mut watcher = reddit::modqueue::ModqueueWatcher::new(&modqueue, credentials.clone(), &client_config);
+                    let (modqueue, bot, shutdown) = (modqueue.clone(), bot.clone(), task_shutdown.clone());
+                    async move {
+                        warn!("Watching the /r/{} modqueue", modqueue.subreddit);
+                        let mut failures = 0;
+                        loop {
+                            select! {
+                                _ = shutdown.cancelled() => break,
+                                _ = sleep(timing.next_delay(failures)) => {}
+                            }
+                            let events = match watcher.update().await {
+                                Ok(e) => {
+                                    failures = 0;
+                                    e
+                                }
+                                Err(e) => {
+                                    error!("Couldn't check the /r/{} modqueue: {}", modqueue.subreddit, e);
+                                    failures += 1;
+                                    continue;
+                                }
+                            };
+                            for event in events {
+                                if let Err(e) = bot.post_notice(serenity::model::id::ChannelId(modqueue.channel), event.discord_string()).await {
+                                    error!("Couldn't relay modqueue event: {}", e);
+                                }
+                            }
+                        }
+                    }
+                }, restart_alerts(discord_bot.clone())));
+            }
+            None => error!("Watching the modqueue needs reddit_auth for a mod account, skipping it"),
+        }
+    }
+
+    // Same for wiki pages, anyone can read those so no account needed
+    if will_sniff && !config.watch.wiki_pages.is_empty() {
+        let (pages, credentials, client_config) = (config.watch.wiki_pages.clone(), config.reddit.auth.clone(), config.reddit.client.clone());
+        let timing = PollTiming::new(&config);
+        let bot = discord_bot.clone();
+        let task_shutdown = shutdown.clone();
+        warn!("Watching {} wiki pages", config.watch.wiki_pages.len());
+        tokio::spawn(supervisor::supervise("wiki", shutdown.clone(), move || {
+            let mut watcher = reddit::wiki::WikiWatcher::new(pages.clone(), credentials.clone(), &client_config);
+            let (bot, shutdown) = (bot.clone(), task_shutdown.clone());
+            async move {
+                loop {
+                    select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = sleep(timing.next_delay(0)) => {}
+                    }
+                    for change in watcher.update().await {
+                        if let Err(e) = bot.post_notice(serenity::model::id::ChannelId(change.watch.channel), change.discord_string()).await {
+                            error!("Couldn't relay wiki change: {}", e);
+                        }
+                    }
+                }
+            }
+        }, restart_alerts(discord_bot.clone())));
+    }
+
+    // Live threads get their own loop each, and a lot faster than everything else
+    if will_sniff {
+        for watch in config.watch.live_threads.clone() {
+            let (credentials, client_config) = (config.reddit.auth.clone(), config.reddit.client.clone());
+            // Skips the usual minimum, these have to keep up
+            let timing = PollTiming {
+                interval: reddit::live::LIVE_POLL_INTERVAL.as_secs(),
+                jitter: 2,
+            };
+            let bot = discord_bot.clone();
+            let task_shutdown = shutdown.clone();
+            tokio::spawn(supervisor::supervise("live thread", shutdown.clone(), move || {
+                let mut watcher = reddit::live::LiveThreadWatcher::new(watch.clone(), credentials.clone(), &client_config);
+                let (bot, shutdown) = (bot.clone(), task_shutdown.clone());
+                async move {
+                    let mut failures = 0;
+                    while !watcher.finished {
+                        select! {
+                            _ = shutdown.cancelled() => return,
+                            _ = sleep(timing.next_delay(failures)) => {}
+                        }
+                        let updates = match watcher.update().await {
+                            Ok(u) => {
+                                if failures > 0 {
+                                    warn!("Back on live thread {} after {} failures", watcher.watch.id, failures);
+                                }
+                                failures = 0;
+                                u
+                            }
+                            Err(e) => {
+                                failures += 1;
+                                error!("Lost live thread {}, trying again in a bit: {}", watcher.watch.id, e);
+                                continue;
+                            }
+                        };
+                        for update in updates {
+                            if let Err(e) = bot.post_notice(serenity::model::id::ChannelId(watcher.watch.channel), update.discord_string()).await {
+                                error!("Couldn't relay live thread update: {}", e);
+                            }
+                        }
+                    }
+                    warn!("Stopped following live thread {}", watcher.watch.id);
+                    // Done for good, stopping now would look like dying to the supervisor
+                    shutdown.cancelled().await;
+                }
+            }, restart_alerts(discord_bot.clone())));
+        }
+    }
+
+    // Heads up when someone we follow gets suspended, deleted or hits a karma milestone
+    if let (true, Some(alerts)) = (will_sniff, config.watch.account_alerts.clone()) {
+        let (users, credentials, client_config) = (config.watched_users(), config.reddit.auth.clone(), config.reddit.client.clone());
+        let bot = discord_bot.clone();
+        let task_shutdown = shutdown.clone();
+        tokio::spawn(supervisor::supervise("account alerts", shutdown.clone(), move || {
+            let mut watcher = reddit::accounts::AccountWatcher::new(users.clone(), &alerts, credentials.clone(), &client_config);
+            let (alerts, bot, shutdown) = (alerts.clone(), bot.clone(), task_shutdown.clone());
+            async move {
+                loop {
+                    for alert in watcher.update().await {
+                        match alerts.channel {
+                            Some(c) => {
+                                if let Err(e) = bot.post_notice(serenity::model::id::ChannelId(c), alert).await {
+                                    error!("Couldn't post account alert: {}", e);
+                                }
+                            }
+                            None => bot.alert_admin(alert).await,
+                        }
+                    }
+                    select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = sleep(reddit::accounts::ACCOUNT_CHECK_INTERVAL) => {}
+                    }
+                }
+            }
+        }, restart_alerts(discord_bot.clone())));
+    }
+
+    //discord_bot.clone().read().await.print_shard_info().await;
+    discord_bot.print_shard_info().await;
+
+    println!("Ctrl-C to exit...");
+
+    // Wait for a signal, or for the scraper to stop on its own
+    let finished = select! {
+        _ = async {
+            match &mut run_token {
+                Some(token) => wait_token(token).await,
+                None => std::future::pending().await,
+            }
+        } => {
+            warn!("Bot thread stopped");
+            true
+        }
+        signal = wait_shutdown() => {
+            warn!("Got {}, shutting down", signal);
+            false
+        }
+    };
+    // Let the scraper get out what it's in the middle of sending, then take everything else down
+    systemd::stopping();
+    shutdown.cancel();
+    if let (false, Some(token)) = (finished, run_token) {
+        if let Err(_) = tokio::time::timeout(SHUTDOWN_TIMEOUT, wait_token(token)).await {
+            error!("Scraper didn't stop within {:?}, not waiting on it", SHUTDOWN_TIMEOUT);
+        }
+    }
+    discord_bot.shutdown().await;
+
+    println!("Gooby!");
+
+}
+
+// What the supervisor tells the admin with when one of our loops had to be started again
+fn restart_alerts(bot: discord::DiscordBot) -> impl Fn(String) -> futures::future::BoxFuture<'static, ()> {
+    move |text| {
+        let bot = bot.clone();
+        Box::pin(async move { bot.alert_admin(text).await })
+    }
+}
+
+// No store means we'd repost everything after a restart, so that's not worth running without
+fn open_store(config: &Config) -> Arc<dyn store::Store> {
+    store::open(&config.store).expect("Error opening the database")
+}
+
+// Re-read the config and hand the new filters, sources and poll timing to everything that
+// can take them without a restart. Any problem and we keep running on the old one
+async fn reload_config(
+    path: &str,
+    profile: Option<&str>,
+    dry_run: bool,
+    sources: &mut [Box<dyn PostSource>],
+    sinks: &[Arc<dyn PostSink>],
+    timing: &mut PollTiming,
+) -> Result<String, String> {
+    let mut config = Config::load(path, profile)?;
+    config.dry_run = dry_run;
+    config::secrets::resolve(&mut config).await?;
+    if let Err(problems) = config.check() {
+        return Err(problems.join("\n"));
+    }
+    for sink in sinks {
+        sink.reload(&config).await?;
+    }
+    // Picks up logging.level, so a SIGHUP can change it as well as /loglevel
+    if logging::level().as_deref() != Some(config.logging.level.as_str()) {
+        logging::set_level(&config.logging.level)?;
+    }
+    let mut changes = Vec::<String>::new();
+    for source in sources.iter_mut() {
+        changes.push(source.reload(&config).await?);
+    }
+    *timing = PollTiming::new(&config);
+    Ok(format!(
+        "Reloaded {}, {} every {}s. Discord settings and the side watchers still need a restart",
+        path, changes.join(", "), timing.interval
+    ))
+}
+
+// Everywhere posts go besides discord, whichever are configured. Anything that won't set up
+// is left out rather than stopping us
+fn other_sinks(config: &Config) -> Vec<Arc<dyn PostSink>> {
+    let mut sinks = Vec::<Arc<dyn PostSink>>::new();
+    match slack::SlackSink::new(config) {
+        Ok(Some(s)) => sinks.push(Arc::new(s)),
+        Ok(None) => {}
+        Err(e) => error!("Couldn't set up slack, not posting there: {}", e),
+    }
+    match mastodon::MastodonSink::new(config) {
+        Ok(Some(s)) => sinks.push(Arc::new(s)),
+        Ok(None) => {}
+        Err(e) => error!("Couldn't set up mastodon, not tooting: {}", e),
+    }
+    match email::EmailSink::new(config) {
+        Ok(Some(s)) => sinks.push(Arc::new(s)),
+        Ok(None) => {}
+        Err(e) => error!("Couldn't set up email, not sending any: {}", e),
+    }
+    match irc::IrcSink::new(config) {
+        Ok(Some(s)) => sinks.push(Arc::new(s)),
+        Ok(None) => {}
+        Err(e) => error!("Couldn't set up IRC, not posting there: {}", e),
+    }
+    match hooks::HookSink::new(config) {
+        Ok(Some(s)) => sinks.push(Arc::new(s)),
+        Ok(None) => {}
+        Err(e) => error!("Couldn't set up the hooks, not posting to them: {}", e),
+    }
+    sinks
+}
+
+// Poll every source once, one failing just sits this round out. Gives back everything that
+// happened, the alerts the sources had and the last failure if there was one
+#[instrument(level = "warn", name = "poll", skip_all)]
+async fn poll_sources(sources: &mut [Box<dyn PostSource>]) -> (Vec<PostEvent>, Vec<String>, Option<(String, SourceError)>) {
+    let _timer = metrics::REDDIT_POLL_SECONDS.start_timer();
+    let (mut events, mut alerts, mut failure) = (Vec::new(), Vec::new(), None);
+    for source in sources.iter_mut() {
+        match source.poll().await {
+            Ok(e) if e.is_empty() => debug!("Nothing new from {}", source.name()),
+            Ok(e) => {
+                warn!("Got {} new post events from {}", e.len(), source.name());
+                let new = e.iter().filter(|e| matches!(e, PostEvent::New(_))).count();
+                metrics::POSTS_SNIFFED.inc_by(new as u64);
+                events.extend(e);
+            }
+            Err(e) => {
+                error!("Encountered an error polling {}\n{}\nskipping it this loop", source.name(), e);
+                metrics::error(&format!("{}_poll", source.name()));
+                failure = Some((String::from(source.name()), e));
+            }
+        }
+        alerts.extend(source.take_alerts());
+    }
+    (events, alerts, failure)
+}
+
+// Hand an event to every sink, gives back the last thing that went wrong
+async fn deliver(sinks: &[Arc<dyn PostSink>], event: PostEvent) -> Option<String> {
+    let mut failed = None;
+    for sink in sinks {
+        if let Err(e) = sink.deliver(event.clone()).await {
+            error!("{}: {}", sink.name(), e);
+            failed = Some(e);
+        }
+    }
+    failed
+}
+
+// Bare bones mode, no shards or audio, just the scraper feeding our webhooks
+async fn run_webhook_only(config: Config) {
+    warn!("Running in webhook only mode");
+    let store = open_store(&config);
+    tokio::spawn(store::prune_forever(store.clone(), config.store.clone()));
+    if let Some(metrics_config) = config.metrics.clone() {
+        tokio::spawn(metrics::serve(metrics_config));
+    }
+    let reddit = reddit::RedditScraper::new(
+        reddit::sources(&config),
+        config.reddit.auth.clone(),
+        reddit::MutedAuthors::default(),
+        reddit::DisabledUsers::default(),
+        config.posting.score_gate.clone(),
+        &config.reddit.anchor_file,
+        &config.reddit.client,
+        config.polling.duplicate_window_minutes,
+        config.has_webhooks(),
+        store.clone(),
+    ).await;
+    let timing = PollTiming::new(&config);
+    let destinations = config.destinations();
+    let filters = filter::Filters::new(&config.filters, &destinations, &config.rules).expect("Error setting up filters");
+    let mut sources: Vec<Box<dyn PostSource>> = vec![Box::new(reddit)];
+    let mut sinks: Vec<Arc<dyn PostSink>> = vec![Arc::new(discord::webhook::WebhookSink::new(destinations, filters, config.dry_run))];
+    sinks.extend(other_sinks(&config));
+    // Same as the full bot, a signal lets whatever's being sent finish first
+    let shutdown = CancellationToken::new();
+    let stopper = shutdown.clone();
+    tokio::spawn(async move {
+        warn!("Got {}, shutting down", wait_shutdown().await);
+        systemd::stopping();
+        stopper.cancel();
+    });
+    // No shards to wait on, we're as ready as we'll get
+    systemd::ready();
+    let mut watchdog = systemd::watchdog_timer();
+    let mut poll_failures = 0;
+    let mut next_poll = Instant::now() + timing.next_delay(poll_failures);
+    loop {
+        select! {
+            _ = shutdown.cancelled() => break,
+            _ = sleep_until(next_poll) => {}
+            _ = systemd::tick(&mut watchdog) => {
+                systemd::watchdog();
+                continue;
+            }
+        }
+        let (events, alerts, failure) = poll_sources(&mut sources).await;
+        for alert in alerts {
+            error!("{}", alert);
+        }
+        match failure {
+            None => poll_failures = 0,
+            Some(_) => {
+                poll_failures += 1;
+                if poll_failures == REDDIT_FAILURE_ALERT {
+                    error!("Polls are degraded, backing off to {:?}", timing.next_delay(poll_failures));
+                }
+            }
+        }
+        for event in events {
+            let span = warn_span!("post", id = %event.post().id, source = %event.post().source);
+            deliver(&sinks, event).instrument(span).await;
+        }
+        next_poll = Instant::now() + timing.next_delay(poll_failures);
+    }
+    for source in sources.iter_mut() {
+        source.shutdown().await;
+    }
+    for sink in sinks.iter() {
+        sink.flush().await;
+    }
+}
+
+// Walk a source's whole history and drop it into the archive channels, oldest first
+async fn run_backfill(config: Config, source: reddit::Source) {
+    warn!("Backfilling {}", source);
+    let posts = match reddit::backfill(config.reddit.auth.clone(), &config.reddit.client, &source).await {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Couldn't backfill {}: {}", source, e);
+            return;
+        }
+    };
+    // Nobody's going to force a poll or reload, we aren't running one
+    let (poll_tx, _) = mpsc::channel::<discord::slash::PollRequest>(1);
+    let (reload_tx, _) = mpsc::channel::<discord::slash::ReloadRequest>(1);
+    let store = open_store(&config);
+    let discord_bot = discord::DiscordBot::new(
+        config.clone(),
+        reddit::MutedAuthors::default(),
+        reddit::DisabledUsers::default(),
+        poll_tx,
+        reload_tx,
+        store.clone(),
+    ).await;
+    let mut archived = 0;
+    for post in &posts {
+        // The database gets everything, discord just the posts the archives want
+        store.save_post(post);
+        match discord_bot.archive_post(post).await {
+            Ok(n) if n > 0 => archived += 1,
+            Ok(_) => {}
+            Err(e) => error!("Couldn't archive {}: {}", post.id, e),
+        }
+    }
+    warn!("Backfilled {} of {} posts from {}", archived, posts.len(), source);
+}
+
+// Write the archive out for some dates, and maybe drop it in a channel too
+async fn run_export(
+    config: &Config,
+    from: Option<String>,
+    to: Option<String>,
+    format: &str,
+    output: Option<String>,
+    upload: Option<u64>,
+    stats: bool,
+) -> Result<(), String> {
+    let format = match store::ExportFormat::parse(format) {
+        Some(f) => f,
+        None => return Err(String::from(format!("Can't export as {}, only json or csv", format))),
+    };
+    let (start, end) = store::date_range(from.as_deref(), to.as_deref())?;
+    let archive = open_store(config);
+    let (file, name, what) = match stats {
+        true => {
+            let rows = archive.stats_between(start, end)?;
+            (store::export_stats(&rows, format)?, "sniffer_stats", format!("{} stats rows", rows.len()))
+        }
+        false => {
+            let posts = archive.posts_between(start, end)?;
+            (store::export(&posts, format)?, "sniffer_archive", format!("{} posts", posts.len()))
+        }
+    };
+    match &output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, &file) {
+                return Err(String::from(format!("Couldn't write {}: {}", path, e)));
+            }
+            warn!("Exported {} to {}", what, path);
+        }
+        None if upload.is_none() => {
+            use std::io::Write;
+            if let Err(e) = std::io::stdout().write_all(&file) {
+                return Err(String::from(format!("Couldn't write the export: {}", e)));
+            }
+        }
+        None => {}
+    }
+    if let Some(channel) = upload {
+        if config.dry_run {
+            warn!("Dry run, not uploading the export to channel {}", channel);
+            return Ok(());
+        }
+        let http = serenity::http::Http::new_with_token(&config.discord.bot_token);
+        let filename = format!("{}.{}", name, format.extension());
+        let text = format!("Archive export, {}", what);
+        let sent = serenity::model::id::ChannelId(channel).send_files(&http, vec![(file.as_slice(), filename.as_str())], |m| {
+            m.content(text)
+        }).await;
+        if let Err(e) = sent {
+            return Err(String::from(format!("Couldn't upload the export to channel {}: {}", channel, e)));
+        }
+        warn!("Uploaded {} to channel {}", what, channel);
+    }
+    Ok(())
+}
+
+// A panicking scraper gets logged rather than taking us down with it
+async fn wait_token<T, H>(handle: H)
+where
+    H: std::future::Future<Output = Result<T, tokio::task::JoinError>>,
+{
+    if let Err(e) = handle.await {
+        error!("Bot thread died: {}", e);
+    }
+}
+
+// ^C or SIGTERM, whichever comes first, says which it was
+async fn wait_shutdown() -> &'static str {
+    let mut terminate = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+        Ok(s) => Some(s),
+        Err(e) => {
+            error!("Couldn't listen for SIGTERM, only ^C will stop us cleanly: {}", e);
+            None
+        }
+    };
+    select! {
+        _ = signal::ctrl_c() => "SIGINT",
+        _ = async {
+            match &mut terminate {
+                Some(t) => { t.recv().await; }
+                None => std::future::pending::<()>().await,
+            }
+        } => "SIGTERM",
+    }
+}
+
+// kill -HUP gets the same reload as the slash command
+async fn reload_on_sighup(reload_requests: mpsc::Sender<discord::slash::ReloadRequest>) {
+    let mut hangups = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Couldn't listen for SIGHUP, no reloading that way: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        warn!("Got SIGHUP, reloading the config");
+        let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+        if let Err(_) = reload_requests.send(result_tx).await {
+            error!("Nothing's polling reddit, nothing to reload");
+            continue;
+        }
+        // The loop logs how it went itself
+        let _ = result_rx.await;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::config::Config;
+use crate::filter::{FilterConfig, Filters, PostFilter};
+use crate::pipeline::PostSink;
+use crate::reddit::{PostEvent, SnifferPost};
+use crate::retry::with_backoff;
+
+// What rules call it
+pub const SINK_NAME: &str = "mastodon";
+// Vanilla mastodon's limit, for instances that don't say what theirs is
+const DEFAULT_MAX_CHARACTERS: usize = 500;
+// Every link counts as this many characters, however long it really is
+const URL_LENGTH: usize = 23;
+const SEND_ATTEMPTS: u32 = 3;
+const SEND_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Toot every sniff from a mastodon account
+#[derive(Deserialize, Debug, Clone)]
+pub struct MastodonConfig {
+    // Like https://mastodon.social
+    pub instance: String,
+    // From the account's development settings, needs write:statuses
+    pub access_token: String,
+    #[serde(default)]
+    pub visibility: Visibility,
+    // What nsfw posts go behind, the subreddit gets added on the end
+    #[serde(default = "default_content_warning")]
+    pub content_warning: String,
+    // These instead of the global filters
+    pub filters: Option<FilterConfig>,
+}
+
+fn default_content_warning() -> String {
+    String::from("NSFW")
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Public,
+    Unlisted,
+    // Followers only
+    Private,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::Unlisted
+    }
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    status: String,
+    visibility: Visibility,
+    sensitive: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spoiler_text: Option<&'a str>,
+}
+
+pub struct MastodonSink {
+    client: reqwest::Client,
+    config: MastodonConfig,
+    own_filter: Option<PostFilter>,
+    filters: RwLock<Arc<Filters>>,
+    // Asked for the first time we toot
+    max_characters: OnceCell<usize>,
+    dry_run: bool,
+}
+
+impl MastodonSink {
+    /// Nothing to do without a [mastodon] section
+    pub fn new(config: &Config) -> Result<Option<MastodonSink>, String> {
+        let mastodon = match &config.mastodon {
+            Some(m) => m.clone(),
+            None => return Ok(None),
+        };
+        let own_filter = match &mastodon.filters {
+            Some(f) => Some(PostFilter::new(f)?),
+            None => None,
+        };
+        warn!("Tooting to {}", mastodon.instance);
+        return Ok(Some(MastodonSink {
+            client: reqwest::Client::new(),
+            config: mastodon,
+            own_filter: own_filter,
+            filters: RwLock::new(Arc::new(Filters::new(&config.filters, &[], &config.rules)?)),
+            max_characters: OnceCell::new(),
+            dry_run: config.dry_run,
+        }))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.instance.trim_end_matches('/'), path)
+    }
+
+    // Whatever the instance says its toots can be, it's not always 500
+    async fn max_characters(&self) -> usize {
+        *self.max_characters.get_or_init(|| async {
+            let response = self.client.get(self.url("/api/v2/instance")).send().await.and_then(|r| r.error_for_status());
+            let limit = match response {
+                Ok(r) => r.json::<serde_json::Value>().await.ok()
+                    .and_then(|v| v["configuration"]["statuses"]["max_characters"].as_u64()),
+                Err(e) => {
+                    error!("Couldn't ask {} how long toots can be, going with {}: {}", self.config.instance, DEFAULT_MAX_CHARACTERS, e);
+                    None
+                }
+            };
+            limit.map_or(DEFAULT_MAX_CHARACTERS, |l| l as usize)
+        }).await
+    }
+
+    async fn toot(&self, post: &SnifferPost, status: &Status<'_>) -> Result<(), String> {
+        let response = self.client.post(self.url("/api/v1/statuses"))
+            .bearer_auth(&self.config.access_token)
+            // Retries of the same post only ever make the one toot
+            .header("Idempotency-Key", post.fullname())
+            .json(status)
+            .send().await
+            .and_then(|r| r.error_for_status());
+        response.map(|_| ()).map_err(|e| String::from(format!("{} said no: {}", self.config.instance, e)))
+    }
+}
+
+#[async_trait]
+impl PostSink for MastodonSink {
+    fn name(&self) -> &str {
+        SINK_NAME
+    }
+
+    // Only new posts, toots stay how they went out
+    async fn deliver(&self, event: PostEvent) -> Result<(), String> {
+        let post = match event {
+            PostEvent::New(p) => p,
+            _ => return Ok(()),
+        };
+        let filters = self.filters.read().await.clone();
+        if !filters.allows_sink(SINK_NAME, self.own_filter.as_ref(), &post) {
+            return Ok(());
+        }
+        let spoiler = format!("{}, /r/{}", self.config.content_warning, post.subreddit);
+        let spoiler_text = match post.nsfw {
+            true => Some(spoiler.as_str()),
+            false => None,
+        };
+        // The content warning counts against the limit too
+        let limit = self.max_characters().await.saturating_sub(spoiler_text.map_or(0, |s| s.chars().count()));
+        let status = Status {
+            status: compose(&post, limit),
+            visibility: self.config.visibility,
+            sensitive: post.nsfw,
+            spoiler_text: spoiler_text,
+        };
+        if self.dry_run {
+            warn!("Dry run, would have tooted {}", post.id);
+            return Ok(());
+        }
+        let result = with_backoff(&format!("Tooting {}", post.id), SEND_ATTEMPTS, SEND_RETRY_DELAY, || self.toot(&post, &status)).await;
+        match result {
+            Ok(_) => {
+                warn!("Tooted {}", post.id);
+                Ok(())
+            }
+            Err(e) => {
+                crate::metrics::error("mastodon_post");
+                Err(e)
+            }
+        }
+    }
+
+    async fn reload(&self, config: &Config) -> Result<(), String> {
+        *self.filters.write().await = Arc::new(Filters::new(&config.filters, &[], &config.rules)?);
+        Ok(())
+    }
+}
+
+/// The toot itself, the title and as much of the body as fits with the link and credit on the end
+fn compose(post: &SnifferPost, limit: usize) -> String {
+    let footer = format!("\n\n/u/{} in /r/{}\nhttps://www.reddit.com{}", post.author, post.subreddit, post.permalink);
+    let room = limit.saturating_sub(toot_length(&footer));
+    let text = match &post.body {
+        Some(b) if !b.trim().is_empty() => format!("{}\n\n{}", post.title, b.trim()),
+        _ => post.title.clone(),
+    };
+    // A zero width space after every @ so nobody on the fediverse gets pinged by a reddit post
+    let text = text.replace('@', "@\u{200b}");
+    let text = match toot_length(&text) > room {
+        true => format!("{}…", cut(&text, room.saturating_sub(1)).trim_end()),
+        false => text,
+    };
+    format!("{}{}", text, footer)
+}
+
+lazy_static! {
+    static ref URLS: Regex = Regex::new(r"https?://\S+").unwrap();
+}
+
+// How long mastodon thinks this is. Every link counts as URL_LENGTH, even ones that are really shorter
+fn toot_length(text: &str) -> usize {
+    URLS.replace_all(text, "x".repeat(URL_LENGTH).as_str()).chars().count()
+}
+
+// As much of the start of text as fits in room, going by toot_length. Links go in whole or not at all
+fn cut(text: &str, room: usize) -> String {
+    let mut kept = String::new();
+    let mut used = 0;
+    let mut last = 0;
+    for url in URLS.find_iter(text).map(Some).chain(std::iter::once(None)) {
+        let end = url.map(|u| u.start()).unwrap_or(text.len());
+        for c in text[last..end].chars() {
+            if used == room {
+                return kept;
+            }
+            kept.push(c);
+            used += 1;
+        }
+        let url = match url {
+            Some(u) => u,
+            None => break,
+        };
+        if used + URL_LENGTH > room {
+            return kept;
+        }
+        kept.push_str(url.as_str());
+        used += URL_LENGTH;
+        last = url.end();
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(title: &str, body: Option<&str>) -> SnifferPost {
+        serde_json::from_value(serde_json::json!({
+            "title": title,
+            "body": body,
+            "subreddit": "test",
+            "id": "abc123",
+            "timestamp": 0,
+            "author": "someone",
+            "score": 1,
+            "permalink": "/r/test/comments/abc123/a_post/",
+            "nsfw": false,
+            "source": "/u/someone",
+            "comment": false,
+            "revisions": [],
+            "gallery": [],
+            "duplicates": [],
+            "awards": [],
+            "stickied": false,
+        })).unwrap()
+    }
+
+    #[test]
+    fn short_posts_go_out_whole() {
+        let toot = compose(&post("A title", Some("Some body")), 500);
+        assert_eq!(toot, "A title\n\nSome body\n\n/u/someone in /r/test\nhttps://www.reddit.com/r/test/comments/abc123/a_post/");
+    }
+
+    #[test]
+    fn long_posts_get_cut_to_fit() {
+        let body = "word ".repeat(200);
+        let toot = compose(&post("A title", Some(&body)), 500);
+        assert!(toot_length(&toot) <= 500, "{} is too long", toot_length(&toot));
+        assert!(toot.contains("…\n\n/u/someone in /r/test\nhttps://www.reddit.com/"));
+    }
+
+    #[test]
+    fn cuts_between_characters() {
+        let toot = compose(&post(&"日本語".repeat(200), None), 100);
+        assert!(toot_length(&toot) <= 100);
+        assert!(toot.starts_with("日本語"));
+    }
+
+    #[test]
+    fn exactly_full_isnt_cut() {
+        let footer = toot_length(&compose(&post("", None), 500));
+        let title = "x".repeat(500 - footer);
+        let toot = compose(&post(&title, None), 500);
+        assert!(toot.starts_with(&title));
+        assert!(!toot.contains('…'));
+        assert_eq!(toot_length(&toot), 500);
+    }
+
+    #[test]
+    fn mentions_dont_ping() {
+        let toot = compose(&post("Hey @someone@mastodon.social", None), 500);
+        assert!(toot.starts_with("Hey @\u{200b}someone@\u{200b}mastodon.social\n\n"));
+    }
+
+    #[test]
+    fn links_count_as_url_length() {
+        let footer = toot_length(&compose(&post("", None), 100));
+        let short = format!("{} http://a.co", "x".repeat(100 - footer - URL_LENGTH - 1));
+        let toot = compose(&post(&short, None), 100);
+        assert!(toot.starts_with(&short));
+        assert_eq!(toot_length(&toot), 100);
+        // One over, and the link doesn't get cut in half
+        let toot = compose(&post(&format!("x{}", short), None), 100);
+        assert!(toot.contains('…'));
+        assert!(!toot.contains("http://a"));
+        assert!(toot_length(&toot) <= 100);
+    }
+}
@@ -0,0 +1,97 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use prometheus::{
+    Encoder, Histogram, IntCounter, IntCounterVec, GaugeVec, TextEncoder,
+    register_histogram, register_int_counter, register_int_counter_vec, register_gauge_vec,
+};
+use serde::Deserialize;
+
+// How often the shard latency gauges get refreshed
+pub const SHARD_METRICS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Serve prometheus metrics over http
+#[derive(Deserialize, Debug, Clone)]
+pub struct MetricsConfig {
+    #[serde(default = "default_listen")]
+    pub listen: String,
+}
+
+fn default_listen() -> String {
+    String::from("127.0.0.1:9184")
+}
+
+lazy_static! {
+    pub static ref POSTS_SNIFFED: IntCounter = register_int_counter!(
+        "sniffer_posts_sniffed_total", "New posts and comments found on reddit"
+    ).unwrap();
+    pub static ref POSTS_RELAYED: IntCounter = register_int_counter!(
+        "sniffer_posts_relayed_total", "Posts that got out to at least one channel"
+    ).unwrap();
+    pub static ref DISCORD_SEND_SECONDS: Histogram = register_histogram!(
+        "sniffer_discord_send_seconds", "How long it took to get a post out to one destination, retries and all"
+    ).unwrap();
+    pub static ref REDDIT_POLL_SECONDS: Histogram = register_histogram!(
+        "sniffer_reddit_poll_seconds", "How long a whole reddit poll took"
+    ).unwrap();
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "sniffer_errors_total", "Things that went wrong, by what kind of thing", &["kind"]
+    ).unwrap();
+    pub static ref SHARD_LATENCY_SECONDS: GaugeVec = register_gauge_vec!(
+        "sniffer_shard_latency_seconds", "Gateway heartbeat latency of each shard", &["shard"]
+    ).unwrap();
+}
+
+/// Count one more of some kind of error, like reddit_poll or discord_post
+pub fn error(kind: &str) {
+    ERRORS.with_label_values(&[kind]).inc();
+}
+
+/// Answer /metrics until we're stopped
+pub async fn serve(config: MetricsConfig) {
+    let address = match config.listen.parse::<SocketAddr>() {
+        Ok(a) => a,
+        Err(e) => {
+            error!("Can't serve metrics on {}: {}", config.listen, e);
+            return;
+        }
+    };
+    let server = match Server::try_bind(&address) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Can't serve metrics on {}: {}", address, e);
+            return;
+        }
+    };
+    warn!("Serving metrics on http://{}/metrics", address);
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(respond)) });
+    if let Err(e) = server.serve(make_service).await {
+        error!("Metrics server fell over: {}", e);
+    }
+}
+
+async fn respond(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::from("Try /metrics"));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        error!("Couldn't encode the metrics: {}", e);
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return Ok(response);
+    }
+    let mut response = Response::new(Body::from(buffer));
+    if let Ok(content_type) = encoder.format_type().parse() {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    Ok(response)
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+use serenity::async_trait;
+
+use crate::config::Config;
+use crate::reddit::PostEvent;
+
+/// Why a poll didn't work out
+#[derive(Debug, Clone)]
+pub struct SourceError {
+    pub message: String,
+    // They're refusing us outright, we might be banned rather than it being a blip
+    pub refused: bool,
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Somewhere posts come from, polled every trip round the main loop
+#[async_trait]
+pub trait PostSource: Send {
+    /// Short and lowercase, it ends up in logs and metrics
+    fn name(&self) -> &str;
+
+    /// Everything that's happened since the last poll
+    async fn poll(&mut self) -> Result<Vec<PostEvent>, SourceError>;
+
+    /// Anything the admin should hear about, only handed out once
+    fn take_alerts(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Pick up a reloaded config, says what it's doing now
+    async fn reload(&mut self, _config: &Config) -> Result<String, String> {
+        Ok(format!("{} needs a restart to pick up changes", self.name()))
+    }
+
+    /// We're stopping, save anything that needs to outlive us
+    async fn shutdown(&mut self) {}
+}
+
+/// Somewhere posts go, every sink gets every event and picks out what it cares about
+#[async_trait]
+pub trait PostSink: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Only new posts not getting out counts as a failure, the rest is best effort
+    async fn deliver(&self, event: PostEvent) -> Result<(), String>;
+
+    /// Pick up a reloaded config
+    async fn reload(&self, _config: &Config) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// We're stopping, get out anything that's still being held back
+    async fn flush(&self) {}
+}
@@ -1,258 +1,1708 @@
-// Formatting
-use std::fmt;
-
-// For our url regex matching
-use regex::Regex;
-
-// for our api request
-use reqwest;
-use reqwest::{Client, Error};
-
-
-#[derive(Debug, Clone)]
-pub struct SnifferPost {
-    pub title: String,
-    pub body: Option<String>,
-    pub subreddit: String,
-    pub url: Option<String>,
-    pub id: String,
-    pub timestamp: u64,
-}
-
-static APP_USER_AGENT: &str = concat!(
-    env!("CARGO_PKG_NAME"),
-    ":",
-    env!("CARGO_PKG_VERSION"),
-    " (I have no account)"
-);
-
-// TODO: Make sense of the timestamps, so that if the post is deleted we can post how long it took for luls
-impl SnifferPost {
-    pub fn from_roux(roux: roux::subreddit::responses::SubmissionsData) -> SnifferPost {
-        debug!("creating a new sniffer post object");
-        SnifferPost {
-            title: roux.title,
-            body: {
-                if roux.selftext.is_empty() {
-                    None
-                }
-                else {
-                    Some(roux.selftext)
-                }
-            },
-            subreddit: roux.subreddit,
-            url: roux.url,
-            id: roux.id,
-            timestamp: roux.created as u64,
-        }
-    }
-    pub fn discord_string(&self) -> String {
-        // If we have body text, use it
-        match &self.body {
-            Some(b) => format!(
-                "{}\n\
-                \n\
-                {}\n\
-                > /r/{}", self.title, b, self.subreddit),
-            None => format!("{}\n> /r/{}", self.title, self.subreddit)
-        }
-    }
-
-    pub fn format_urls(&mut self) {
-    //pub fn url_convert(mut self) -> SnifferPost {
-        // This is to ensure that this regex is only compiled once, so we aren't dropping
-        // it from scope and compiling it in a loop when this function runs over iterated objects
-        // this regex is compiled at runtime
-        lazy_static! {
-            // Returns the whole string to replace in the first capture, contents of [] in 2nd and () in 3rd
-            static ref RE: Regex = Regex::new(r"\[(.*)\]\((.*)\)").unwrap();
-        }
-
-        match self.body.as_ref() {
-            Some(body) => {
-                match RE.captures(body.as_str()) {
-                    Some(m) => {
-                        // We got a match
-                        let string_to_match = &m[0];
-                        let first_url = &m[1];
-                        let second_url = &m[1];
-                        //println!("{}\n{}\n{}", string_to_match, first_url, second_url);
-        
-                        // Just make extra sure, make sure the contents of the html formatting string are equal
-                        if first_url == second_url {
-                            // Replace our html url with a discord friendly one wrapped in <> tags
-                            let replace_string = format!("<{}>", first_url);
-                            self.body = Some(body.replace(string_to_match, replace_string.as_str()));
-                            warn!("Parsed url in post {}", self.id);
-                        }
-                        else {
-                            error!("Our two urls didn't match in the regex, something must be off");
-                        }
-                    }
-                    None => () // do nothing
-                }
-            }
-            None => ()     
-        }
-        //return self; // Give ourselves back whether we made changes or not
-    }
-}
-
-
-pub struct RedditScraper {
-    the_sniffer: String,
-    reqwest: reqwest::Client,
-    last_post_timestamp: u64,
-    post_cache: Vec<SnifferPost>,
-}
-
-impl PartialEq for SnifferPost {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-    }
-}
-
-impl fmt::Display for SnifferPost {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}, {:?}, {}, {:?}", self.title, self.body, self.subreddit, self.url)    
-    }
-}
-
-impl RedditScraper {
-
-    pub fn new(sniffer: String) -> RedditScraper {
-        warn!("Creating the reddit scraper with user agent: {}", APP_USER_AGENT);
-
-        let scraper = RedditScraper {
-            the_sniffer: sniffer,
-            reqwest: Client::builder()
-                .user_agent(APP_USER_AGENT)
-                //.connection_verbose(true)
-                //.use_native_tls()
-                .http1_title_case_headers()
-                .http2_prior_knowledge()
-                .http2_adaptive_window(true)
-                .build().expect("Error building reqwest client"),
-            last_post_timestamp: 0,
-            post_cache: Vec::new()
-        };
-
-        scraper.init()
-    }
-
-    fn init(mut self) -> RedditScraper {
-        // Get from reddit api
-        match self.pull_posts() {
-            Ok(mut p) => {
-                warn!("Got posts");
-                // Format the hyperlink text of all our pulled posts for consistency
-                for post in p.iter_mut() {
-                    post.format_urls();
-                }
-
-                // Add our pulled posts to our cache
-                self.post_cache.append(&mut p);
-
-                // update our most recent timestamp
-                self.last_post_timestamp = self.post_cache.last().unwrap().timestamp;
-
-                warn!("Pulled {} intial posts", self.post_cache.len());
-            }
-            Err(e) => {
-                error!("Got error (probably ratelimit) - {:?}", e);
-            } 
-        }
-
-        return self;
-    }
-
-    //fn pull_posts(&self) -> Result<Vec<SnifferPost>, RouxError> {
-    fn pull_posts(&self) -> Result<Vec<SnifferPost>, Error> {
-        // Get from reddit api
-
-        // dumb shit to run async in a sync function
-        let reddit_posts = tokio::task::block_in_place(move || {
-            tokio::runtime::Handle::current().block_on(async move {
-                //self.the_sniffer.submitted().await
-                let request = self.reqwest
-                    .get(format!("https://www.reddit.com/user/{}/submitted.json", self.the_sniffer));
-                let result = request.send().await.expect("Failed to get our request");
-                debug!("Response status: {:?}", result.status());
-                debug!("Reponse headers:\n{:?}", result.headers());
-                result.json::<roux::subreddit::responses::Submissions>().await
-            })
-        });
-        match reddit_posts {
-            Ok(submissions_data) => {
-                let mut new_posts = Vec::<SnifferPost>::new();
-                for p in submissions_data.data.children {
-                    new_posts.push(SnifferPost::from_roux(p.data));
-                }
-                // Always sort our posts oldest->newest bc reddit just gives them in random order
-                new_posts.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
-                return Ok(new_posts);
-            }
-            Err(error) => {
-                    error!("Encountered an error grabbing reddit posts\n{}", error);
-                    return Err(error)
-                },
-        };
-    }
-    
-    //pub fn update(&mut self) -> Result<Option<Vec<SnifferPost>>, RouxError> {
-    pub fn update(&mut self) -> Result<Option<Vec<SnifferPost>>, Error> {
-
-        debug!("Updating reddit posts");
-
-        // Strip the async requirement out of this function
-        //let posts_result = tokio::task::block_in_place(move || {
-
-        let posts_result = self.pull_posts();
-
-        //let fresh_posts = match self.pull_posts().await {
-        let mut fresh_posts = match posts_result {
-            Ok(d) => d,
-            Err(e) => return Err(e),
-        };
-
-        // Our vec of potential new posts
-        let mut new_posts = Vec::<SnifferPost>::new();
-
-        // Check our new posts with our cache to see if any exist
-        for p in fresh_posts.iter_mut() {
-            // we only need to check the new post timestamps against the last recorded one
-            if p.timestamp > self.last_post_timestamp {
-                // Double-check to make sure that reddit didn't decide to "update" the timestamp on an older post
-                match self.post_cache.iter_mut().find(|x| *x.id == p.id) {
-                    Some(x) => { 
-                        error!("Reddit gave us an incorrectly modified timestamp on existing post {}", x.id);
-                        // update the post with the new timestamp, thanks reddit
-                        error!("Updating {} timestamp to {} from {}", x.id, x.timestamp, p.timestamp);
-                        x.timestamp = p.timestamp;
-                        // Update our last_post_timestamp after correction
-                        self.last_post_timestamp = p.timestamp;
-                    }
-                    None => {
-                        debug!("New sniffer post {}", p);
-                        // Fix and urls in the post's body
-                        p.format_urls();
-                        // record our new posts in the cache
-                        self.post_cache.push(p.clone());
-                        warn!("Cached a new post");
-                        // Add our new posts
-                        new_posts.push(p.clone());
-                        // Update the most recent timestamp 
-                        self.last_post_timestamp = new_posts.last().unwrap().timestamp;
-                    },
-                }    
-            } // If there's no new post detected, we don't put any in our vec
-        }
-
-        if !new_posts.is_empty() {
-            // record our new posts in the cache
-            return Ok(Some(new_posts));
-        }
-        return Ok(None);
-    }
-
-}
+// Formatting
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+use futures::future::join_all;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// For our url regex matching
+use regex::Regex;
+
+// for our api request
+use reqwest;
+use reqwest::Error;
+
+pub mod oauth;
+use oauth::{ClientConfig, Failure, RedditApi, RedditCredentials};
+pub mod anchors;
+use anchors::Anchors;
+use crate::store::Store;
+use crate::config::Config;
+use crate::pipeline::{PostSource, SourceError};
+use crate::discord::split::escape_spoilers;
+pub mod modqueue;
+pub mod wiki;
+pub mod live;
+pub mod accounts;
+
+// For building discord embeds out of our posts
+use serenity::builder::CreateEmbed;
+use chrono::{TimeZone, Utc};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnifferPost {
+    pub title: String,
+    pub body: Option<String>,
+    pub subreddit: String,
+    pub url: Option<String>,
+    pub id: String,
+    pub timestamp: u64,
+    pub author: String,
+    pub score: i64,
+    pub permalink: String,
+    pub author_icon: Option<String>,
+    pub nsfw: bool,
+    // Which of our sources this came from, like /u/someone or /r/something
+    pub source: String,
+    // Every one of our sources that turned it up this time around, source is just the first
+    #[serde(default)]
+    pub seen_in: Vec<String>,
+    // A comment rather than a submission, url points at the thread it's in
+    pub comment: bool,
+    // Link flair on the post and the author's flair in that subreddit, text and template id
+    pub flair: Option<String>,
+    pub flair_id: Option<String>,
+    pub author_flair: Option<String>,
+    pub author_flair_id: Option<String>,
+    // When reddit says it was last edited, if it ever was
+    pub edited: Option<u64>,
+    // Every earlier version we've seen, oldest first
+    pub revisions: Vec<PostRevision>,
+    // Reddit says it's been taken down, the content is gone from its side
+    pub removed: Option<Removal>,
+    // Every image in a gallery post, in order
+    pub gallery: Vec<String>,
+    // Where it was originally posted, if this is a crosspost
+    pub crosspost: Option<CrosspostOrigin>,
+    // Direct mp4 for reddit hosted videos, v.redd.it links themselves don't play in discord
+    pub video: Option<String>,
+    // Poll posts have their options and votes in here
+    pub poll: Option<Poll>,
+    // Permalinks of the same thing posted elsewhere, that we didn't relay again
+    pub duplicates: Vec<String>,
+    // Gold and whatever else people have thrown at it
+    pub awards: Vec<Award>,
+    // Pinned to the top of its subreddit or profile
+    pub stickied: bool,
+    // "moderator" or "admin" when it's posted with their hat on
+    pub distinguished: Option<String>,
+}
+
+/// One kind of award on a post, and how many of it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Award {
+    pub name: String,
+    pub count: u64,
+}
+
+/// A reddit poll, votes only show up once you've voted or it's over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+    pub options: Vec<PollOption>,
+    // Unix time it closes
+    pub ends: u64,
+    pub total_votes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollOption {
+    pub text: String,
+    pub votes: Option<u64>,
+}
+
+impl Poll {
+    pub fn is_over(&self) -> bool {
+        Utc::now().timestamp() as u64 >= self.ends
+    }
+
+    /// The options as a list, with votes if reddit gave us any
+    pub fn discord_string(&self) -> String {
+        let status = match self.is_over() {
+            true => format!("Poll closed <t:{}:R> with {} votes", self.ends, self.total_votes),
+            false => format!("Poll closes <t:{}:R>", self.ends),
+        };
+        let mut lines = vec![format!("📊 {}", status)];
+        for option in &self.options {
+            match option.votes {
+                Some(v) => lines.push(format!("• {} ({} votes)", option.text, v)),
+                None => lines.push(format!("• {}", option.text)),
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// The post a crosspost came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrosspostOrigin {
+    pub subreddit: String,
+    pub author: String,
+    pub permalink: String,
+}
+
+/// Why a post isn't around anymore
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Removal {
+    // It just stopped showing up in the listing
+    Missing,
+    // The author deleted it
+    Author,
+    // Mods, admins or the spam filter took it down
+    Moderators,
+}
+
+impl Removal {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Removal::Missing => "deleted on Reddit",
+            Removal::Author => "deleted by its author",
+            Removal::Moderators => "removed by the moderators",
+        }
+    }
+}
+
+/// What a post looked like before an edit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostRevision {
+    pub title: String,
+    pub body: Option<String>,
+    // When we noticed it change
+    pub replaced: u64,
+}
+
+// What discord takes in the parts of an embed
+const EMBED_TITLE_LENGTH: usize = 256;
+const EMBED_DESCRIPTION_LENGTH: usize = 4096;
+const EMBED_FIELD_LENGTH: usize = 1024;
+// Every this many polls we go back over recent posts one by one, for the ones that fell out of the listings
+const RECHECK_EVERY: u64 = 5;
+// How far back that goes
+const RECHECK_WINDOW: u64 = 24 * 60 * 60;
+// Most ids reddit takes in one info request
+const INFO_BATCH_SIZE: usize = 100;
+// How alike two posts' words have to be to count as the same post
+const DUPLICATE_SIMILARITY: f64 = 0.9;
+// Posts with fewer words than this have to match exactly
+const MIN_DUPLICATE_WORDS: usize = 5;
+// Biggest page a listing gives back
+const LISTING_PAGE_SIZE: usize = 100;
+// Most pages we'll follow forward from an anchor in one poll
+const MAX_ANCHOR_PAGES: usize = 10;
+// How long to wait between pages when backfilling, reddit wants us under 60 requests a minute
+const BACKFILL_PAGE_DELAY: Duration = Duration::from_secs(2);
+
+/// Something that happened to one of the sniffer's posts
+#[derive(Debug, Clone)]
+pub enum PostEvent {
+    New(SnifferPost),
+    // Both versions, so whoever gets this can show what changed
+    Edited { before: SnifferPost, after: SnifferPost },
+    // Somebody gave it awards since we last looked, just the new ones
+    Awarded { post: SnifferPost, new_awards: Vec<Award> },
+    // Something we already relayed showed up again, original has the new one in its duplicates
+    Duplicate { original: SnifferPost, duplicate: SnifferPost },
+    // The post as we last saw it, so we still have what it said
+    Deleted(SnifferPost, Removal),
+}
+
+impl PostEvent {
+    /// The post this is all about, which for duplicates is the original
+    pub fn post(&self) -> &SnifferPost {
+        match self {
+            PostEvent::New(p) => p,
+            PostEvent::Edited { after, .. } => after,
+            PostEvent::Awarded { post, .. } => post,
+            PostEvent::Duplicate { original, .. } => original,
+            PostEvent::Deleted(p, _) => p,
+        }
+    }
+}
+
+pub static APP_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    ":",
+    env!("CARGO_PKG_VERSION"),
+    " (I have no account)"
+);
+
+// TODO: Make sense of the timestamps, so that if the post is deleted we can post how long it took for luls
+impl SnifferPost {
+    pub fn from_roux(roux: roux::subreddit::responses::SubmissionsData) -> SnifferPost {
+        debug!("creating a new sniffer post object");
+        SnifferPost {
+            title: roux.title,
+            body: {
+                if roux.selftext.is_empty() {
+                    None
+                }
+                else {
+                    Some(roux.selftext)
+                }
+            },
+            subreddit: roux.subreddit,
+            url: roux.url,
+            id: roux.id,
+            timestamp: roux.created as u64,
+            author: roux.author,
+            score: roux.score as i64,
+            permalink: roux.permalink,
+            author_icon: None,
+            nsfw: roux.over_18,
+            source: String::new(),
+            seen_in: Vec::new(),
+            comment: false,
+            // Filled in from the listing json, see posts_from_listing
+            flair: None,
+            flair_id: None,
+            author_flair: None,
+            author_flair_id: None,
+            edited: None,
+            revisions: Vec::new(),
+            removed: None,
+            gallery: Vec::new(),
+            crosspost: None,
+            video: None,
+            poll: None,
+            duplicates: Vec::new(),
+            awards: Vec::new(),
+            stickied: false,
+            distinguished: None,
+        }
+    }
+    /// What reddit calls it in listing cursors, t1_ for comments and t3_ for submissions
+    pub fn fullname(&self) -> String {
+        match self.comment {
+            true => format!("t1_{}", self.id),
+            false => format!("t3_{}", self.id),
+        }
+    }
+
+    /// When the post was made, discord shows it relative and in everyone's own timezone
+    pub fn discord_timestamp(&self) -> String {
+        format!("<t:{}:R>", self.timestamp)
+    }
+
+    /// Credit for the original post if this is a crosspost
+    pub fn crosspost_credit(&self) -> Option<String> {
+        self.crosspost.as_ref().map(|c| format!(
+            "crossposted from /r/{} by /u/{} <https://www.reddit.com{}>", c.subreddit, c.author, c.permalink
+        ))
+    }
+
+    /// Stickied, or posted as a mod or admin
+    pub fn is_announcement(&self) -> bool {
+        self.stickied || matches!(self.distinguished.as_deref(), Some("moderator") | Some("admin"))
+    }
+
+    // What goes in front of the title so announcements stand out
+    fn announcement_tag(&self) -> Option<&'static str> {
+        match (self.distinguished.as_deref(), self.stickied) {
+            (Some("admin"), _) => Some("📢 **Admin announcement**"),
+            (Some("moderator"), _) => Some("📢 **Mod announcement**"),
+            (_, true) => Some("📌 **Stickied**"),
+            _ => None,
+        }
+    }
+
+    pub fn discord_string(&self) -> String {
+        // If we have body text, use it
+        let body = match (&self.body, &self.poll) {
+            (Some(b), Some(p)) => Some(format!("{}\n\n{}", b, p.discord_string())),
+            (None, Some(p)) => Some(p.discord_string()),
+            (b, None) => b.clone(),
+        };
+        let text = match &body {
+            Some(b) => format!(
+                "{}\n\
+                \n\
+                {}\n\
+                > /r/{} · {}", self.title, b, self.subreddit, self.discord_timestamp()),
+            None => format!("{}\n> /r/{} · {}", self.title, self.subreddit, self.discord_timestamp())
+        };
+        let text = match self.announcement_tag() {
+            Some(t) => format!("{}\n{}", t, text),
+            None => text,
+        };
+        match self.crosspost_credit() {
+            Some(c) => format!("{}\n> {}", text, c),
+            None => text,
+        }
+    }
+
+    /// The awards that are new or went up between two versions, by how much they went up
+    pub fn new_awards(before: &SnifferPost, after: &SnifferPost) -> Vec<Award> {
+        after.awards.iter().filter_map(|a| {
+            let had = before.awards.iter().find(|b| b.name == a.name).map_or(0, |b| b.count);
+            match a.count > had {
+                true => Some(Award { name: a.name.clone(), count: a.count - had }),
+                false => None,
+            }
+        }).collect()
+    }
+
+    /// Awards in a line, like "2x Gold, 1x Helpful"
+    pub fn award_summary(awards: &[Award]) -> String {
+        awards.iter().map(|a| format!("{}x {}", a.count, a.name)).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Where else this got posted, one link per line
+    pub fn duplicate_links(&self) -> String {
+        self.duplicates.iter().map(|d| format!("<https://www.reddit.com{}>", d)).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Whether two posts look like the same thing, either pointing at the same link, one being a
+    /// crosspost of the other, or saying pretty much the same thing
+    pub fn same_as(&self, other: &SnifferPost) -> bool {
+        if let (Some(a), Some(b)) = (self.url.as_deref().and_then(link_key), other.url.as_deref().and_then(link_key)) {
+            if a == b {
+                return true;
+            }
+        }
+        let crossposted = |a: &SnifferPost, b: &SnifferPost| a.crosspost.as_ref().map_or(false, |c| c.permalink == b.permalink);
+        if crossposted(self, other) || crossposted(other, self) {
+            return true;
+        }
+        let words = |p: &SnifferPost| -> HashSet<String> {
+            format!("{} {}", p.title, p.body.as_deref().unwrap_or(""))
+                .to_lowercase()
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let (a, b) = (words(self), words(other));
+        // Too short to go by overlap, these have to match exactly
+        if a.len() < MIN_DUPLICATE_WORDS || b.len() < MIN_DUPLICATE_WORDS {
+            return !a.is_empty() && a == b;
+        }
+        let shared = a.intersection(&b).count() as f64;
+        shared / (a.union(&b).count() as f64) >= DUPLICATE_SIMILARITY
+    }
+
+    /// Copy of the post for an embed, with the title and body hidden behind a spoiler in the description.
+    /// Embed titles don't do spoilers, so it gets a neutral one
+    pub fn spoilered_embed(&self) -> SnifferPost {
+        let mut post = self.clone();
+        let text = match &self.body {
+            Some(b) => format!("{}\n\n{}", self.title, b),
+            None => self.title.clone(),
+        };
+        // Cut it down before wrapping, so the closing bars don't get cut off with the rest
+        let text = escape_spoilers(&text);
+        let text = match text.chars().count() > EMBED_DESCRIPTION_LENGTH - 4 {
+            true => format!("{}…", text.chars().take(EMBED_DESCRIPTION_LENGTH - 5).collect::<String>()),
+            false => text,
+        };
+        post.title = String::from("NSFW post, click the spoiler to see it");
+        post.body = Some(format!("||{}||", text));
+        post
+    }
+
+    /// Take on an edited version of the post, keeping what it used to say
+    fn apply_edit(&mut self, edited: &SnifferPost) {
+        self.revisions.push(PostRevision {
+            title: self.title.clone(),
+            body: self.body.clone(),
+            replaced: Utc::now().timestamp() as u64,
+        });
+        self.title = edited.title.clone();
+        self.body = edited.body.clone();
+        self.score = edited.score;
+        self.edited = edited.edited;
+    }
+
+    /// Line by line rundown of what changed between two versions, in the style of a diff
+    pub fn diff(before: &SnifferPost, after: &SnifferPost) -> String {
+        let old = format!("{}\n{}", before.title, before.body.as_deref().unwrap_or(""));
+        let new = format!("{}\n{}", after.title, after.body.as_deref().unwrap_or(""));
+        diff_lines(&old, &new)
+    }
+
+    /// Fill out a discord embed with our post, the title links back to the post on reddit
+    pub fn discord_embed<'a>(&self, embed: &'a mut CreateEmbed, include_url: bool) -> &'a mut CreateEmbed {
+        embed.title(truncated(&self.title, EMBED_TITLE_LENGTH));
+        embed.url(format!("https://www.reddit.com{}", self.permalink));
+        if let Some(t) = self.announcement_tag() {
+            embed.footer(|f| f.text(t.replace("**", "")));
+        }
+        embed.author(|a| a.name(format!("/u/{}", self.author)));
+        if let Some(b) = &self.body {
+            embed.description(truncated(b, EMBED_DESCRIPTION_LENGTH));
+        }
+        embed.field("Subreddit", format!("/r/{}", self.subreddit), true);
+        embed.field("Score", self.score, true);
+        if let Some(p) = &self.poll {
+            embed.field("Poll", truncated(&p.discord_string(), EMBED_FIELD_LENGTH), false);
+        }
+        if let Some(c) = &self.crosspost {
+            embed.field("Crossposted from", format!(
+                "[/r/{} by /u/{}](https://www.reddit.com{})", c.subreddit, c.author, c.permalink
+            ), false);
+        }
+        // The archive gets the link the post points to, same as the text version
+        if include_url {
+            if let Some(u) = &self.url {
+                embed.field("Link", format!("<{}>", u), false);
+            }
+            if !self.duplicates.is_empty() {
+                embed.field("Also posted", truncated(&self.duplicate_links(), EMBED_FIELD_LENGTH), false);
+            }
+        }
+        embed.timestamp(Utc.timestamp(self.timestamp as i64, 0).to_rfc3339());
+        embed
+    }
+
+    pub fn format_urls(&mut self) {
+    //pub fn url_convert(mut self) -> SnifferPost {
+        // This is to ensure that this regex is only compiled once, so we aren't dropping
+        // it from scope and compiling it in a loop when this function runs over iterated objects
+        // this regex is compiled at runtime
+        lazy_static! {
+            // Returns the whole string to replace in the first capture, contents of [] in 2nd and () in 3rd
+            static ref RE: Regex = Regex::new(r"\[(.*)\]\((.*)\)").unwrap();
+        }
+
+        match self.body.as_ref() {
+            Some(body) => {
+                match RE.captures(body.as_str()) {
+                    Some(m) => {
+                        // We got a match
+                        let string_to_match = &m[0];
+                        let first_url = &m[1];
+                        let second_url = &m[1];
+                        //println!("{}\n{}\n{}", string_to_match, first_url, second_url);
+        
+                        // Just make extra sure, make sure the contents of the html formatting string are equal
+                        if first_url == second_url {
+                            // Replace our html url with a discord friendly one wrapped in <> tags
+                            let replace_string = format!("<{}>", first_url);
+                            self.body = Some(body.replace(string_to_match, replace_string.as_str()));
+                            warn!("Parsed url in post {}", self.id);
+                        }
+                        else {
+                            error!("Our two urls didn't match in the regex, something must be off");
+                        }
+                    }
+                    None => () // do nothing
+                }
+            }
+            None => ()     
+        }
+        //return self; // Give ourselves back whether we made changes or not
+    }
+}
+
+
+// A link boiled down so the same thing compares equal, no scheme, www, query string or trailing slash.
+// Links back into reddit threads are left out, every self post has one of those
+fn link_key(url: &str) -> Option<String> {
+    let url = url.split(|c| c == '?' || c == '#').next().unwrap_or(url).to_lowercase();
+    let url = url.trim_start_matches("https://").trim_start_matches("http://").trim_start_matches("www.");
+    let url = url.trim_end_matches('/');
+    if url.is_empty() || url.contains("reddit.com/r/") || url.starts_with("/r/") {
+        return None;
+    }
+    Some(String::from(url))
+}
+
+// Cut text down to a number of characters, with an ellipsis if anything went
+fn truncated(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return String::from(text);
+    }
+    let cut: String = text.chars().take(limit - 1).collect();
+    format!("{}…", cut)
+}
+
+// Percent encode something for a query string, search queries are full of quotes and colons
+fn url_encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Lines that went away get a -, lines that showed up get a +
+pub fn diff_lines(old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let mut out = Vec::<String>::new();
+    for line in &old_lines {
+        if !new_lines.contains(line) {
+            out.push(format!("- {}", line));
+        }
+    }
+    for line in &new_lines {
+        if !old_lines.contains(line) {
+            out.push(format!("+ {}", line));
+        }
+    }
+    out.join("\n")
+}
+
+/// Authors we've been told to ignore, shared with discord so people can mute from there
+#[derive(Clone, Default)]
+pub struct MutedAuthors(Arc<RwLock<HashSet<String>>>);
+
+impl MutedAuthors {
+    pub fn mute(&self, author: &str) {
+        warn!("Muting /u/{}", author);
+        self.0.write().unwrap().insert(author.to_lowercase());
+    }
+
+    pub fn is_muted(&self, author: &str) -> bool {
+        self.0.read().unwrap().contains(&author.to_lowercase())
+    }
+}
+
+/// Watched users who've been switched off for now, shared with discord so admins can flip them
+#[derive(Clone, Default)]
+pub struct DisabledUsers(Arc<RwLock<HashSet<String>>>);
+
+impl DisabledUsers {
+    pub fn disable(&self, user: &str) {
+        warn!("Disabling /u/{}", user);
+        self.0.write().unwrap().insert(user.to_lowercase());
+    }
+
+    pub fn enable(&self, user: &str) {
+        warn!("Enabling /u/{}", user);
+        self.0.write().unwrap().remove(&user.to_lowercase());
+    }
+
+    pub fn is_disabled(&self, user: &str) -> bool {
+        self.0.read().unwrap().contains(&user.to_lowercase())
+    }
+}
+
+/// Grab the current state of a single post or comment by its fullname, for when we want to check
+/// back on something we sniffed
+pub async fn fetch_post(api: &RedditApi, fullname: &str) -> Result<Option<SnifferPost>, Error> {
+    let listing = api.get(&format!("/api/info?id={}", fullname)).await?.json::<serde_json::Value>().await?;
+    // Comments come back in their own shape
+    if fullname.starts_with("t1_") {
+        return Ok(match serde_json::from_value::<CommentListing>(listing) {
+            Ok(comments) => comments.data.children.into_iter().next().map(|c| SnifferPost::from_comment(c.data)),
+            Err(e) => {
+                error!("Couldn't parse comment {}: {}", fullname, e);
+                None
+            }
+        });
+    }
+    Ok(posts_from_listing(listing).into_iter().next())
+}
+
+// Gallery images are listed in gallery_data for the order, and media_metadata for the actual urls
+fn gallery_urls(data: &serde_json::Value) -> Vec<String> {
+    let items = match data["gallery_data"]["items"].as_array() {
+        Some(i) => i,
+        None => return Vec::new(),
+    };
+    let mut urls = Vec::<String>::new();
+    for item in items {
+        let id = match item["media_id"].as_str() {
+            Some(id) => id,
+            None => continue,
+        };
+        let source = &data["media_metadata"][id]["s"];
+        // Animated ones only have a gif or mp4
+        let url = source["u"].as_str().or(source["gif"].as_str()).or(source["mp4"].as_str());
+        match url {
+            // Same html escaping as everywhere else
+            Some(u) => urls.push(u.replace("&amp;", "&")),
+            None => warn!("Gallery item {} doesn't have a url we can use", id),
+        }
+    }
+    urls
+}
+
+// Reddit hosted videos come as DASH, but there's always a plain mp4 fallback we can use
+// (it's missing the audio track, but it plays)
+fn video_url(data: &serde_json::Value) -> Option<String> {
+    let video = match data["secure_media"]["reddit_video"].is_object() {
+        true => &data["secure_media"]["reddit_video"],
+        false => &data["media"]["reddit_video"],
+    };
+    video["fallback_url"].as_str().map(|u| u.replace("&amp;", "&"))
+}
+
+fn awards(data: &serde_json::Value) -> Vec<Award> {
+    match data["all_awardings"].as_array() {
+        Some(a) => a.iter().map(|a| Award {
+            name: a["name"].as_str().unwrap_or("Award").to_string(),
+            count: a["count"].as_u64().unwrap_or(1),
+        }).collect(),
+        None => Vec::new(),
+    }
+}
+
+fn poll(data: &serde_json::Value) -> Option<Poll> {
+    let poll = &data["poll_data"];
+    let options = poll["options"].as_array()?;
+    Some(Poll {
+        options: options.iter().map(|o| PollOption {
+            text: o["text"].as_str().unwrap_or_default().to_string(),
+            votes: o["vote_count"].as_u64(),
+        }).collect(),
+        // Reddit does this one in milliseconds for some reason
+        ends: poll["voting_end_timestamp"].as_u64().unwrap_or_default() / 1000,
+        total_votes: poll["total_vote_count"].as_u64().unwrap_or_default(),
+    })
+}
+
+// Roux covers most of a submission, anything it doesn't we pick out of the json ourselves
+fn posts_from_listing(listing: serde_json::Value) -> Vec<SnifferPost> {
+    let children = match listing["data"]["children"].as_array() {
+        Some(c) => c.clone(),
+        None => return Vec::new(),
+    };
+    let mut posts = Vec::<SnifferPost>::new();
+    for child in children {
+        let data = &child["data"];
+        match serde_json::from_value::<roux::subreddit::responses::SubmissionsData>(data.clone()) {
+            Ok(d) => {
+                let mut post = SnifferPost::from_roux(d);
+                post.flair = data["link_flair_text"].as_str().map(String::from);
+                post.flair_id = data["link_flair_template_id"].as_str().map(String::from);
+                post.author_flair = data["author_flair_text"].as_str().map(String::from);
+                post.author_flair_id = data["author_flair_template_id"].as_str().map(String::from);
+                // This is false if it's never been edited, and a timestamp if it has
+                post.edited = data["edited"].as_f64().map(|t| t as u64);
+                post.removed = match data["removed_by_category"].as_str() {
+                    Some("deleted") => Some(Removal::Author),
+                    Some(_) => Some(Removal::Moderators),
+                    None if post.author == "[deleted]" => Some(Removal::Author),
+                    None => None,
+                };
+                post.gallery = gallery_urls(data);
+                post.video = video_url(data);
+                post.poll = poll(data);
+                post.awards = awards(data);
+                post.stickied = data["stickied"].as_bool().unwrap_or(false);
+                post.distinguished = data["distinguished"].as_str().map(String::from);
+                // Crossposts keep all their content on the original
+                let parent = &data["crosspost_parent_list"][0];
+                if parent.is_object() {
+                    post.crosspost = Some(CrosspostOrigin {
+                        subreddit: parent["subreddit"].as_str().unwrap_or_default().to_string(),
+                        author: parent["author"].as_str().unwrap_or_default().to_string(),
+                        permalink: parent["permalink"].as_str().unwrap_or_default().to_string(),
+                    });
+                    if post.gallery.is_empty() {
+                        post.gallery = gallery_urls(parent);
+                    }
+                    if post.video.is_none() {
+                        post.video = video_url(parent);
+                    }
+                }
+                posts.push(post);
+            }
+            Err(e) => error!("Couldn't read a post out of the listing, skipping it: {}", e),
+        }
+    }
+    posts
+}
+
+/// Somewhere on reddit we pull posts from
+#[derive(Debug, Clone)]
+pub enum Source {
+    User(String),
+    UserComments(String),
+    Subreddit(String),
+    // A reddit search, newest results first
+    Search(String),
+    // Somebody's multireddit, a bunch of subreddits in one listing
+    Multi { user: String, name: String },
+}
+
+impl Source {
+    /// Read a source off the command line or a rule, like u/someone, u/someone/comments,
+    /// r/something, search:query or someone/m/multi
+    pub fn parse(s: &str) -> Option<Source> {
+        let s = s.trim();
+        if let Some(q) = s.strip_prefix("search:") {
+            return Some(Source::Search(String::from(q.trim())));
+        }
+        let s = s.trim_matches('/');
+        if let Some(m) = Source::parse_multi(s) {
+            return Some(m);
+        }
+        if let Some(u) = s.strip_prefix("u/").or(s.strip_prefix("user/")) {
+            if let Some(u) = u.strip_suffix("/comments") {
+                return Some(Source::UserComments(String::from(u)));
+            }
+            return Some(Source::User(String::from(u)));
+        }
+        if let Some(r) = s.strip_prefix("r/") {
+            return Some(Source::Subreddit(String::from(r)));
+        }
+        None
+    }
+
+    /// A multireddit like user/m/name, with or without the u/ in front
+    pub fn parse_multi(s: &str) -> Option<Source> {
+        let s = s.trim_start_matches('/');
+        let s = s.strip_prefix("u/").or(s.strip_prefix("user/")).unwrap_or(s);
+        match s.trim_end_matches('/').split('/').collect::<Vec<_>>().as_slice() {
+            [user, "m", name] => Some(Source::Multi { user: user.to_string(), name: name.to_string() }),
+            _ => None,
+        }
+    }
+
+    fn listing_path(&self) -> String {
+        match self {
+            Source::User(u) => format!("/user/{}/submitted", u),
+            Source::UserComments(u) => format!("/user/{}/comments", u),
+            Source::Subreddit(s) => format!("/r/{}/new", s),
+            Source::Search(q) => format!("/search?q={}&sort=new", url_encode(q)),
+            Source::Multi { user, name } => format!("/user/{}/m/{}/new", user, name),
+        }
+    }
+
+    // The listing with some more query string tacked on, searches already have one
+    fn listing_query(&self, query: &str) -> String {
+        let path = self.listing_path();
+        match path.contains('?') {
+            true => format!("{}&{}", path, query),
+            false => format!("{}?{}", path, query),
+        }
+    }
+
+    /// Whether a post is one this source would turn up, going by who posted it and where, so it
+    /// doesn't matter which of our sources happened to see it first. Searches and multis only
+    /// have where we actually saw it to go on
+    pub fn covers(&self, post: &SnifferPost) -> bool {
+        let name = self.to_string();
+        let seen = post.source.eq_ignore_ascii_case(&name) || post.seen_in.iter().any(|s| s.eq_ignore_ascii_case(&name));
+        match self {
+            Source::User(u) => seen || (!post.comment && post.author.eq_ignore_ascii_case(u)),
+            Source::UserComments(u) => seen || (post.comment && post.author.eq_ignore_ascii_case(u)),
+            Source::Subreddit(s) => seen || (!post.comment && post.subreddit.eq_ignore_ascii_case(s)),
+            Source::Search(_) | Source::Multi { .. } => seen,
+        }
+    }
+
+    // The user behind this source, if it's one
+    fn user(&self) -> Option<&str> {
+        match self {
+            Source::User(u) | Source::UserComments(u) => Some(u),
+            Source::Subreddit(_) | Source::Search(_) | Source::Multi { .. } => None,
+        }
+    }
+}
+
+// Reddit doesn't care what case names are in, so u/Someone and u/someone are the same source
+impl PartialEq for Source {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string().eq_ignore_ascii_case(&other.to_string())
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::User(u) => write!(f, "/u/{}", u),
+            Source::UserComments(u) => write!(f, "/u/{} comments", u),
+            Source::Subreddit(s) => write!(f, "/r/{}", s),
+            Source::Search(q) => write!(f, "search \"{}\"", q),
+            Source::Multi { user, name } => write!(f, "/u/{}/m/{}", user, name),
+        }
+    }
+}
+
+// Roux doesn't do comment listings the way we want, so we read the bits we care about ourselves
+#[derive(Deserialize)]
+struct CommentListing {
+    data: CommentListingData,
+}
+
+#[derive(Deserialize)]
+struct CommentListingData {
+    children: Vec<CommentChild>,
+}
+
+#[derive(Deserialize)]
+struct CommentChild {
+    data: CommentData,
+}
+
+#[derive(Deserialize)]
+struct CommentData {
+    id: String,
+    body: String,
+    author: String,
+    subreddit: String,
+    created_utc: f64,
+    score: i64,
+    permalink: String,
+    // Only user listings have these, /api/info leaves them off
+    #[serde(default)]
+    link_title: String,
+    #[serde(default)]
+    link_permalink: String,
+    #[serde(default)]
+    over_18: bool,
+    author_flair_text: Option<String>,
+    author_flair_template_id: Option<String>,
+    #[serde(default)]
+    stickied: bool,
+    distinguished: Option<String>,
+}
+
+impl SnifferPost {
+    fn from_comment(comment: CommentData) -> SnifferPost {
+        SnifferPost {
+            title: format!("Comment on: {}", comment.link_title),
+            body: Some(comment.body),
+            subreddit: comment.subreddit,
+            // The thread it's in, so people can see what it's replying to
+            url: Some(comment.link_permalink),
+            id: comment.id,
+            timestamp: comment.created_utc as u64,
+            author: comment.author,
+            score: comment.score,
+            // Show a few parents up for context
+            permalink: format!("{}?context=3", comment.permalink),
+            author_icon: None,
+            nsfw: comment.over_18,
+            source: String::new(),
+            seen_in: Vec::new(),
+            comment: true,
+            // Comments don't have link flair of their own
+            flair: None,
+            flair_id: None,
+            author_flair: comment.author_flair_text,
+            author_flair_id: comment.author_flair_template_id,
+            edited: None,
+            revisions: Vec::new(),
+            removed: None,
+            gallery: Vec::new(),
+            crosspost: None,
+            video: None,
+            poll: None,
+            duplicates: Vec::new(),
+            awards: Vec::new(),
+            stickied: comment.stickied,
+            distinguished: comment.distinguished,
+        }
+    }
+}
+
+/// Hold new posts for a while and only let them through if they've scored well by then
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScoreGate {
+    pub delay_minutes: u64,
+    pub min_score: i64,
+}
+
+// Every source keeps its own cursor and cache so they don't step on each other
+struct SourceState {
+    source: Source,
+    last_post_timestamp: u64,
+    post_cache: Vec<SnifferPost>,
+    // We stopped watching this for a while, so the next pull just catches the cache up
+    resync: bool,
+    // Fullname of the newest thing we've seen, we only ask reddit for what came after it
+    anchor: Option<String>,
+    // When the anchor was posted as of last run, for when the anchor itself has gone missing
+    resume_from: u64,
+    // Reddit told us this one's banned, private or gone, so we stopped asking
+    dead: bool,
+}
+
+impl SourceState {
+    fn new(source: Source, anchors: &Anchors) -> SourceState {
+        let cursor = anchors.get(&source);
+        return SourceState {
+            resume_from: cursor.as_ref().map(|c| c.timestamp).unwrap_or(0),
+            anchor: cursor.map(|c| c.fullname),
+            source: source,
+            last_post_timestamp: 0,
+            post_cache: Vec::new(),
+            resync: false,
+            dead: false,
+        }
+    }
+}
+
+pub struct RedditScraper {
+    sources: Vec<SourceState>,
+    muted_authors: MutedAuthors,
+    disabled_users: DisabledUsers,
+    api: RedditApi,
+    score_gate: Option<ScoreGate>,
+    // How many times we've updated, for spacing out the rechecks
+    polls: u64,
+    // New posts waiting on the score gate, with when they're up for a re-check. Kept in the
+    // store too so a restart doesn't lose them
+    held: Vec<(u64, SnifferPost)>,
+    anchors: Anchors,
+    // What we've relayed lately, to catch lookalikes coming in from another source
+    recent: Vec<SnifferPost>,
+    // And everything we've ever relayed, so nothing goes out twice even across restarts
+    store: Arc<dyn Store>,
+    duplicate_window: u64,
+    // Only worth a request per post if something's going to show them
+    author_icons: bool,
+    // Things the admin should hear about, see take_alerts
+    alerts: Vec<String>,
+}
+
+impl PartialEq for SnifferPost {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl fmt::Display for SnifferPost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {:?}, {}, {:?}", self.title, self.body, self.subreddit, self.url)    
+    }
+}
+
+impl RedditScraper {
+
+    pub async fn new(
+        sources: Vec<Source>,
+        credentials: Option<RedditCredentials>,
+        muted_authors: MutedAuthors,
+        disabled_users: DisabledUsers,
+        score_gate: Option<ScoreGate>,
+        anchor_file: &str,
+        client_config: &ClientConfig,
+        duplicate_window_minutes: u64,
+        author_icons: bool,
+        store: Arc<dyn Store>,
+    ) -> RedditScraper {
+        warn!("Creating the reddit scraper, logged in: {}", credentials.is_some());
+
+        let anchors = Anchors::load(store.clone(), anchor_file);
+        let mut scraper = RedditScraper {
+            sources: sources.into_iter().map(|s| SourceState::new(s, &anchors)).collect(),
+            muted_authors: muted_authors,
+            disabled_users: disabled_users,
+            api: RedditApi::new(credentials, client_config),
+            score_gate: score_gate,
+            polls: 0,
+            held: store.held_posts().into_iter().map(|(due, p)| (due as u64, p)).collect(),
+            anchors: anchors,
+            recent: Vec::new(),
+            store: store,
+            duplicate_window: duplicate_window_minutes * 60,
+            author_icons: author_icons,
+            alerts: Vec::new(),
+        };
+
+        scraper.init(0).await;
+        scraper
+    }
+
+    /// Swap in a new set of sources and settings from a reloaded config. Sources we already
+    /// had keep their caches, new ones get pulled once so their history doesn't all come out
+    /// as new
+    pub async fn reconfigure(&mut self, sources: Vec<Source>, score_gate: Option<ScoreGate>, duplicate_window_minutes: u64) {
+        let before = self.sources.len();
+        self.sources.retain(|s| sources.contains(&s.source));
+        let kept = self.sources.len();
+        for source in sources {
+            if !self.sources.iter().any(|s| s.source == source) {
+                self.sources.push(SourceState::new(source, &self.anchors));
+            }
+        }
+        warn!("Reconfigured the scraper, dropped {} sources and added {}", before - kept, self.sources.len() - kept);
+        self.score_gate = score_gate;
+        self.duplicate_window = duplicate_window_minutes * 60;
+        self.init(kept).await;
+    }
+
+    // Pull the starting listing for every source from `first` on
+    async fn init(&mut self, first: usize) {
+        let api = &self.api;
+        let pulls = join_all(self.sources[first..].iter().map(|s| pull_posts(api, &s.source))).await;
+        for (state, pulled) in self.sources[first..].iter_mut().zip(pulls) {
+            // Get from reddit api
+            match pulled {
+                Ok(mut p) => {
+                    // Format the hyperlink text of all our pulled posts for consistency
+                    for post in p.iter_mut() {
+                        post.format_urls();
+                    }
+
+                    // Anything newer than where we left off last run hasn't been posted yet, leave it
+                    // out so the first anchored poll picks it up as new
+                    let saved = state.anchor.as_ref().and_then(|a| p.iter().position(|x| x.fullname() == *a));
+                    match (&state.anchor, saved) {
+                        (Some(a), Some(index)) => {
+                            let missed = p.split_off(index + 1);
+                            warn!("Picking {} back up from {}, {} posts since then", state.source, a, missed.len());
+                        }
+                        (Some(a), None) if state.resume_from > 0 => {
+                            // The anchor got deleted, go by when it was posted instead
+                            let resume_from = state.resume_from;
+                            let index = p.iter().position(|x| x.timestamp > resume_from).unwrap_or(p.len());
+                            let missed = p.split_off(index);
+                            warn!("Anchor {} for {} isn't in its listing anymore, picking back up from {}, {} posts since then", a, state.source, resume_from, missed.len());
+                            state.anchor = None;
+                        }
+                        (Some(a), None) => {
+                            warn!("Anchor {} for {} isn't in its listing anymore, starting from the newest post", a, state.source);
+                            state.anchor = None;
+                        }
+                        (None, _) => {}
+                    }
+
+                    // Add our pulled posts to our cache
+                    state.post_cache.append(&mut p);
+
+                    // update our most recent timestamp
+                    if let Some(last) = state.post_cache.last() {
+                        state.last_post_timestamp = last.timestamp;
+                        if state.anchor.is_none() {
+                            state.anchor = Some(last.fullname());
+                            self.anchors.set(&state.source, &last.fullname(), last.timestamp);
+                        }
+                    }
+
+                    warn!("Pulled {} intial posts from {}", state.post_cache.len(), state.source);
+                }
+                Err(e) => {
+                    error!("Got error (probably ratelimit) pulling {} - {:?}", state.source, e);
+                }
+            }
+        }
+    }
+
+    pub async fn update(&mut self) -> Result<Option<Vec<PostEvent>>, Error> {
+
+        debug!("Updating reddit posts");
+
+        // Users who've been switched off get skipped until they're back on
+        let mut enabled = Vec::<usize>::new();
+        for (index, state) in self.sources.iter_mut().enumerate() {
+            if state.dead {
+                continue;
+            }
+            match state.source.user() {
+                Some(u) if self.disabled_users.is_disabled(u) => {
+                    debug!("{} is disabled, skipping it", state.source);
+                    state.resync = true;
+                }
+                _ => enabled.push(index),
+            }
+        }
+
+        // Normally we just ask for what's newer than each source's anchor, but every so often we
+        // look at the whole listing. That's how we notice deletions, and it gets us unstuck if the
+        // anchor post itself is gone, since reddit just gives back nothing after a deleted one
+        let full = (self.polls + 1) % RECHECK_EVERY == 0;
+        let fulls: Vec<bool> = enabled.iter()
+            .map(|i| full || self.sources[*i].resync || self.sources[*i].anchor.is_none())
+            .collect();
+
+        // Hit every source at once, no sense waiting on them one by one
+        let api = &self.api;
+        let sources = &self.sources;
+        let pulls = join_all(enabled.iter().zip(fulls.iter()).map(|(i, full)| {
+            let state = &sources[*i];
+            async move {
+                match (*full, &state.anchor) {
+                    (false, Some(anchor)) => pull_since(api, &state.source, anchor).await,
+                    _ => pull_posts(api, &state.source).await,
+                }
+            }
+        })).await;
+
+        // Our vec of things that happened to the posts since last time
+        let mut events = Vec::<PostEvent>::new();
+        let mut first_error = None;
+        let mut any_ok = false;
+        // Sources reddit says are never coming back
+        let mut gone = Vec::<(usize, Failure, Error)>::new();
+        for ((index, full), pulled) in enabled.into_iter().zip(fulls).zip(pulls) {
+            match pulled {
+                Ok(fresh_posts) if self.sources[index].resync => {
+                    any_ok = true;
+                    self.resync_source(index, fresh_posts);
+                    self.refresh_anchor(index);
+                }
+                Ok(fresh_posts) => {
+                    any_ok = true;
+                    let mut found = self.update_source(index, fresh_posts, full).await;
+                    events.append(&mut found);
+                    self.refresh_anchor(index);
+                }
+                Err(e) => {
+                    let failure = Failure::of(&e);
+                    error!("Couldn't update {} ({:?}): {}", self.sources[index].source, failure, e);
+                    if failure.is_permanent() {
+                        gone.push((index, failure, e));
+                    }
+                    else if first_error.is_none() {
+                        first_error = Some(e);
+                    }
+                }
+            }
+        }
+
+        // If everything's 403ing it's us that's banned, not them, so only give up on sources
+        // when some others are still working
+        if any_ok {
+            for (index, failure, e) in gone {
+                let state = &mut self.sources[index];
+                state.dead = true;
+                let reason = match failure {
+                    Failure::NotFound => "doesn't exist anymore",
+                    _ => "is banned or private",
+                };
+                warn!("Giving up on {}, it {}", state.source, reason);
+                self.alerts.push(format!("Stopped watching {}, reddit says it {} ({})", state.source, reason, e));
+            }
+        }
+        else if let Some((_, _, e)) = gone.into_iter().next() {
+            if first_error.is_none() {
+                first_error = Some(e);
+            }
+        }
+
+        // One source acting up shouldn't count as the whole poll failing
+        if let Some(e) = first_error {
+            if !any_ok {
+                return Err(e);
+            }
+        }
+
+        // Every so often go back over recent posts, listings only show so much
+        self.polls += 1;
+        if self.polls % RECHECK_EVERY == 0 {
+            events.append(&mut self.recheck_recent().await);
+            for index in 0..self.sources.len() {
+                self.refresh_anchor(index);
+            }
+        }
+
+        // New posts sit out the score gate first, comments don't have one
+        if let Some(gate) = &self.score_gate {
+            let due = Utc::now().timestamp() as u64 + gate.delay_minutes * 60;
+            let mut passed = Vec::<PostEvent>::new();
+            for event in events {
+                match event {
+                    PostEvent::New(p) if !p.comment => {
+                        warn!("Holding post {} for {} minutes to see how it scores", p.id, gate.delay_minutes);
+                        self.store.hold_post(&p, due as i64);
+                        self.held.push((due, p));
+                    }
+                    e => passed.push(e),
+                }
+            }
+            events = passed;
+        }
+        events.append(&mut self.release_held().await);
+
+        events = self.drop_relayed(events);
+        if self.duplicate_window > 0 {
+            events = self.suppress_duplicates(events);
+        }
+        self.archive(&events);
+
+        if !events.is_empty() {
+            return Ok(Some(events));
+        }
+        return Ok(None);
+    }
+
+    // Fetch recent posts straight from reddit and see if any were edited or taken down since we last looked
+    async fn recheck_recent(&mut self) -> Vec<PostEvent> {
+        let cutoff = (Utc::now().timestamp() as u64).saturating_sub(RECHECK_WINDOW);
+        let ids: Vec<String> = self.sources.iter()
+            .flat_map(|s| s.post_cache.iter())
+            .filter(|p| !p.comment && p.timestamp >= cutoff)
+            .map(|p| format!("t3_{}", p.id))
+            .collect();
+        let mut current = Vec::<SnifferPost>::new();
+        for batch in ids.chunks(INFO_BATCH_SIZE) {
+            match self.api.get(&format!("/api/info?id={}", batch.join(","))).await {
+                Ok(r) => match r.json::<serde_json::Value>().await {
+                    Ok(v) => current.append(&mut posts_from_listing(v)),
+                    Err(e) => error!("Couldn't read recent posts to recheck: {}", e),
+                },
+                Err(e) => error!("Couldn't recheck recent posts: {}", e),
+            }
+        }
+
+        let mut events = Vec::<PostEvent>::new();
+        // Posts in more than one of our sources only get their awards announced once
+        let mut awarded = HashSet::<String>::new();
+        for mut fresh in current {
+            // Taken down, what we have cached is the only copy of what it said now
+            if let Some(removal) = fresh.removed {
+                for state in self.sources.iter_mut() {
+                    if let Some(index) = state.post_cache.iter().position(|x| x.id == fresh.id) {
+                        let mut post = state.post_cache.remove(index);
+                        warn!("Recheck found post {} was {}", post.id, removal.describe());
+                        post.removed = Some(removal);
+                        events.push(PostEvent::Deleted(post, removal));
+                    }
+                }
+                continue;
+            }
+            fresh.format_urls();
+            self.store.record_score(&fresh);
+            for state in self.sources.iter_mut() {
+                if let Some(x) = state.post_cache.iter_mut().find(|x| x.id == fresh.id) {
+                    let new_awards = SnifferPost::new_awards(x, &fresh);
+                    x.awards = fresh.awards.clone();
+                    x.score = fresh.score;
+                    if !new_awards.is_empty() && awarded.insert(x.id.clone()) {
+                        warn!("Recheck found post {} got {} new awards", x.id, new_awards.len());
+                        events.push(PostEvent::Awarded { post: x.clone(), new_awards: new_awards });
+                    }
+                    if x.title != fresh.title || x.body != fresh.body {
+                        warn!("Recheck found post {} was edited", x.id);
+                        let before = x.clone();
+                        x.apply_edit(&fresh);
+                        events.push(PostEvent::Edited { before: before, after: x.clone() });
+                    }
+                    else if fresh.edited != x.edited {
+                        // Edited and put back the way it was, nothing to show for it
+                        x.edited = fresh.edited;
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    // Check back on held posts that are due, and let through the ones that scored well enough
+    async fn release_held(&mut self) -> Vec<PostEvent> {
+        let min_score = match &self.score_gate {
+            Some(g) => g.min_score,
+            None => {
+                // The gate's been turned off, whatever was waiting on it goes out as it is
+                if !self.held.is_empty() {
+                    warn!("No score gate anymore, letting {} held posts through", self.held.len());
+                }
+                let held: Vec<SnifferPost> = self.held.drain(..).map(|(_, p)| p).collect();
+                for post in held.iter() {
+                    self.store.release_post(&post.id);
+                }
+                return held.into_iter().map(PostEvent::New).collect();
+            }
+        };
+        let now = Utc::now().timestamp() as u64;
+        let (due, waiting): (Vec<_>, Vec<_>) = self.held.drain(..).partition(|(t, _)| *t <= now);
+        self.held = waiting;
+
+        let mut events = Vec::<PostEvent>::new();
+        for batch in due.chunks(INFO_BATCH_SIZE) {
+            let ids: Vec<String> = batch.iter().map(|(_, p)| format!("t3_{}", p.id)).collect();
+            let current = match self.api.get(&format!("/api/info?id={}", ids.join(","))).await {
+                Ok(r) => match r.json::<serde_json::Value>().await {
+                    Ok(v) => posts_from_listing(v),
+                    Err(e) => {
+                        error!("Couldn't read held post scores, trying again next time: {}", e);
+                        self.held.extend_from_slice(batch);
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    error!("Couldn't check held post scores, trying again next time: {}", e);
+                    self.held.extend_from_slice(batch);
+                    continue;
+                }
+            };
+
+            for (_, post) in batch {
+                self.store.release_post(&post.id);
+                match current.iter().find(|c| c.id == post.id) {
+                    Some(c) if c.score >= min_score => {
+                        warn!("Held post {} scored {}, letting it through", post.id, c.score);
+                        let mut post = post.clone();
+                        post.score = c.score;
+                        events.push(PostEvent::New(post));
+                    }
+                    Some(c) => warn!("Held post {} only scored {}, dropping it", post.id, c.score),
+                    None => warn!("Held post {} is gone, dropping it", post.id),
+                }
+            }
+        }
+        events
+    }
+
+    /// Anything that happened the admin should hear about, only handed out once
+    pub fn take_alerts(&mut self) -> Vec<String> {
+        self.alerts.drain(..).collect()
+    }
+
+    /// Save where everything got up to, we're stopping
+    pub fn shutdown(&mut self) {
+        self.anchors.flush();
+        // They're in the store, the next run picks them back up
+        if !self.held.is_empty() {
+            warn!("Leaving {} posts waiting on the score gate for next time", self.held.len());
+        }
+    }
+
+    // Swap out new posts we've already relayed from somewhere else for a note on the original
+    fn suppress_duplicates(&mut self, events: Vec<PostEvent>) -> Vec<PostEvent> {
+        let cutoff = (Utc::now().timestamp() as u64).saturating_sub(self.duplicate_window);
+        self.recent.retain(|p| p.timestamp >= cutoff);
+        let mut out = Vec::<PostEvent>::new();
+        for event in events {
+            match event {
+                // Comments are their own thing, they'd never match a post
+                PostEvent::New(p) if !p.comment => {
+                    match self.recent.iter_mut().find(|r| r.same_as(&p)) {
+                        Some(original) => {
+                            warn!("Post {} looks like a duplicate of {}, not relaying it", p.id, original.id);
+                            original.duplicates.push(p.permalink.clone());
+                            out.push(PostEvent::Duplicate { original: original.clone(), duplicate: p });
+                        }
+                        None => {
+                            self.recent.push(p.clone());
+                            out.push(PostEvent::New(p));
+                        }
+                    }
+                }
+                e => out.push(e),
+            }
+        }
+        out
+    }
+
+    // Same post through two of our sources, like a user who posts in a subreddit we watch,
+    // or one we sent out before a restart
+    fn drop_relayed(&self, events: Vec<PostEvent>) -> Vec<PostEvent> {
+        let mut out = Vec::<PostEvent>::new();
+        // Fullname -> where it went in out
+        let mut seen = HashMap::<String, usize>::new();
+        for event in events {
+            match event {
+                PostEvent::New(p) => {
+                    if let Some(&i) = seen.get(&p.fullname()) {
+                        // Rules for either source should still get it
+                        if let PostEvent::New(first) = &mut out[i] {
+                            for source in p.seen_in {
+                                if !first.seen_in.contains(&source) {
+                                    first.seen_in.push(source);
+                                }
+                            }
+                        }
+                        debug!("Already relayed {}", p.id);
+                        continue;
+                    }
+                    if self.store.was_relayed(&p) {
+                        debug!("Already relayed {}", p.id);
+                        continue;
+                    }
+                    seen.insert(p.fullname(), out.len());
+                    out.push(PostEvent::New(p));
+                }
+                e => out.push(e),
+            }
+        }
+        out
+    }
+
+    // Keep the archive up to date with whatever's happened to our posts
+    fn archive(&self, events: &[PostEvent]) {
+        for event in events {
+            match event {
+                PostEvent::New(p) => self.store.save_post(p),
+                PostEvent::Edited { after, .. } => self.store.save_post(after),
+                PostEvent::Awarded { post, .. } => self.store.save_post(post),
+                PostEvent::Duplicate { original, duplicate } => {
+                    self.store.save_post(original);
+                    self.store.mark_relayed(duplicate);
+                }
+                PostEvent::Deleted(p, _) => self.store.save_post(p),
+            }
+        }
+    }
+
+    // Move a source's anchor up to the newest thing we have cached for it, or back if that got deleted
+    fn refresh_anchor(&mut self, index: usize) {
+        let state = &mut self.sources[index];
+        let (newest, timestamp) = match state.post_cache.iter().max_by_key(|p| p.timestamp) {
+            Some(p) => (p.fullname(), p.timestamp),
+            None => return, // Keep what we have, nothing better to go on
+        };
+        if state.anchor.as_ref() != Some(&newest) {
+            debug!("Anchoring {} at {}", state.source, newest);
+            self.anchors.set(&state.source, &newest, timestamp);
+            state.anchor = Some(newest);
+        }
+    }
+
+    // Take a source's listing as the new normal without telling anyone about what's in it
+    fn resync_source(&mut self, index: usize, mut fresh_posts: Vec<SnifferPost>) {
+        let state = &mut self.sources[index];
+        for post in fresh_posts.iter_mut() {
+            post.format_urls();
+        }
+        if let Some(last) = fresh_posts.last() {
+            state.last_post_timestamp = state.last_post_timestamp.max(last.timestamp);
+        }
+        state.post_cache = fresh_posts;
+        state.resync = false;
+        warn!("Caught {} back up, skipped whatever it posted while disabled", state.source);
+    }
+
+    // Check one source's fresh posts against what we had for it, full is whether it's the
+    // whole listing or just what came after the anchor
+    async fn update_source(&mut self, index: usize, mut fresh_posts: Vec<SnifferPost>, full: bool) -> Vec<PostEvent> {
+        let mut events = Vec::<PostEvent>::new();
+        let api = &self.api;
+        let muted_authors = &self.muted_authors;
+        let author_icons = self.author_icons;
+        let state = &mut self.sources[index];
+
+        // Check our new posts with our cache to see if any exist
+        for p in fresh_posts.iter_mut() {
+            // Fix any urls in the post's body first so it lines up with what we cached
+            p.format_urls();
+            // we only need to check the new post timestamps against the last recorded one
+            if p.timestamp > state.last_post_timestamp {
+                // Double-check to make sure that reddit didn't decide to "update" the timestamp on an older post
+                match state.post_cache.iter_mut().find(|x| *x.id == p.id) {
+                    Some(x) => { 
+                        error!("Reddit gave us an incorrectly modified timestamp on existing post {}", x.id);
+                        // update the post with the new timestamp, thanks reddit
+                        error!("Updating {} timestamp to {} from {}", x.id, x.timestamp, p.timestamp);
+                        x.timestamp = p.timestamp;
+                        // Update our last_post_timestamp after correction
+                        state.last_post_timestamp = p.timestamp;
+                    }
+                    None => {
+                        debug!("New post {} from {}", p, state.source);
+                        // Still cache it so we don't see it as new next time, just don't tell anyone
+                        if muted_authors.is_muted(&p.author) {
+                            warn!("Skipping post {} from muted author {}", p.id, p.author);
+                            state.post_cache.push(p.clone());
+                            state.last_post_timestamp = p.timestamp;
+                            continue;
+                        }
+                        if author_icons {
+                            p.author_icon = pull_author_icon(api, &p.author).await;
+                        }
+                        // record our new posts in the cache
+                        state.post_cache.push(p.clone());
+                        warn!("Cached a new post");
+                        // Add our new posts
+                        events.push(PostEvent::New(p.clone()));
+                        // Update the most recent timestamp 
+                        state.last_post_timestamp = p.timestamp;
+                    },
+                }    
+            }
+            // Something we've already seen, check to see if it's been edited
+            else if let Some(x) = state.post_cache.iter_mut().find(|x| *x.id == p.id) {
+                if x.title != p.title || x.body != p.body {
+                    warn!("Post {} was edited", x.id);
+                    let before = x.clone();
+                    x.apply_edit(p);
+                    events.push(PostEvent::Edited { before: before, after: x.clone() });
+                }
+            }
+        }
+
+        // Anything we have cached that's recent enough to still be in the listing, but isn't, got deleted.
+        // Only works on whole listings, an anchored pull doesn't have anything old in it to compare.
+        // Search results come and go as reddit reindexes, so those don't count either
+        let searching = matches!(state.source, Source::Search(_));
+        if let Some(oldest) = fresh_posts.first().filter(|_| full && !searching) {
+            let oldest_timestamp = oldest.timestamp;
+            let mut deleted = Vec::<SnifferPost>::new();
+            state.post_cache.retain(|x| {
+                let gone = x.timestamp >= oldest_timestamp && !fresh_posts.iter().any(|p| p.id == x.id);
+                if gone {
+                    deleted.push(x.clone());
+                }
+                !gone
+            });
+            for x in deleted {
+                warn!("Post {} is gone from {}, must've been deleted", x.id, state.source);
+                events.push(PostEvent::Deleted(x, Removal::Missing));
+            }
+        }
+
+        events
+    }
+
+}
+
+/// Grab the latest listing for a source, oldest first
+async fn pull_posts(api: &RedditApi, source: &Source) -> Result<Vec<SnifferPost>, Error> {
+    let mut new_posts = pull_page(api, &source.listing_path(), source).await?.posts;
+    // Always sort our posts oldest->newest bc reddit just gives them in random order
+    new_posts.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
+    Ok(new_posts)
+}
+
+/// Everything newer than an anchor, following the listing forward a page at a time, oldest first
+async fn pull_since(api: &RedditApi, source: &Source, anchor: &str) -> Result<Vec<SnifferPost>, Error> {
+    let mut posts = Vec::<SnifferPost>::new();
+    let mut before = String::from(anchor);
+    for _ in 0..MAX_ANCHOR_PAGES {
+        let path = source.listing_query(&format!("limit={}&before={}", LISTING_PAGE_SIZE, before));
+        let page = pull_page(api, &path, source).await?;
+        posts.extend(page.posts);
+        before = match page.before {
+            Some(b) => b,
+            None => break,
+        };
+    }
+    posts.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
+    posts.dedup_by(|a, b| a.id == b.id);
+    Ok(posts)
+}
+
+/// One page of a listing, with the cursors for the pages either side of it
+struct Page {
+    posts: Vec<SnifferPost>,
+    // Newer stuff
+    before: Option<String>,
+    // Older stuff
+    after: Option<String>,
+}
+
+async fn pull_page(api: &RedditApi, path: &str, source: &Source) -> Result<Page, Error> {
+    let result = api.get(path).await?;
+    debug!("Response status: {:?}", result.status());
+    debug!("Reponse headers:\n{:?}", result.headers());
+    let listing = result.json::<serde_json::Value>().await?;
+    let before = listing["data"]["before"].as_str().map(String::from);
+    let after = listing["data"]["after"].as_str().map(String::from);
+    let mut posts = match source {
+        Source::UserComments(_) => {
+            match serde_json::from_value::<CommentListing>(listing) {
+                Ok(comments) => comments.data.children.into_iter().map(|c| SnifferPost::from_comment(c.data)).collect::<Vec<_>>(),
+                Err(e) => {
+                    error!("Couldn't parse comments for {}: {}", source, e);
+                    Vec::new()
+                }
+            }
+        }
+        _ => posts_from_listing(listing),
+    };
+    for post in posts.iter_mut() {
+        post.source = source.to_string();
+        post.seen_in = vec![source.to_string()];
+    }
+    Ok(Page {
+        posts: posts,
+        before: before,
+        after: after,
+    })
+}
+
+/// Everywhere on reddit we're told to watch
+pub fn sources(config: &Config) -> Vec<Source> {
+    let mut sources = Vec::<Source>::new();
+    for user in config.watched_users() {
+        let comments = config.watch.comment_users.iter().any(|u| u.trim_start_matches("u/").eq_ignore_ascii_case(&user));
+        sources.push(Source::User(user.clone()));
+        if comments {
+            sources.push(Source::UserComments(user));
+        }
+    }
+    for subreddit in &config.watch.subreddits {
+        sources.push(Source::Subreddit(subreddit.trim_start_matches("r/").to_string()));
+    }
+    for query in &config.watch.searches {
+        sources.push(Source::Search(query.clone()));
+    }
+    for multi in &config.watch.multireddits {
+        match Source::parse_multi(multi) {
+            Some(m) => sources.push(m),
+            None => error!("{} isn't a multireddit, they look like user/m/name", multi),
+        }
+    }
+    // Whatever the rules watch on top of that
+    for rule in &config.rules {
+        match rule.parsed_sources() {
+            Ok(rule_sources) => {
+                for source in rule_sources {
+                    if !sources.contains(&source) {
+                        sources.push(source);
+                    }
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+    sources
+}
+
+#[serenity::async_trait]
+impl PostSource for RedditScraper {
+    fn name(&self) -> &str {
+        "reddit"
+    }
+
+    async fn poll(&mut self) -> Result<Vec<PostEvent>, SourceError> {
+        match self.update().await {
+            Ok(events) => Ok(events.unwrap_or_default()),
+            Err(e) => Err(SourceError {
+                message: e.to_string(),
+                refused: e.status() == Some(reqwest::StatusCode::FORBIDDEN),
+            }),
+        }
+    }
+
+    fn take_alerts(&mut self) -> Vec<String> {
+        RedditScraper::take_alerts(self)
+    }
+
+    async fn shutdown(&mut self) {
+        RedditScraper::shutdown(self)
+    }
+
+    async fn reload(&mut self, config: &Config) -> Result<String, String> {
+        let sources = sources(config);
+        let count = sources.len();
+        self.reconfigure(sources, config.posting.score_gate.clone(), config.polling.duplicate_window_minutes).await;
+        Ok(format!("watching {} reddit sources", count))
+    }
+}
+
+/// Walk back through everything a source has ever posted, a page at a time, oldest first.
+/// Reddit only lets listings go back about 1000 posts, so that's as far as this gets
+pub async fn backfill(credentials: Option<RedditCredentials>, client_config: &ClientConfig, source: &Source) -> Result<Vec<SnifferPost>, Error> {
+    let api = RedditApi::new(credentials, client_config);
+    let mut posts = Vec::<SnifferPost>::new();
+    let mut after: Option<String> = None;
+    loop {
+        let path = match &after {
+            Some(a) => source.listing_query(&format!("limit={}&after={}", LISTING_PAGE_SIZE, a)),
+            None => source.listing_query(&format!("limit={}", LISTING_PAGE_SIZE)),
+        };
+        let page = pull_page(&api, &path, source).await?;
+        warn!("Backfilled {} posts from {}, {} so far", page.posts.len(), source, posts.len() + page.posts.len());
+        posts.extend(page.posts);
+        after = match page.after {
+            Some(n) => Some(n),
+            None => break,
+        };
+        // Go easy on reddit, this is a lot of requests at once
+        tokio::time::sleep(BACKFILL_PAGE_DELAY).await;
+    }
+    posts.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
+    posts.dedup_by(|a, b| a.id == b.id);
+    Ok(posts)
+}
+
+/// Look up the avatar of a reddit user, used to dress up webhook posts
+async fn pull_author_icon(api: &RedditApi, author: &str) -> Option<String> {
+    let about = match api.get(&format!("/user/{}/about", author)).await {
+        Ok(r) => r.json::<serde_json::Value>().await,
+        Err(e) => Err(e),
+    };
+    match about {
+        Ok(v) => {
+            // Reddit html escapes the query string in these for some reason
+            v["data"]["icon_img"].as_str().map(|i| i.replace("&amp;", "&"))
+        }
+        Err(e) => {
+            error!("Couldn't get the icon for {}: {}", author, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use super::oauth::{ClientConfig, RedditApi, RedditCredentials};
+
+// Accounts don't change much, no need to hammer their about pages
+pub const ACCOUNT_CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Keep an eye on the accounts we follow and say something when one changes
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccountAlertConfig {
+    // Where the alerts go, otherwise they get DMed to the admin
+    pub channel: Option<u64>,
+    #[serde(default = "default_karma_milestones")]
+    pub karma_milestones: Vec<i64>,
+}
+
+fn default_karma_milestones() -> Vec<i64> {
+    vec![1_000, 10_000, 50_000, 100_000, 500_000, 1_000_000]
+}
+
+/// What we could tell about an account last time we looked
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AccountStatus {
+    Active { karma: i64 },
+    Suspended,
+    // Deleted accounts and shadowbanned ones both just 404, there's no telling them apart from outside
+    Missing,
+}
+
+impl AccountStatus {
+    fn describe(&self) -> &'static str {
+        match self {
+            AccountStatus::Active { .. } => "active",
+            AccountStatus::Suspended => "suspended",
+            AccountStatus::Missing => "gone (deleted or shadowbanned)",
+        }
+    }
+}
+
+pub struct AccountWatcher {
+    api: RedditApi,
+    users: Vec<String>,
+    milestones: Vec<i64>,
+    last: HashMap<String, AccountStatus>,
+}
+
+impl AccountWatcher {
+    pub fn new(users: Vec<String>, config: &AccountAlertConfig, credentials: Option<RedditCredentials>, client_config: &ClientConfig) -> AccountWatcher {
+        let mut milestones = config.karma_milestones.clone();
+        milestones.sort();
+        return AccountWatcher {
+            api: RedditApi::new(credentials, client_config),
+            users: users,
+            milestones: milestones,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Alerts for every account that changed since we last looked. The first look just
+    /// remembers where everyone's at
+    pub async fn update(&mut self) -> Vec<String> {
+        let mut alerts = Vec::<String>::new();
+        for user in self.users.iter() {
+            let status = match self.api.get(&format!("/user/{}/about", user)).await {
+                Ok(r) => match r.json::<AboutResponse>().await {
+                    Ok(a) if a.data.is_suspended => AccountStatus::Suspended,
+                    Ok(a) => AccountStatus::Active { karma: a.data.total_karma.unwrap_or(a.data.link_karma + a.data.comment_karma) },
+                    Err(e) => {
+                        error!("Couldn't read /u/{}'s account: {}", user, e);
+                        continue;
+                    }
+                },
+                Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => AccountStatus::Missing,
+                Err(e) => {
+                    error!("Couldn't check /u/{}'s account: {}", user, e);
+                    continue;
+                }
+            };
+            if let Some(before) = self.last.insert(user.clone(), status) {
+                match (before, status) {
+                    (AccountStatus::Active { karma: old }, AccountStatus::Active { karma: new }) => {
+                        // Only the biggest one crossed, if a few went by at once
+                        if let Some(m) = self.milestones.iter().rev().find(|m| old < **m && new >= **m) {
+                            alerts.push(format!("🎉 /u/{} just passed {} karma", user, m));
+                        }
+                    }
+                    (before, after) if before != after => {
+                        warn!("/u/{} went from {} to {}", user, before.describe(), after.describe());
+                        alerts.push(format!("⚠️ /u/{} is {} now, was {}", user, after.describe(), before.describe()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[derive(Deserialize)]
+struct AboutResponse {
+    data: AboutData,
+}
+
+#[derive(Deserialize)]
+struct AboutData {
+    // Suspended accounts only come back with a name and this
+    #[serde(default)]
+    is_suspended: bool,
+    total_karma: Option<i64>,
+    #[serde(default)]
+    link_karma: i64,
+    #[serde(default)]
+    comment_karma: i64,
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::Arc;
+
+use crate::store::Store;
+
+use super::Source;
+
+/// Where we got up to with a source, the newest thing we've seen and when it was posted.
+/// The timestamp is what we fall back on when the anchor post itself is gone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub fullname: String,
+    pub timestamp: u64,
+}
+
+/// The newest thing we've seen from each source, kept in the database so a restart picks up
+/// right where we left off instead of skipping whatever got posted while we were down
+pub struct Anchors {
+    store: Arc<dyn Store>,
+    anchors: HashMap<String, Cursor>,
+}
+
+impl Anchors {
+    /// Anchors from the old json file get moved into the database the first time round,
+    /// nothing there at all is fine, we just start fresh
+    pub fn load(store: Arc<dyn Store>, legacy_path: &str) -> Anchors {
+        let mut anchors = store.cursors();
+        if let Ok(f) = File::open(legacy_path) {
+            match serde_json::from_reader::<_, HashMap<String, String>>(f) {
+                Ok(legacy) => {
+                    for (source, fullname) in legacy {
+                        if anchors.contains_key(&source) {
+                            continue;
+                        }
+                        // Never knew when these were posted, the fullname will have to do
+                        let cursor = Cursor { fullname: fullname, timestamp: 0 };
+                        store.set_cursor(&source, &cursor);
+                        anchors.insert(source, cursor);
+                    }
+                    warn!("Moved the anchors in {} into the database, it can go now", legacy_path);
+                }
+                Err(e) => error!("Couldn't read old anchors from {}, ignoring them: {}", legacy_path, e),
+            }
+        }
+        return Anchors {
+            store: store,
+            anchors: anchors,
+        }
+    }
+
+    pub fn get(&self, source: &Source) -> Option<Cursor> {
+        self.anchors.get(&source.to_string()).cloned()
+    }
+
+    /// Writes straight through to the database, these don't change often enough to bother batching
+    pub fn set(&mut self, source: &Source, fullname: &str, timestamp: u64) {
+        let key = source.to_string();
+        let cursor = Cursor {
+            fullname: String::from(fullname),
+            timestamp: timestamp,
+        };
+        if self.anchors.get(&key) == Some(&cursor) {
+            return;
+        }
+        self.store.set_cursor(&key, &cursor);
+        self.anchors.insert(key, cursor);
+    }
+
+    /// Write every cursor out again before we stop, anything that failed to save along the
+    /// way gets another go
+    pub fn flush(&self) {
+        for (source, cursor) in self.anchors.iter() {
+            self.store.set_cursor(source, cursor);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use reqwest::Error;
+use serde::Deserialize;
+
+use super::oauth::{ClientConfig, RedditApi, RedditCredentials};
+
+// Live threads move fast, so these get polled a lot more than listings
+pub const LIVE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// Every this many polls we check if the thread's been closed
+const ABOUT_EVERY: u64 = 30;
+
+/// A live thread to follow, and the channel its updates go to
+#[derive(Deserialize, Debug, Clone)]
+pub struct LiveThreadWatch {
+    pub id: String,
+    pub channel: u64,
+}
+
+/// One new update on a live thread
+#[derive(Debug, Clone)]
+pub struct LiveUpdate {
+    pub thread_title: String,
+    pub author: String,
+    pub body: String,
+    pub timestamp: u64,
+}
+
+impl LiveUpdate {
+    pub fn discord_string(&self) -> String {
+        format!("🔴 **{}** /u/{} <t:{}:R>\n{}", self.thread_title, self.author, self.timestamp, self.body)
+    }
+}
+
+pub struct LiveThreadWatcher {
+    pub watch: LiveThreadWatch,
+    api: RedditApi,
+    title: String,
+    // Fullname of the newest update we've seen, like LiveUpdate_<uuid>
+    anchor: Option<String>,
+    // The first look just finds where the thread's at. Not the same as having an anchor, the thread
+    // could've been empty when we started
+    primed: bool,
+    polls: u64,
+    // The thread's been marked complete, nothing else is coming
+    pub finished: bool,
+}
+
+impl LiveThreadWatcher {
+    pub fn new(watch: LiveThreadWatch, credentials: Option<RedditCredentials>, client_config: &ClientConfig) -> LiveThreadWatcher {
+        return LiveThreadWatcher {
+            title: watch.id.clone(),
+            watch: watch,
+            api: RedditApi::new(credentials, client_config),
+            anchor: None,
+            primed: false,
+            polls: 0,
+            finished: false,
+        }
+    }
+
+    /// Updates since we last looked, oldest first. The first look just finds where the thread's at
+    pub async fn update(&mut self) -> Result<Vec<LiveUpdate>, Error> {
+        if self.polls % ABOUT_EVERY == 0 {
+            self.check_about().await?;
+        }
+        self.polls += 1;
+
+        let path = match (&self.anchor, self.primed) {
+            (Some(a), _) => format!("/live/{}?limit=100&before={}", self.watch.id, a),
+            // Nothing had been posted yet, so everything there is new
+            (None, true) => format!("/live/{}?limit=100", self.watch.id),
+            (None, false) => format!("/live/{}?limit=1", self.watch.id),
+        };
+        let listing = self.api.get(&path).await?.json::<LiveListing>().await?;
+        // Newest first out of reddit
+        let mut updates: Vec<LiveUpdateData> = listing.data.children.into_iter().map(|c| c.data).collect();
+        if let Some(newest) = updates.first() {
+            self.anchor = Some(newest.name.clone());
+        }
+        if !self.primed {
+            self.primed = true;
+            warn!("Following live thread {} ({})", self.watch.id, self.title);
+            return Ok(Vec::new());
+        }
+        updates.reverse();
+        Ok(updates.into_iter()
+            // Stricken updates are the author taking it back
+            .filter(|u| !u.stricken)
+            .map(|u| LiveUpdate {
+                thread_title: self.title.clone(),
+                author: u.author.unwrap_or_else(|| String::from("[deleted]")),
+                body: u.body,
+                timestamp: u.created_utc as u64,
+            })
+            .collect())
+    }
+
+    async fn check_about(&mut self) -> Result<(), Error> {
+        let about = self.api.get(&format!("/live/{}/about", self.watch.id)).await?.json::<LiveAbout>().await?;
+        self.title = about.data.title;
+        if about.data.state == "complete" {
+            warn!("Live thread {} is over", self.watch.id);
+            self.finished = true;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct LiveAbout {
+    data: LiveAboutData,
+}
+
+#[derive(Deserialize)]
+struct LiveAboutData {
+    title: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct LiveListing {
+    data: LiveListingData,
+}
+
+#[derive(Deserialize)]
+struct LiveListingData {
+    children: Vec<LiveListingChild>,
+}
+
+#[derive(Deserialize)]
+struct LiveListingChild {
+    data: LiveUpdateData,
+}
+
+#[derive(Deserialize)]
+struct LiveUpdateData {
+    name: String,
+    author: Option<String>,
+    body: String,
+    created_utc: f64,
+    #[serde(default)]
+    stricken: bool,
+}
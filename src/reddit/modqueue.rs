@@ -0,0 +1,210 @@
+use std::collections::{HashMap, HashSet};
+
+use reqwest::Error;
+use serde::Deserialize;
+
+use super::oauth::{ClientConfig, RedditApi, RedditCredentials};
+
+// Modlog actions that count as something getting taken down
+const REMOVAL_ACTIONS: &[&str] = &["removelink", "removecomment", "spamlink", "spamcomment"];
+
+/// Watch the modqueue and modlog of a subreddit we moderate, needs reddit_auth for a mod account
+#[derive(Deserialize, Debug, Clone)]
+pub struct ModqueueConfig {
+    pub subreddit: String,
+    // Private channel the reports and removals go to
+    pub channel: u64,
+}
+
+/// Something the mods should know about
+#[derive(Debug, Clone)]
+pub enum ModEvent {
+    Reported {
+        title: String,
+        author: String,
+        permalink: String,
+        reports: Vec<String>,
+    },
+    Removed {
+        moderator: String,
+        action: String,
+        author: String,
+        title: String,
+        permalink: String,
+        details: Option<String>,
+    },
+}
+
+impl ModEvent {
+    pub fn discord_string(&self) -> String {
+        match self {
+            ModEvent::Reported { title, author, permalink, reports } => format!(
+                "🚩 **Reported:** {} by /u/{}\n{}\n<https://www.reddit.com{}>",
+                title, author, reports.iter().map(|r| format!("> {}", r)).collect::<Vec<_>>().join("\n"), permalink
+            ),
+            ModEvent::Removed { moderator, action, author, title, permalink, details } => {
+                let mut text = format!("🛑 **{}** by /u/{}: {} by /u/{}", action, moderator, title, author);
+                if let Some(d) = details {
+                    text.push_str(format!(" ({})", d).as_str());
+                }
+                if !permalink.is_empty() {
+                    text.push_str(format!("\n<https://www.reddit.com{}>", permalink).as_str());
+                }
+                text
+            }
+        }
+    }
+}
+
+pub struct ModqueueWatcher {
+    subreddit: String,
+    api: RedditApi,
+    // How many reports we've already told everyone about for each thing in the queue, counting
+    // every user that picked the same reason
+    seen_reports: HashMap<String, usize>,
+    // When the newest modlog entry we've looked at was, and every entry we've looked at from
+    // right then, since a few can land in the same instant
+    last_log_timestamp: f64,
+    last_log_ids: HashSet<String>,
+    // The first look just catches us up, nobody wants the whole queue dumped on them
+    primed: bool,
+}
+
+impl ModqueueWatcher {
+    pub fn new(config: &ModqueueConfig, credentials: RedditCredentials, client_config: &ClientConfig) -> ModqueueWatcher {
+        return ModqueueWatcher {
+            subreddit: config.subreddit.trim_start_matches("r/").to_string(),
+            api: RedditApi::new(Some(credentials), client_config),
+            seen_reports: HashMap::new(),
+            last_log_timestamp: 0.0,
+            last_log_ids: HashSet::new(),
+            primed: false,
+        }
+    }
+
+    /// New reports and removals since we last looked
+    pub async fn update(&mut self) -> Result<Vec<ModEvent>, Error> {
+        let mut events = self.check_queue().await?;
+        events.append(&mut self.check_log().await?);
+        if !self.primed {
+            warn!("Caught up on the /r/{} modqueue and modlog", self.subreddit);
+            self.primed = true;
+            return Ok(Vec::new());
+        }
+        Ok(events)
+    }
+
+    async fn check_queue(&mut self) -> Result<Vec<ModEvent>, Error> {
+        let queue = self.api.get(&format!("/r/{}/about/modqueue?limit=100", self.subreddit)).await?
+            .json::<Listing<QueueItem>>().await?;
+        let mut events = Vec::<ModEvent>::new();
+        let mut seen = HashMap::<String, usize>::new();
+        for item in queue.data.children.into_iter().map(|c| c.data) {
+            // Mod reports come as [reason, mod], user ones as [reason, count, ...]
+            let reports: Vec<String> = item.mod_reports.iter()
+                .map(|r| format!("{} (/u/{})", report_field(r, 0), report_field(r, 1)))
+                .chain(item.user_reports.iter().map(|r| format!("{} ({}x)", report_field(r, 0), report_field(r, 1))))
+                .collect();
+            // Another user picking a reason that's already there just bumps its count
+            let count = item.mod_reports.len() + item.user_reports.iter()
+                .map(|r| r.get(1).and_then(|c| c.as_u64()).unwrap_or(1) as usize)
+                .sum::<usize>();
+            let before = self.seen_reports.get(&item.name).cloned().unwrap_or(0);
+            if count > before {
+                events.push(ModEvent::Reported {
+                    // Comments don't have a title of their own
+                    title: item.title.clone().or(item.body.clone()).unwrap_or_default(),
+                    author: item.author.clone(),
+                    permalink: item.permalink.clone(),
+                    reports: reports.clone(),
+                });
+            }
+            seen.insert(item.name, count);
+        }
+        // Only hang onto what's still in the queue
+        self.seen_reports = seen;
+        Ok(events)
+    }
+
+    async fn check_log(&mut self) -> Result<Vec<ModEvent>, Error> {
+        let log = self.api.get(&format!("/r/{}/about/log?limit=100", self.subreddit)).await?
+            .json::<Listing<LogEntry>>().await?;
+        let mut entries: Vec<LogEntry> = log.data.children.into_iter()
+            .map(|c| c.data)
+            .filter(|e| e.created_utc > self.last_log_timestamp
+                || (e.created_utc == self.last_log_timestamp && !self.last_log_ids.contains(&e.id)))
+            .collect();
+        entries.sort_by(|a, b| a.created_utc.partial_cmp(&b.created_utc).unwrap());
+        if let Some(last) = entries.last() {
+            if last.created_utc > self.last_log_timestamp {
+                self.last_log_timestamp = last.created_utc;
+                self.last_log_ids.clear();
+            }
+            let newest = self.last_log_timestamp;
+            self.last_log_ids.extend(entries.iter().filter(|e| e.created_utc == newest).map(|e| e.id.clone()));
+        }
+        Ok(entries.into_iter()
+            .filter(|e| REMOVAL_ACTIONS.contains(&e.action.as_str()))
+            .map(|e| ModEvent::Removed {
+                moderator: e.moderator,
+                action: e.action,
+                author: e.target_author.unwrap_or_default(),
+                title: e.target_title.or(e.target_body).unwrap_or_default(),
+                permalink: e.target_permalink.unwrap_or_default(),
+                details: e.details,
+            })
+            .collect())
+    }
+}
+
+fn report_field(report: &[serde_json::Value], index: usize) -> String {
+    match report.get(index) {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => String::from("no reason"),
+        Some(v) => v.to_string(),
+    }
+}
+
+#[derive(Deserialize)]
+struct Listing<T> {
+    data: ListingData<T>,
+}
+
+#[derive(Deserialize)]
+struct ListingData<T> {
+    children: Vec<ListingChild<T>>,
+}
+
+#[derive(Deserialize)]
+struct ListingChild<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct QueueItem {
+    name: String,
+    author: String,
+    permalink: String,
+    title: Option<String>,
+    body: Option<String>,
+    // Arrays of mixed stuff, and reddit's been known to tack more onto the end
+    #[serde(default)]
+    mod_reports: Vec<Vec<serde_json::Value>>,
+    #[serde(default)]
+    user_reports: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Deserialize)]
+struct LogEntry {
+    // Like ModAction_<uuid>
+    id: String,
+    action: String,
+    #[serde(rename = "mod")]
+    moderator: String,
+    created_utc: f64,
+    target_author: Option<String>,
+    target_title: Option<String>,
+    target_body: Option<String>,
+    target_permalink: Option<String>,
+    details: Option<String>,
+}
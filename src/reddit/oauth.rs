@@ -0,0 +1,286 @@
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Semaphore};
+
+use reqwest::{Client, Error, Response, StatusCode};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+
+use super::APP_USER_AGENT;
+
+// Grab a new token a little before the old one runs out
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+// Start spacing requests out and complaining when we're down to this many for the window
+const RATELIMIT_LOW: f64 = 10.0;
+// How long to sit out a 429 if reddit doesn't say
+const RATELIMITED_WAIT: Duration = Duration::from_secs(60);
+// Reddit falling over on its end is usually over in a few seconds, try again a couple times
+const SERVER_RETRIES: u32 = 3;
+const SERVER_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// What kind of trouble reddit gave us, they each get dealt with differently
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    // 429, wait it out
+    RateLimited,
+    // 403, we're banned or it's private, not getting better on its own
+    Forbidden,
+    // 404, the user or subreddit is gone
+    NotFound,
+    // 5xx, reddit's problem, try again
+    Server,
+    // Timeouts, dropped connections and anything else
+    Other,
+}
+
+impl Failure {
+    pub fn of(e: &Error) -> Failure {
+        match e.status() {
+            Some(StatusCode::TOO_MANY_REQUESTS) => Failure::RateLimited,
+            Some(StatusCode::FORBIDDEN) => Failure::Forbidden,
+            Some(StatusCode::NOT_FOUND) => Failure::NotFound,
+            Some(s) if s.is_server_error() => Failure::Server,
+            _ => Failure::Other,
+        }
+    }
+
+    /// Whether trying again in a bit has any chance of working
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Failure::Forbidden | Failure::NotFound)
+    }
+}
+
+/// Credentials for a reddit "script" app, gets us the oauth api and its higher rate limits
+#[derive(Deserialize, Debug, Clone)]
+pub struct RedditCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// How we present ourselves to reddit, they block generic user agents so every deployment
+/// should really set its own
+#[derive(Deserialize, Debug, Clone)]
+pub struct ClientConfig {
+    pub user_agent: Option<String>,
+    // Give up on reddit answering after this, so a hang counts as a failure
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    // Most requests we'll have out to reddit at once
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+fn default_max_concurrent() -> usize {
+    4
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            user_agent: None,
+            timeout_secs: default_timeout(),
+            max_concurrent: default_max_concurrent(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Whatever's configured, otherwise something that at least follows reddit's format
+    pub fn user_agent(&self, credentials: Option<&RedditCredentials>) -> String {
+        if let Some(u) = &self.user_agent {
+            return u.clone();
+        }
+        // Reddit wants logged in clients to say who they are
+        match credentials {
+            Some(c) => format!("{}:{}:{} (by /u/{})", "script", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), c.username),
+            None => String::from(APP_USER_AGENT),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct Token {
+    access_token: String,
+    expires: Instant,
+}
+
+/// What reddit last told us about our quota
+#[derive(Debug, Clone, Copy)]
+struct RateLimit {
+    remaining: f64,
+    reset: Instant,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<RateLimit> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<f64>().ok();
+        Some(RateLimit {
+            remaining: header("x-ratelimit-remaining")?,
+            reset: Instant::now() + Duration::from_secs_f64(header("x-ratelimit-reset")?),
+        })
+    }
+
+    // How long to hold off before the next request. Nothing if we've got plenty left, the rest
+    // of the window if we're out, and an even share of it if we're getting low
+    fn delay(&self) -> Option<Duration> {
+        let until_reset = self.reset.saturating_duration_since(Instant::now());
+        if until_reset.is_zero() || self.remaining > RATELIMIT_LOW {
+            return None;
+        }
+        if self.remaining < 1.0 {
+            return Some(until_reset);
+        }
+        Some(until_reset.div_f64(self.remaining))
+    }
+}
+
+/// Everything we ask reddit goes through here, logged in if we have credentials and anonymous if we don't
+pub struct RedditApi {
+    reqwest: Client,
+    credentials: Option<RedditCredentials>,
+    token: Mutex<Option<Token>>,
+    rate_limit: Mutex<Option<RateLimit>>,
+    // Keeps us under max_concurrent requests, however many sources want to go at once
+    in_flight: Semaphore,
+}
+
+impl RedditApi {
+    pub fn new(credentials: Option<RedditCredentials>, config: &ClientConfig) -> RedditApi {
+        let user_agent = config.user_agent(credentials.as_ref());
+        warn!("Creating the reddit client with user agent: {}", user_agent);
+        return RedditApi {
+            reqwest: Client::builder()
+                .user_agent(user_agent)
+                .timeout(config.timeout())
+                //.connection_verbose(true)
+                //.use_native_tls()
+                .http1_title_case_headers()
+                .http2_prior_knowledge()
+                .http2_adaptive_window(true)
+                .build().expect("Error building reqwest client"),
+            credentials: credentials,
+            token: Mutex::new(None),
+            rate_limit: Mutex::new(None),
+            // Zero would block forever
+            in_flight: Semaphore::new(config.max_concurrent.max(1)),
+        }
+    }
+
+    /// GET something off the api, path is like /user/someone/submitted with an optional query string
+    pub async fn get(&self, path: &str) -> Result<Response, Error> {
+        // We never close it, so this can't fail
+        let _permit = self.in_flight.acquire().await.expect("Reddit request semaphore closed");
+        let mut delay = SERVER_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            self.wait_for_quota().await;
+            match self.get_once(path).await {
+                Err(e) if Failure::of(&e) == Failure::Server && attempt < SERVER_RETRIES => {
+                    warn!("Reddit had a problem with {} ({}), trying again in {:?}", path, e, delay);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn get_once(&self, path: &str) -> Result<Response, Error> {
+        let response = match &self.credentials {
+            Some(c) => {
+                let token = self.access_token(c).await?;
+                self.reqwest.get(format!("https://oauth.reddit.com{}", path))
+                    .bearer_auth(token)
+                    .send().await?
+            }
+            None => {
+                // The .json goes before any query string
+                let url = match path.split_once('?') {
+                    Some((p, q)) => format!("https://www.reddit.com{}.json?{}", p, q),
+                    None => format!("https://www.reddit.com{}.json", path),
+                };
+                self.reqwest.get(url).send().await?
+            }
+        };
+        self.record_quota(response.headers()).await;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            self.record_ratelimited(response.headers()).await;
+        }
+        // Turn bad statuses into errors so whoever's asking can see what happened
+        response.error_for_status()
+    }
+
+    // Hold the request back if reddit said we're running out
+    async fn wait_for_quota(&self) {
+        let limit = *self.rate_limit.lock().await;
+        if let Some(delay) = limit.and_then(|l| l.delay()) {
+            warn!("Reddit rate limit is low, waiting {:?} before the next request", delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // We went over, hold everything until reddit says we're good again
+    async fn record_ratelimited(&self, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<f64>().ok();
+        let wait = header("retry-after").or(header("x-ratelimit-reset"))
+            .map(Duration::from_secs_f64)
+            .unwrap_or(RATELIMITED_WAIT);
+        error!("Reddit rate limited us, holding off for {:?}", wait);
+        *self.rate_limit.lock().await = Some(RateLimit {
+            remaining: 0.0,
+            reset: Instant::now() + wait,
+        });
+    }
+
+    async fn record_quota(&self, headers: &HeaderMap) {
+        let limit = match RateLimit::from_headers(headers) {
+            Some(l) => l,
+            None => return, // Not every response has them
+        };
+        if limit.remaining <= RATELIMIT_LOW {
+            warn!("Only {} reddit requests left, resets in {:?}", limit.remaining, limit.reset.saturating_duration_since(Instant::now()));
+        }
+        *self.rate_limit.lock().await = Some(limit);
+    }
+
+    // Hand out the token we have, or log in again if it's about to run out
+    async fn access_token(&self, credentials: &RedditCredentials) -> Result<String, Error> {
+        let mut token = self.token.lock().await;
+        if let Some(t) = token.as_ref() {
+            if Instant::now() + TOKEN_EXPIRY_MARGIN < t.expires {
+                return Ok(t.access_token.clone());
+            }
+        }
+        warn!("Getting a new reddit access token for /u/{}", credentials.username);
+        let response = self.reqwest.post("https://www.reddit.com/api/v1/access_token")
+            .basic_auth(&credentials.client_id, Some(&credentials.client_secret))
+            .form(&[
+                ("grant_type", "password"),
+                ("username", credentials.username.as_str()),
+                ("password", credentials.password.as_str()),
+            ])
+            .send().await?
+            .error_for_status()?
+            .json::<TokenResponse>().await?;
+        let access_token = response.access_token.clone();
+        *token = Some(Token {
+            access_token: response.access_token,
+            expires: Instant::now() + Duration::from_secs(response.expires_in),
+        });
+        Ok(access_token)
+    }
+}
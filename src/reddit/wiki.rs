@@ -0,0 +1,138 @@
+use serde::Deserialize;
+
+use super::diff_lines;
+use super::oauth::{ClientConfig, RedditApi, RedditCredentials};
+
+// How much of a diff makes it into the summary
+const DIFF_SNIPPET_LENGTH: usize = 1500;
+
+/// A wiki page to keep an eye on, and the channel its changes go to
+#[derive(Deserialize, Debug, Clone)]
+pub struct WikiWatch {
+    pub subreddit: String,
+    pub page: String,
+    pub channel: u64,
+}
+
+/// Someone changed a wiki page we're watching
+#[derive(Debug, Clone)]
+pub struct WikiChange {
+    pub watch: WikiWatch,
+    pub author: String,
+    pub reason: Option<String>,
+    pub revision: String,
+    pub diff: String,
+}
+
+impl WikiChange {
+    pub fn discord_string(&self) -> String {
+        let mut text = format!("📝 /u/{} edited the /r/{} wiki page **{}**", self.author, self.watch.subreddit, self.watch.page);
+        if let Some(r) = &self.reason {
+            text.push_str(format!(" ({})", r).as_str());
+        }
+        let mut diff = self.diff.clone();
+        if diff.len() > DIFF_SNIPPET_LENGTH {
+            let mut end = DIFF_SNIPPET_LENGTH;
+            while !diff.is_char_boundary(end) {
+                end -= 1;
+            }
+            diff.truncate(end);
+            diff.push_str("\n...");
+        }
+        text.push_str(format!("\n```diff\n{}\n```", diff).as_str());
+        text.push_str(format!(
+            "<https://www.reddit.com/r/{}/wiki/{}?v={}>", self.watch.subreddit, self.watch.page, self.revision
+        ).as_str());
+        text
+    }
+}
+
+// The last version of each page we saw
+struct PageState {
+    watch: WikiWatch,
+    revision: Option<String>,
+    content: String,
+}
+
+pub struct WikiWatcher {
+    api: RedditApi,
+    pages: Vec<PageState>,
+}
+
+impl WikiWatcher {
+    pub fn new(watches: Vec<WikiWatch>, credentials: Option<RedditCredentials>, client_config: &ClientConfig) -> WikiWatcher {
+        return WikiWatcher {
+            api: RedditApi::new(credentials, client_config),
+            pages: watches.into_iter().map(|w| PageState {
+                watch: WikiWatch {
+                    subreddit: w.subreddit.trim_start_matches("r/").to_string(),
+                    page: w.page,
+                    channel: w.channel,
+                },
+                revision: None,
+                content: String::new(),
+            }).collect(),
+        }
+    }
+
+    /// Every page that's got a new revision since we last looked. The first look at a page
+    /// just remembers where it's at
+    pub async fn update(&mut self) -> Vec<WikiChange> {
+        let mut changes = Vec::<WikiChange>::new();
+        for state in self.pages.iter_mut() {
+            let path = format!("/r/{}/wiki/{}", state.watch.subreddit, state.watch.page);
+            let page = match self.api.get(&path).await {
+                Ok(r) => match r.json::<WikiResponse>().await {
+                    Ok(w) => w.data,
+                    Err(e) => {
+                        error!("Couldn't read wiki page {}: {}", path, e);
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    error!("Couldn't get wiki page {}: {}", path, e);
+                    continue;
+                }
+            };
+            if state.revision.as_ref() == Some(&page.revision_id) {
+                continue;
+            }
+            if state.revision.is_some() {
+                warn!("/r/{} wiki page {} has a new revision {}", state.watch.subreddit, state.watch.page, page.revision_id);
+                changes.push(WikiChange {
+                    watch: state.watch.clone(),
+                    author: page.revision_by.map(|r| r.data.name).unwrap_or_else(|| String::from("[unknown]")),
+                    reason: page.reason.filter(|r| !r.is_empty()),
+                    revision: page.revision_id.clone(),
+                    diff: diff_lines(&state.content, &page.content_md),
+                });
+            }
+            state.revision = Some(page.revision_id);
+            state.content = page.content_md;
+        }
+        changes
+    }
+}
+
+#[derive(Deserialize)]
+struct WikiResponse {
+    data: WikiPage,
+}
+
+#[derive(Deserialize)]
+struct WikiPage {
+    content_md: String,
+    revision_id: String,
+    reason: Option<String>,
+    revision_by: Option<WikiUser>,
+}
+
+#[derive(Deserialize)]
+struct WikiUser {
+    data: WikiUserData,
+}
+
+#[derive(Deserialize)]
+struct WikiUserData {
+    name: String,
+}
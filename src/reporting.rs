@@ -0,0 +1,72 @@
+use serde::Deserialize;
+#[cfg(feature = "sentry")]
+use tracing::Subscriber;
+#[cfg(feature = "sentry")]
+use tracing_subscriber::registry::LookupSpan;
+
+/// Send panics and errors to sentry, for when nobody's reading the logs. Only does anything
+/// when we're built with the sentry feature
+#[derive(Deserialize, Debug, Clone)]
+pub struct SentryConfig {
+    pub dsn: String,
+    // Like prod or dev, the profile otherwise
+    pub environment: Option<String>,
+    // How many errors actually get sent, between 0 and 1
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f32,
+}
+
+fn default_sample_rate() -> f32 {
+    1.0
+}
+
+/// Whatever keeps sentry going, hang on to it until we're done or queued up errors get lost
+#[cfg(feature = "sentry")]
+pub type Guard = sentry::ClientInitGuard;
+#[cfg(not(feature = "sentry"))]
+pub type Guard = ();
+
+/// Start sending to sentry if there's a [sentry] section. Errors come from the logs, and carry
+/// the fields of whatever cycle and post spans they happened in, so they say what we were
+/// working on
+#[cfg(feature = "sentry")]
+pub fn init(config: Option<&SentryConfig>, profile: Option<&str>, sniffer: &str) -> Option<Guard> {
+    let config = config?;
+    let environment = config.environment.clone().or_else(|| profile.map(String::from));
+    let guard = sentry::init((config.dsn.clone(), sentry::ClientOptions {
+        release: sentry::release_name!(),
+        environment: environment.map(Into::into),
+        sample_rate: config.sample_rate,
+        ..Default::default()
+    }));
+    if !guard.is_enabled() {
+        error!("Sentry didn't start, check sentry.dsn");
+        return None;
+    }
+    sentry::configure_scope(|scope| scope.set_tag("sniffer", sniffer));
+    warn!("Sending errors to sentry");
+    Some(guard)
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn init(config: Option<&SentryConfig>, _profile: Option<&str>, _sniffer: &str) -> Option<Guard> {
+    if config.is_some() {
+        warn!("There's a [sentry] section but we weren't built with the sentry feature, ignoring it");
+    }
+    None
+}
+
+/// Hands error logs to sentry as events, and the rest as breadcrumbs leading up to them
+#[cfg(feature = "sentry")]
+pub fn layer<S>() -> Option<sentry_tracing::SentryLayer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    Some(sentry_tracing::layer())
+}
+
+// Nothing to hand over, Identity fits in any subscriber so there's nothing to infer
+#[cfg(not(feature = "sentry"))]
+pub fn layer() -> Option<tracing_subscriber::layer::Identity> {
+    None
+}
@@ -0,0 +1,29 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+
+/// Keep trying an async operation, doubling our wait between each failed attempt.
+/// Gives back the last error once we run out of attempts
+pub async fn with_backoff<T, E, F, Fut>(what: &str, attempts: u32, initial_delay: Duration, mut operation: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    let mut delay = initial_delay;
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Ok(t) => return Ok(t),
+            Err(e) => {
+                if attempt >= attempts {
+                    return Err(String::from(format!("{} failed after {} attempts: {}", what, attempt, e)));
+                }
+                warn!("{} failed (attempt {}/{}), retrying in {:?}: {}", what, attempt, attempts, delay, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
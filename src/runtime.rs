@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+lazy_static! {
+    /// What the process has been up to since it started, for /botstats
+    pub static ref STATS: RuntimeStats = RuntimeStats::new();
+}
+
+// How the last reddit poll went, and when
+struct PollOutcome {
+    at: Instant,
+    result: Result<usize, String>,
+}
+
+/// Counters anything can bump as it goes, kept cheap enough to not worry about
+pub struct RuntimeStats {
+    started: Instant,
+    posts: AtomicU64,
+    last_poll: Mutex<Option<PollOutcome>>,
+    // Queues that said how full they are, by name
+    queues: Mutex<Vec<(&'static str, Arc<AtomicUsize>)>>,
+}
+
+impl RuntimeStats {
+    fn new() -> RuntimeStats {
+        return RuntimeStats {
+            started: Instant::now(),
+            posts: AtomicU64::new(0),
+            last_poll: Mutex::new(None),
+            queues: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Another post, edit or deletion off reddit got dealt with
+    pub fn post_processed(&self) {
+        self.posts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A reddit poll finished, with how many new posts it found or what went wrong
+    pub fn poll_finished(&self, result: Result<usize, String>) {
+        *self.last_poll.lock().unwrap() = Some(PollOutcome {
+            at: Instant::now(),
+            result: result,
+        });
+    }
+
+    /// Show a queue's depth, whoever owns it keeps the count up to date
+    pub fn track_queue(&self, name: &'static str, depth: Arc<AtomicUsize>) {
+        self.queues.lock().unwrap().push((name, depth));
+    }
+
+    /// Everything in a few lines, has to be called from inside the runtime
+    pub fn report(&self) -> String {
+        let uptime = self.started.elapsed().as_secs();
+        let mut lines = vec![format!("Up for {}d {}h {}m", uptime / 86400, (uptime % 86400) / 3600, (uptime % 3600) / 60)];
+        lines.push(match rss_bytes() {
+            Some(b) => format!("Using {:.1} MB of memory", b as f64 / (1024.0 * 1024.0)),
+            None => String::from("No idea how much memory we're using"),
+        });
+        let tokio = tokio::runtime::Handle::current().metrics();
+        lines.push(format!(
+            "{} tasks alive on {} workers, {} waiting in the global queue",
+            tokio.num_alive_tasks(), tokio.num_workers(), tokio.global_queue_depth(),
+        ));
+        lines.push(format!("{} posts processed since startup", self.posts.load(Ordering::Relaxed)));
+        for (name, depth) in self.queues.lock().unwrap().iter() {
+            lines.push(format!("{} queued for {}", depth.load(Ordering::Relaxed), name));
+        }
+        lines.push(match &*self.last_poll.lock().unwrap() {
+            Some(PollOutcome { at, result: Ok(found) }) => format!("Last reddit poll {}s ago found {} new posts", at.elapsed().as_secs(), found),
+            Some(PollOutcome { at, result: Err(e) }) => format!("Last reddit poll {}s ago failed: {}", at.elapsed().as_secs(), e),
+            None => String::from("Haven't polled reddit yet"),
+        });
+        lines.join("\n")
+    }
+}
+
+// Resident memory out of /proc, so linux only
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
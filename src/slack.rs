@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serenity::async_trait;
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::filter::{FilterConfig, Filters, PostFilter};
+use crate::pipeline::PostSink;
+use crate::reddit::{PostEvent, SnifferPost};
+use crate::retry::with_backoff;
+
+const POST_MESSAGE_URL: &str = "https://slack.com/api/chat.postMessage";
+// Slack cuts section text off past this
+const SECTION_LENGTH: usize = 3000;
+const SEND_ATTEMPTS: u32 = 3;
+const SEND_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Relaying to slack as well, through incoming webhooks or the Web API
+#[derive(Deserialize, Debug, Clone)]
+pub struct SlackConfig {
+    // A bot token with chat:write, only needed for destinations with a channel instead of a webhook
+    pub token: Option<String>,
+    #[serde(default)]
+    pub destinations: Vec<SlackDestination>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SlackDestination {
+    // What rules send posts to, as slack:<name>
+    pub name: String,
+    // One or the other
+    pub webhook: Option<String>,
+    pub channel: Option<String>,
+    // These instead of the global filters
+    pub filters: Option<FilterConfig>,
+}
+
+impl SlackDestination {
+    pub fn sink_name(&self) -> String {
+        format!("slack:{}", self.name)
+    }
+}
+
+pub struct SlackSink {
+    client: reqwest::Client,
+    token: Option<String>,
+    destinations: Vec<(SlackDestination, Option<PostFilter>)>,
+    filters: RwLock<Arc<Filters>>,
+    dry_run: bool,
+}
+
+impl SlackSink {
+    /// Nothing to do if there's no [slack] section with destinations in it
+    pub fn new(config: &Config) -> Result<Option<SlackSink>, String> {
+        let slack = match &config.slack {
+            Some(s) if !s.destinations.is_empty() => s,
+            _ => return Ok(None),
+        };
+        let mut destinations = Vec::new();
+        for d in &slack.destinations {
+            let filter = match &d.filters {
+                Some(f) => Some(PostFilter::new(f)?),
+                None => None,
+            };
+            destinations.push((d.clone(), filter));
+        }
+        warn!("Posting to {} slack destinations", destinations.len());
+        return Ok(Some(SlackSink {
+            client: reqwest::Client::new(),
+            token: slack.token.clone(),
+            destinations: destinations,
+            filters: RwLock::new(Arc::new(Filters::new(&config.filters, &[], &config.rules)?)),
+            dry_run: config.dry_run,
+        }))
+    }
+
+    async fn send(&self, destination: &SlackDestination, payload: &serde_json::Value) -> Result<(), String> {
+        if let Some(webhook) = &destination.webhook {
+            let response = self.client.post(webhook).json(payload).send().await.and_then(|r| r.error_for_status());
+            return response.map(|_| ()).map_err(|e| String::from(format!("webhook said no: {}", e)));
+        }
+        let (channel, token) = match (&destination.channel, &self.token) {
+            (Some(c), Some(t)) => (c, t),
+            (Some(_), None) => return Err(String::from("it has a channel but there's no slack.token")),
+            _ => return Err(String::from("it needs a webhook or a channel")),
+        };
+        let mut payload = payload.clone();
+        payload["channel"] = serde_json::Value::from(channel.as_str());
+        let response = self.client.post(POST_MESSAGE_URL)
+            .bearer_auth(token)
+            .json(&payload)
+            .send().await
+            .and_then(|r| r.error_for_status());
+        let body = match response {
+            Ok(r) => r.json::<serde_json::Value>().await.map_err(|e| String::from(format!("slack gave us something odd: {}", e)))?,
+            Err(e) => return Err(String::from(format!("slack said no: {}", e))),
+        };
+        // The Web API says 200 for everything, it's the ok field that matters
+        match body["ok"].as_bool() {
+            Some(true) => Ok(()),
+            _ => Err(String::from(format!("slack said no: {}", body["error"].as_str().unwrap_or("no reason given")))),
+        }
+    }
+}
+
+#[async_trait]
+impl PostSink for SlackSink {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    // Only new posts, slack messages don't get edited or cleaned up after
+    async fn deliver(&self, event: PostEvent) -> Result<(), String> {
+        let post = match event {
+            PostEvent::New(p) => p,
+            _ => return Ok(()),
+        };
+        let filters = self.filters.read().await.clone();
+        let payload = blocks(&post);
+        let mut failed = Vec::<String>::new();
+        for (destination, own) in &self.destinations {
+            let name = destination.sink_name();
+            if !filters.allows_sink(&name, own.as_ref(), &post) {
+                continue;
+            }
+            if self.dry_run {
+                warn!("Dry run, would have sent {} to {}", post.id, name);
+                continue;
+            }
+            let result = with_backoff(&format!("Sending {} to {}", post.id, name), SEND_ATTEMPTS, SEND_RETRY_DELAY, || {
+                self.send(destination, &payload)
+            }).await;
+            match result {
+                Ok(_) => warn!("Sent {} to {}", post.id, name),
+                Err(e) => {
+                    crate::metrics::error("slack_post");
+                    failed.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(failed.join(", ")),
+        }
+    }
+
+    async fn reload(&self, config: &Config) -> Result<(), String> {
+        *self.filters.write().await = Arc::new(Filters::new(&config.filters, &[], &config.rules)?);
+        Ok(())
+    }
+}
+
+// Slack wants these three escaped in mrkdwn and nothing else
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Cut on a char boundary with room for the ellipsis
+fn truncate(text: &str, limit: usize) -> String {
+    match text.char_indices().nth(limit.saturating_sub(1)) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => String::from(text),
+    }
+}
+
+/// The post as Block Kit, the plain text is what notifications show
+fn blocks(post: &SnifferPost) -> serde_json::Value {
+    let link = format!("https://www.reddit.com{}", post.permalink);
+    let mut text = format!("*<{}|{}>*", link, escape(&post.title));
+    if let Some(body) = &post.body {
+        text.push('\n');
+        text.push_str(&escape(body));
+    }
+    let image = post.gallery.first().cloned().or_else(|| post.url.clone().filter(|u| {
+        let u = u.to_lowercase();
+        [".jpg", ".jpeg", ".png", ".gif", ".webp"].iter().any(|e| u.ends_with(e))
+    }));
+    // No spoilers in slack, nsfw images stay out of the channel and it's left at the link
+    if image.is_some() && post.nsfw {
+        text = format!("{}\n_nsfw image, open the post to see it_", truncate(&text, SECTION_LENGTH - 40));
+    }
+    let mut blocks = vec![serde_json::json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": truncate(&text, SECTION_LENGTH) },
+    })];
+    if let (Some(url), false) = (image, post.nsfw) {
+        blocks.push(serde_json::json!({ "type": "image", "image_url": url, "alt_text": truncate(&post.title, 2000) }));
+    }
+    blocks.push(serde_json::json!({
+        "type": "context",
+        "elements": [{
+            "type": "mrkdwn",
+            "text": format!("/u/{} in /r/{} · <!date^{}^{{date_short_pretty}} {{time}}|posted>", escape(&post.author), escape(&post.subreddit), post.timestamp),
+        }],
+    }));
+    serde_json::json!({
+        "text": format!("{} by /u/{}", post.title, post.author),
+        "blocks": blocks,
+        "unfurl_links": false,
+    })
+}
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::reddit::anchors::Cursor;
+use crate::reddit::{Removal, SnifferPost};
+
+pub mod sqlite;
+pub mod memory;
+
+// How often old stuff gets cleared out, when there's a retention set
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    Sqlite,
+    // Nothing kept between runs, for when there's no disk worth writing to
+    Memory,
+}
+
+impl Default for StoreBackend {
+    fn default() -> Self {
+        StoreBackend::Sqlite
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StoreConfig {
+    #[serde(default)]
+    pub backend: StoreBackend,
+    #[serde(default = "default_store_path")]
+    pub path: String,
+    // Forget posts older than this many days, keep everything forever otherwise
+    pub retention_days: Option<u64>,
+    // Shrink the file back down after pruning, it locks the database while it runs
+    #[serde(default)]
+    pub vacuum: bool,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig {
+            backend: StoreBackend::default(),
+            path: default_store_path(),
+            retention_days: None,
+            vacuum: false,
+        }
+    }
+}
+
+fn default_store_path() -> String {
+    String::from("sniffer.db")
+}
+
+/// Everything we keep between polls: what we've relayed so nothing goes out twice, the archive
+/// of every post, where each source got up to and which discord messages went with what.
+/// Anything that can go wrong gets logged rather than handed back, unless the caller can do
+/// something about it
+pub trait Store: Send + Sync {
+    /// Whether we've sent this post or comment out before, any errors and we assume not
+    fn was_relayed(&self, post: &SnifferPost) -> bool;
+    fn mark_relayed(&self, post: &SnifferPost);
+
+    /// Archive a post, or bring what we have of it up to date, and note down its score
+    fn save_post(&self, post: &SnifferPost);
+    /// Just the score, for archived posts we've looked at again
+    fn record_score(&self, post: &SnifferPost);
+    /// Everything created in a window of unix timestamps, oldest first
+    fn posts_between(&self, from: i64, to: i64) -> Result<Vec<ArchivedPost>, String>;
+    /// Everything we have on a post exactly as we last saw it, posts archived before we kept
+    /// them whole don't have it
+    fn post(&self, post_id: &str) -> Option<SnifferPost>;
+    /// The newest few posts created since a unix timestamp, newest first
+    fn recent_posts(&self, since: i64, limit: usize) -> Result<Vec<ArchivedPost>, String>;
+    /// The newest few posts by someone, their name in any case
+    fn posts_by_author(&self, author: &str, limit: usize) -> Result<Vec<ArchivedPost>, String>;
+    /// Stats for every day in a window of unix timestamps, brought up to date with the archive first
+    fn stats_between(&self, from: i64, to: i64) -> Result<Vec<StatsRow>, String>;
+
+    /// Where every source got up to, keyed by the source's name. Any errors and we start fresh
+    fn cursors(&self) -> HashMap<String, Cursor>;
+    fn set_cursor(&self, source: &str, cursor: &Cursor);
+
+    /// Remember the discord messages a post went out as
+    fn save_messages(&self, post_id: &str, messages: &[MessageRecord]);
+    /// Every message we've got on record for a post, any errors and there aren't any
+    fn messages_for(&self, post_id: &str) -> Vec<MessageRecord>;
+    /// Which post one of our discord messages was for
+    fn post_for_message(&self, message: u64) -> Option<String>;
+    /// Forget one message, it's been deleted
+    fn forget_message(&self, message: u64);
+    /// Forget every message for a post, there's nothing more we'll do with them
+    fn forget_messages(&self, post_id: &str);
+
+    /// Keep a post that couldn't get to a destination so it can be sent again, failing again
+    /// just counts another attempt. Sent chunks is how many pieces of a split post already
+    /// went out, so trying again doesn't send them twice
+    fn dead_letter(&self, post: &SnifferPost, destination: u64, error: &str, sent_chunks: usize);
+    /// Every post waiting to go out again, oldest failure first
+    fn dead_letters(&self) -> Result<Vec<DeadLetter>, String>;
+    /// It got there in the end, or it's not going anywhere
+    fn clear_dead_letter(&self, post_id: &str, destination: u64);
+
+    /// Keep a new post that's waiting on the score gate, with the unix timestamp it's due a look
+    fn hold_post(&self, post: &SnifferPost, due: i64);
+    /// Every post waiting on the score gate, soonest due first. Any errors and there aren't any
+    fn held_posts(&self) -> Vec<(i64, SnifferPost)>;
+    /// It's been let through or dropped
+    fn release_post(&self, post_id: &str);
+
+    /// Drop everything from before a unix timestamp, gives back how many posts went. Stats
+    /// outlive the posts they came from
+    fn prune(&self, before: i64) -> Result<usize, String>;
+    /// Write a consistent copy of the whole thing to a file, safe to do while we're running
+    fn snapshot(&self, path: &str) -> Result<(), String>;
+    /// Give back whatever space pruning freed up
+    fn vacuum(&self) -> Result<(), String>;
+}
+
+/// Open whichever store the config asks for
+pub fn open(config: &StoreConfig) -> Result<Arc<dyn Store>, String> {
+    match config.backend {
+        StoreBackend::Sqlite => Ok(Arc::new(sqlite::SqliteStore::open(&config.path)?)),
+        StoreBackend::Memory => {
+            warn!("Keeping everything in memory, a restart will forget what we've relayed");
+            Ok(Arc::new(memory::MemoryStore::default()))
+        }
+    }
+}
+
+/// A post the way it's kept in the archive
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedPost {
+    pub id: String,
+    pub source: String,
+    pub subreddit: String,
+    pub author: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub url: Option<String>,
+    pub permalink: String,
+    pub flair: Option<String>,
+    pub nsfw: bool,
+    pub comment: bool,
+    pub score: i64,
+    pub removed: Option<String>,
+    pub created_at: i64,
+    pub edited_at: Option<i64>,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// One discord message we sent for a post. The channel is where the message actually is,
+/// which for forum destinations is the post's own thread rather than the destination
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MessageRecord {
+    pub destination: u64,
+    pub channel: u64,
+    pub message: u64,
+}
+
+/// A post that ran out of retries getting to one destination
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub post: SnifferPost,
+    pub destination: u64,
+    // The last thing that went wrong
+    pub error: String,
+    pub failed_at: i64,
+    pub attempts: i64,
+    // Pieces of a split post that already made it
+    pub sent_chunks: usize,
+}
+
+/// How many posts someone made somewhere in an hour of a day, and what they scored altogether
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsRow {
+    pub day: String,
+    pub hour: i64,
+    pub author: String,
+    pub subreddit: String,
+    pub posts: i64,
+    pub total_score: i64,
+}
+
+/// The highlights out of a bunch of stats rows
+#[derive(Debug, Clone, Default)]
+pub struct StatsSummary {
+    pub posts: i64,
+    pub average_score: f64,
+    // In UTC
+    pub busiest_hour: Option<(i64, i64)>,
+    pub busiest_day: Option<(String, i64)>,
+    // Name, posts and average score, most posts first
+    pub authors: Vec<(String, i64, f64)>,
+    pub subreddits: Vec<(String, i64, f64)>,
+}
+
+impl StatsSummary {
+    pub fn new(rows: &[StatsRow]) -> StatsSummary {
+        let mut summary = StatsSummary::default();
+        let mut hours = HashMap::<i64, i64>::new();
+        let mut days = HashMap::<String, i64>::new();
+        let mut authors = HashMap::<String, (i64, i64)>::new();
+        let mut subreddits = HashMap::<String, (i64, i64)>::new();
+        let mut total_score = 0;
+        for row in rows {
+            summary.posts += row.posts;
+            total_score += row.total_score;
+            *hours.entry(row.hour).or_default() += row.posts;
+            *days.entry(row.day.clone()).or_default() += row.posts;
+            let author = authors.entry(row.author.clone()).or_default();
+            author.0 += row.posts;
+            author.1 += row.total_score;
+            let subreddit = subreddits.entry(row.subreddit.clone()).or_default();
+            subreddit.0 += row.posts;
+            subreddit.1 += row.total_score;
+        }
+        if summary.posts > 0 {
+            summary.average_score = total_score as f64 / summary.posts as f64;
+        }
+        summary.busiest_hour = hours.into_iter().max_by_key(|(hour, posts)| (*posts, -hour));
+        summary.busiest_day = days.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+        let ranked = |tally: HashMap<String, (i64, i64)>| {
+            let mut ranked: Vec<(String, i64, f64)> = tally.into_iter()
+                .map(|(name, (posts, score))| (name, posts, score as f64 / posts as f64))
+                .collect();
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            ranked
+        };
+        summary.authors = ranked(authors);
+        summary.subreddits = ranked(subreddits);
+        summary
+    }
+}
+
+/// What an archive export comes out as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<ExportFormat> {
+        match s.to_lowercase().as_str() {
+            "json" => Some(ExportFormat::Json),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Dump archived posts out as a file
+pub fn export(posts: &[ArchivedPost], format: ExportFormat) -> Result<Vec<u8>, String> {
+    match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(posts).map_err(|e| String::from(format!("Couldn't write json: {}", e))),
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for post in posts {
+                if let Err(e) = writer.serialize(post) {
+                    return Err(String::from(format!("Couldn't write csv: {}", e)));
+                }
+            }
+            writer.into_inner().map_err(|e| String::from(format!("Couldn't write csv: {}", e)))
+        }
+    }
+}
+
+/// Same again for stats rows
+pub fn export_stats(rows: &[StatsRow], format: ExportFormat) -> Result<Vec<u8>, String> {
+    match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(rows).map_err(|e| String::from(format!("Couldn't write json: {}", e))),
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for row in rows {
+                if let Err(e) = writer.serialize(row) {
+                    return Err(String::from(format!("Couldn't write csv: {}", e)));
+                }
+            }
+            writer.into_inner().map_err(|e| String::from(format!("Couldn't write csv: {}", e)))
+        }
+    }
+}
+
+/// Turn a pair of YYYY-MM-DD dates into unix timestamps, the end date counts the whole day.
+/// No start means the beginning of time, no end means now
+pub fn date_range(from: Option<&str>, to: Option<&str>) -> Result<(i64, i64), String> {
+    let parse = |d: &str| match NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d") {
+        Ok(date) => Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()),
+        Err(e) => Err(String::from(format!("{} isn't a date like 2024-01-31: {}", d, e))),
+    };
+    let start = match from {
+        Some(d) => parse(d)?,
+        None => 0,
+    };
+    let end = match to {
+        Some(d) => parse(d)? + Duration::days(1).num_seconds(),
+        None => Utc::now().timestamp() + 1,
+    };
+    if end <= start {
+        return Err(String::from("The end date is before the start date"));
+    }
+    Ok((start, end))
+}
+
+/// Keep the database down to the configured retention, once at startup and then once a day.
+/// Does nothing if there's no retention set
+pub async fn prune_forever(store: Arc<dyn Store>, config: StoreConfig) {
+    let days = match config.retention_days {
+        Some(d) => d,
+        None => return,
+    };
+    warn!("Keeping {} days of history", days);
+    loop {
+        // Whole days at a time, so the stats never get tallied off half a day
+        let cutoff = (Utc::now() - Duration::days(days as i64)).date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+        // It's all blocking work, keep it off the runtime
+        let pruner = store.clone();
+        let vacuum = config.vacuum;
+        let result = tokio::task::spawn_blocking(move || -> Result<usize, String> {
+            let pruned = pruner.prune(cutoff)?;
+            if vacuum && pruned > 0 {
+                pruner.vacuum()?;
+            }
+            Ok(pruned)
+        }).await;
+        match result {
+            Ok(Ok(pruned)) => warn!("Pruned {} posts older than {} days", pruned, days),
+            Ok(Err(e)) => error!("{}", e),
+            Err(e) => error!("Pruning the store fell over: {}", e),
+        }
+        tokio::time::sleep(PRUNE_INTERVAL).await;
+    }
+}
+
+fn removal_name(removal: Removal) -> &'static str {
+    match removal {
+        Removal::Missing => "missing",
+        Removal::Author => "author",
+        Removal::Moderators => "moderators",
+    }
+}
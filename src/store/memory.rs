@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Timelike, Utc};
+
+use crate::reddit::anchors::Cursor;
+use crate::reddit::SnifferPost;
+
+use super::{removal_name, ArchivedPost, DeadLetter, MessageRecord, StatsRow, Store};
+
+// day, hour, author, subreddit
+type StatsKey = (String, i64, String, String);
+
+/// Everything in memory and gone when we stop, for running somewhere without a disk to keep.
+/// Doesn't bother with score history, there's no exporting it before it's forgotten anyway
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    // fullname -> when we relayed it
+    relayed: HashMap<String, i64>,
+    // fullname -> the post
+    posts: HashMap<String, ArchivedPost>,
+    // fullname -> the post as we last saw it
+    full_posts: HashMap<String, SnifferPost>,
+    cursors: HashMap<String, Cursor>,
+    // post id, the message and when we sent it
+    messages: Vec<(String, MessageRecord, i64)>,
+    // posts and total score
+    stats: HashMap<StatsKey, (i64, i64)>,
+    // post id and destination
+    dead_letters: HashMap<(String, u64), DeadLetter>,
+    // post id -> when it's due and the post
+    held: HashMap<String, (i64, SnifferPost)>,
+}
+
+impl MemoryState {
+    // Same deal as the sqlite one, groups get replaced whole so pruned days keep their tally
+    fn roll_up_stats(&mut self) {
+        let mut tally = HashMap::<StatsKey, (i64, i64)>::new();
+        for post in self.posts.values() {
+            let (day, hour) = match DateTime::<Utc>::from_timestamp(post.created_at, 0) {
+                Some(t) => (t.format("%Y-%m-%d").to_string(), t.hour() as i64),
+                None => continue,
+            };
+            let entry = tally.entry((day, hour, post.author.clone(), post.subreddit.clone())).or_default();
+            entry.0 += 1;
+            entry.1 += post.score;
+        }
+        self.stats.extend(tally);
+    }
+}
+
+impl Store for MemoryStore {
+    fn was_relayed(&self, post: &SnifferPost) -> bool {
+        self.state.lock().unwrap().relayed.contains_key(&post.fullname())
+    }
+
+    fn mark_relayed(&self, post: &SnifferPost) {
+        self.state.lock().unwrap().relayed.entry(post.fullname()).or_insert(Utc::now().timestamp());
+    }
+
+    fn save_post(&self, post: &SnifferPost) {
+        self.mark_relayed(post);
+        let now = Utc::now().timestamp();
+        let mut state = self.state.lock().unwrap();
+        let first_seen = state.posts.get(&post.fullname()).map(|p| p.first_seen).unwrap_or(now);
+        state.full_posts.insert(post.fullname(), post.clone());
+        state.posts.insert(post.fullname(), ArchivedPost {
+            id: post.id.clone(),
+            source: post.source.clone(),
+            subreddit: post.subreddit.clone(),
+            author: post.author.clone(),
+            title: post.title.clone(),
+            body: post.body.clone(),
+            url: post.url.clone(),
+            permalink: post.permalink.clone(),
+            flair: post.flair.clone(),
+            nsfw: post.nsfw,
+            comment: post.comment,
+            score: post.score,
+            removed: post.removed.map(|r| String::from(removal_name(r))),
+            created_at: post.timestamp as i64,
+            edited_at: post.edited.map(|e| e as i64),
+            first_seen: first_seen,
+            last_seen: now,
+        });
+    }
+
+    fn record_score(&self, post: &SnifferPost) {
+        if let Some(p) = self.state.lock().unwrap().posts.get_mut(&post.fullname()) {
+            p.score = post.score;
+            p.last_seen = Utc::now().timestamp();
+        }
+    }
+
+    fn posts_between(&self, from: i64, to: i64) -> Result<Vec<ArchivedPost>, String> {
+        let state = self.state.lock().unwrap();
+        let mut posts: Vec<ArchivedPost> = state.posts.values()
+            .filter(|p| p.created_at >= from && p.created_at < to)
+            .cloned()
+            .collect();
+        posts.sort_by_key(|p| p.created_at);
+        Ok(posts)
+    }
+
+    fn post(&self, post_id: &str) -> Option<SnifferPost> {
+        self.state.lock().unwrap().full_posts.values().find(|p| p.id == post_id).cloned()
+    }
+
+    fn recent_posts(&self, since: i64, limit: usize) -> Result<Vec<ArchivedPost>, String> {
+        let state = self.state.lock().unwrap();
+        let mut posts: Vec<ArchivedPost> = state.posts.values()
+            .filter(|p| p.created_at >= since)
+            .cloned()
+            .collect();
+        posts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        posts.truncate(limit);
+        Ok(posts)
+    }
+
+    fn posts_by_author(&self, author: &str, limit: usize) -> Result<Vec<ArchivedPost>, String> {
+        let state = self.state.lock().unwrap();
+        let mut posts: Vec<ArchivedPost> = state.posts.values()
+            .filter(|p| p.author.eq_ignore_ascii_case(author))
+            .cloned()
+            .collect();
+        posts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        posts.truncate(limit);
+        Ok(posts)
+    }
+
+    fn stats_between(&self, from: i64, to: i64) -> Result<Vec<StatsRow>, String> {
+        let day = |t: i64| DateTime::<Utc>::from_timestamp(t, 0).map(|t| t.format("%Y-%m-%d").to_string()).unwrap_or_default();
+        let (first, last) = (day(from), day(to - 1));
+        let mut state = self.state.lock().unwrap();
+        state.roll_up_stats();
+        let mut rows: Vec<StatsRow> = state.stats.iter()
+            .filter(|((d, ..), _)| *d >= first && *d <= last)
+            .map(|((day, hour, author, subreddit), (posts, score))| StatsRow {
+                day: day.clone(),
+                hour: *hour,
+                author: author.clone(),
+                subreddit: subreddit.clone(),
+                posts: *posts,
+                total_score: *score,
+            })
+            .collect();
+        rows.sort_by(|a, b| (&a.day, a.hour, &a.author, &a.subreddit).cmp(&(&b.day, b.hour, &b.author, &b.subreddit)));
+        Ok(rows)
+    }
+
+    fn cursors(&self) -> HashMap<String, Cursor> {
+        self.state.lock().unwrap().cursors.clone()
+    }
+
+    fn set_cursor(&self, source: &str, cursor: &Cursor) {
+        self.state.lock().unwrap().cursors.insert(String::from(source), cursor.clone());
+    }
+
+    fn save_messages(&self, post_id: &str, messages: &[MessageRecord]) {
+        let now = Utc::now().timestamp();
+        let mut state = self.state.lock().unwrap();
+        for m in messages {
+            state.messages.retain(|(p, old, _)| !(p == post_id && old.message == m.message));
+            state.messages.push((String::from(post_id), *m, now));
+        }
+    }
+
+    fn messages_for(&self, post_id: &str) -> Vec<MessageRecord> {
+        self.state.lock().unwrap().messages.iter()
+            .filter(|(p, ..)| p == post_id)
+            .map(|(_, m, _)| *m)
+            .collect()
+    }
+
+    fn post_for_message(&self, message: u64) -> Option<String> {
+        self.state.lock().unwrap().messages.iter()
+            .find(|(_, m, _)| m.message == message)
+            .map(|(p, ..)| p.clone())
+    }
+
+    fn forget_message(&self, message: u64) {
+        self.state.lock().unwrap().messages.retain(|(_, m, _)| m.message != message);
+    }
+
+    fn forget_messages(&self, post_id: &str) {
+        self.state.lock().unwrap().messages.retain(|(p, ..)| p != post_id);
+    }
+
+    fn dead_letter(&self, post: &SnifferPost, destination: u64, error: &str, sent_chunks: usize) {
+        let mut state = self.state.lock().unwrap();
+        let letter = state.dead_letters.entry((post.id.clone(), destination)).or_insert_with(|| DeadLetter {
+            post: post.clone(),
+            destination: destination,
+            error: String::new(),
+            failed_at: 0,
+            attempts: 0,
+            sent_chunks: 0,
+        });
+        letter.post = post.clone();
+        letter.error = String::from(error);
+        letter.failed_at = Utc::now().timestamp();
+        letter.attempts += 1;
+        letter.sent_chunks = sent_chunks;
+    }
+
+    fn dead_letters(&self) -> Result<Vec<DeadLetter>, String> {
+        let mut letters: Vec<DeadLetter> = self.state.lock().unwrap().dead_letters.values().cloned().collect();
+        letters.sort_by_key(|l| l.failed_at);
+        Ok(letters)
+    }
+
+    fn clear_dead_letter(&self, post_id: &str, destination: u64) {
+        self.state.lock().unwrap().dead_letters.remove(&(String::from(post_id), destination));
+    }
+
+    fn hold_post(&self, post: &SnifferPost, due: i64) {
+        self.state.lock().unwrap().held.insert(post.id.clone(), (due, post.clone()));
+    }
+
+    fn held_posts(&self) -> Vec<(i64, SnifferPost)> {
+        let mut held: Vec<(i64, SnifferPost)> = self.state.lock().unwrap().held.values().cloned().collect();
+        held.sort_by_key(|(due, _)| *due);
+        held
+    }
+
+    fn release_post(&self, post_id: &str) {
+        self.state.lock().unwrap().held.remove(post_id);
+    }
+
+    fn prune(&self, before: i64) -> Result<usize, String> {
+        let mut state = self.state.lock().unwrap();
+        state.roll_up_stats();
+        let count = state.posts.len();
+        state.posts.retain(|_, p| p.created_at >= before);
+        let pruned = count - state.posts.len();
+        state.full_posts.retain(|_, p| p.timestamp as i64 >= before);
+        state.messages.retain(|(_, _, sent_at)| *sent_at >= before);
+        state.dead_letters.retain(|_, l| l.failed_at >= before);
+        state.held.retain(|_, (due, _)| *due >= before);
+        state.relayed.retain(|_, relayed_at| *relayed_at >= before);
+        Ok(pruned)
+    }
+
+    fn snapshot(&self, _path: &str) -> Result<(), String> {
+        Err(String::from("The memory store has nothing on disk to back up"))
+    }
+
+    fn vacuum(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
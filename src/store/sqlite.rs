@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::reddit::anchors::Cursor;
+use crate::reddit::SnifferPost;
+
+use super::{removal_name, ArchivedPost, DeadLetter, MessageRecord, StatsRow, Store};
+
+// Every schema change goes on the end of here, never edit one that's shipped. The database
+// remembers how far it's got in user_version
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE relayed (
+        fullname TEXT PRIMARY KEY,
+        source TEXT NOT NULL,
+        relayed_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE posts (
+        fullname TEXT PRIMARY KEY,
+        id TEXT NOT NULL,
+        source TEXT NOT NULL,
+        subreddit TEXT NOT NULL,
+        author TEXT NOT NULL,
+        title TEXT NOT NULL,
+        body TEXT,
+        url TEXT,
+        permalink TEXT NOT NULL,
+        flair TEXT,
+        nsfw INTEGER NOT NULL,
+        comment INTEGER NOT NULL,
+        score INTEGER NOT NULL,
+        removed TEXT,
+        created_at INTEGER NOT NULL,
+        edited_at INTEGER,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+    CREATE INDEX posts_created_at ON posts (created_at);
+    CREATE TABLE score_snapshots (
+        fullname TEXT NOT NULL,
+        score INTEGER NOT NULL,
+        taken_at INTEGER NOT NULL
+    );
+    CREATE INDEX score_snapshots_fullname ON score_snapshots (fullname);",
+    "CREATE TABLE cursors (
+        source TEXT PRIMARY KEY,
+        fullname TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE messages (
+        post_id TEXT NOT NULL,
+        destination INTEGER NOT NULL,
+        channel INTEGER NOT NULL,
+        message INTEGER NOT NULL,
+        sent_at INTEGER NOT NULL,
+        PRIMARY KEY (post_id, message)
+    );
+    CREATE INDEX messages_message ON messages (message);",
+    "CREATE TABLE stats (
+        day TEXT NOT NULL,
+        hour INTEGER NOT NULL,
+        author TEXT NOT NULL,
+        subreddit TEXT NOT NULL,
+        posts INTEGER NOT NULL,
+        total_score INTEGER NOT NULL,
+        PRIMARY KEY (day, hour, author, subreddit)
+    )",
+    "CREATE TABLE dead_letters (
+        post_id TEXT NOT NULL,
+        destination INTEGER NOT NULL,
+        post TEXT NOT NULL,
+        error TEXT NOT NULL,
+        failed_at INTEGER NOT NULL,
+        attempts INTEGER NOT NULL,
+        PRIMARY KEY (post_id, destination)
+    )",
+    // The whole post as json, older rows don't have it
+    "ALTER TABLE posts ADD COLUMN post TEXT",
+    "CREATE TABLE held_posts (
+        post_id TEXT PRIMARY KEY,
+        post TEXT NOT NULL,
+        due_at INTEGER NOT NULL
+    )",
+    "ALTER TABLE dead_letters ADD COLUMN sent_chunks INTEGER NOT NULL DEFAULT 0",
+];
+
+// Tally the archive up into the stats table. Groups get replaced whole, so this can run as often
+// as we like, and days that get pruned out of posts keep their last tally
+const ROLL_UP_STATS: &str = "INSERT OR REPLACE INTO stats (day, hour, author, subreddit, posts, total_score)
+    SELECT strftime('%Y-%m-%d', created_at, 'unixepoch'), CAST(strftime('%H', created_at, 'unixepoch') AS INTEGER),
+        author, subreddit, COUNT(*), SUM(score)
+    FROM posts GROUP BY 1, 2, 3, 4";
+/// Everything kept in one sqlite file, what you want unless there's nowhere to keep it
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<SqliteStore, String> {
+        let mut conn = match Connection::open(path) {
+            Ok(c) => c,
+            Err(e) => return Err(String::from(format!("Couldn't open the database {}: {}", path, e))),
+        };
+        migrate(&mut conn).map_err(|e| String::from(format!("Couldn't migrate the database {}: {}", path, e)))?;
+        return Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl Store for SqliteStore {
+    fn was_relayed(&self, post: &SnifferPost) -> bool {
+        let conn = self.conn.lock().unwrap();
+        let found = conn.query_row(
+            "SELECT 1 FROM relayed WHERE fullname = ?1",
+            params![post.fullname()],
+            |_| Ok(()),
+        ).optional();
+        match found {
+            Ok(f) => f.is_some(),
+            Err(e) => {
+                error!("Couldn't check if {} was relayed: {}", post.id, e);
+                false
+            }
+        }
+    }
+
+    fn save_post(&self, post: &SnifferPost) {
+        self.mark_relayed(post);
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        let result = conn.execute(
+            "INSERT INTO posts (fullname, id, source, subreddit, author, title, body, url, permalink, flair, nsfw,
+                comment, score, removed, created_at, edited_at, first_seen, last_seen, post)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?17, ?18)
+            ON CONFLICT (fullname) DO UPDATE SET
+                subreddit = excluded.subreddit, author = excluded.author, title = excluded.title,
+                body = excluded.body, url = excluded.url, permalink = excluded.permalink,
+                flair = excluded.flair, nsfw = excluded.nsfw, score = excluded.score,
+                removed = excluded.removed, created_at = excluded.created_at,
+                edited_at = excluded.edited_at, last_seen = excluded.last_seen, post = excluded.post",
+            params![
+                post.fullname(), post.id, post.source, post.subreddit, post.author, post.title, post.body,
+                post.url, post.permalink, post.flair, post.nsfw, post.comment, post.score,
+                post.removed.map(removal_name), post.timestamp as i64, post.edited.map(|e| e as i64), now,
+                serde_json::to_string(post).ok(),
+            ],
+        );
+        if let Err(e) = result {
+            error!("Couldn't archive {}: {}", post.id, e);
+        }
+        drop(conn);
+        self.record_score(post);
+    }
+
+    fn record_score(&self, post: &SnifferPost) {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        let result = conn.execute(
+            "INSERT INTO score_snapshots (fullname, score, taken_at)
+                SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM posts WHERE fullname = ?1)",
+            params![post.fullname(), post.score, now],
+        ).and_then(|_| conn.execute(
+            "UPDATE posts SET score = ?2, last_seen = ?3 WHERE fullname = ?1",
+            params![post.fullname(), post.score, now],
+        ));
+        if let Err(e) = result {
+            error!("Couldn't record the score of {}: {}", post.id, e);
+        }
+    }
+
+    fn posts_between(&self, from: i64, to: i64) -> Result<Vec<ArchivedPost>, String> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<Vec<ArchivedPost>, rusqlite::Error> {
+            let mut statement = conn.prepare(
+                "SELECT id, source, subreddit, author, title, body, url, permalink, flair, nsfw, comment, score,
+                    removed, created_at, edited_at, first_seen, last_seen
+                FROM posts WHERE created_at >= ?1 AND created_at < ?2 ORDER BY created_at",
+            )?;
+            let rows = statement.query_map(params![from, to], archived_post)?;
+            rows.collect()
+        };
+        query().map_err(|e| String::from(format!("Couldn't read the archive: {}", e)))
+    }
+
+    fn post(&self, post_id: &str) -> Option<SnifferPost> {
+        let conn = self.conn.lock().unwrap();
+        let json: Result<String, rusqlite::Error> = conn.query_row(
+            "SELECT post FROM posts WHERE id = ?1 AND post IS NOT NULL ORDER BY last_seen DESC LIMIT 1",
+            params![post_id],
+            |r| r.get(0),
+        );
+        match json {
+            Ok(j) => match serde_json::from_str(&j) {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    error!("Couldn't read back post {}: {}", post_id, e);
+                    None
+                }
+            },
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => {
+                error!("Couldn't look up post {}: {}", post_id, e);
+                None
+            }
+        }
+    }
+
+    fn recent_posts(&self, since: i64, limit: usize) -> Result<Vec<ArchivedPost>, String> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<Vec<ArchivedPost>, rusqlite::Error> {
+            let mut statement = conn.prepare(
+                "SELECT id, source, subreddit, author, title, body, url, permalink, flair, nsfw, comment, score,
+                    removed, created_at, edited_at, first_seen, last_seen
+                FROM posts WHERE created_at >= ?1 ORDER BY created_at DESC LIMIT ?2",
+            )?;
+            let rows = statement.query_map(params![since, limit as i64], archived_post)?;
+            rows.collect()
+        };
+        query().map_err(|e| String::from(format!("Couldn't read the archive: {}", e)))
+    }
+
+    fn posts_by_author(&self, author: &str, limit: usize) -> Result<Vec<ArchivedPost>, String> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<Vec<ArchivedPost>, rusqlite::Error> {
+            let mut statement = conn.prepare(
+                "SELECT id, source, subreddit, author, title, body, url, permalink, flair, nsfw, comment, score,
+                    removed, created_at, edited_at, first_seen, last_seen
+                FROM posts WHERE author = ?1 COLLATE NOCASE ORDER BY created_at DESC LIMIT ?2",
+            )?;
+            let rows = statement.query_map(params![author, limit as i64], archived_post)?;
+            rows.collect()
+        };
+        query().map_err(|e| String::from(format!("Couldn't read the archive: {}", e)))
+    }
+
+    fn cursors(&self) -> HashMap<String, Cursor> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<HashMap<String, Cursor>, rusqlite::Error> {
+            let mut statement = conn.prepare("SELECT source, fullname, timestamp FROM cursors")?;
+            let rows = statement.query_map([], |r| {
+                let timestamp: i64 = r.get(2)?;
+                Ok((r.get(0)?, Cursor { fullname: r.get(1)?, timestamp: timestamp as u64 }))
+            })?;
+            rows.collect()
+        };
+        match query() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Couldn't read where the sources got up to, starting fresh: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    fn set_cursor(&self, source: &str, cursor: &Cursor) {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.execute(
+            "INSERT INTO cursors (source, fullname, timestamp, updated_at) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (source) DO UPDATE SET
+                fullname = excluded.fullname, timestamp = excluded.timestamp, updated_at = excluded.updated_at",
+            params![source, cursor.fullname, cursor.timestamp as i64, Utc::now().timestamp()],
+        );
+        if let Err(e) = result {
+            error!("Couldn't save where {} got up to: {}", source, e);
+        }
+    }
+
+    fn stats_between(&self, from: i64, to: i64) -> Result<Vec<StatsRow>, String> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<Vec<StatsRow>, rusqlite::Error> {
+            conn.execute(ROLL_UP_STATS, [])?;
+            let mut statement = conn.prepare(
+                "SELECT day, hour, author, subreddit, posts, total_score FROM stats
+                WHERE day >= date(?1, 'unixepoch') AND day <= date(?2 - 1, 'unixepoch')
+                ORDER BY day, hour, author, subreddit",
+            )?;
+            let rows = statement.query_map(params![from, to], |r| Ok(StatsRow {
+                day: r.get(0)?,
+                hour: r.get(1)?,
+                author: r.get(2)?,
+                subreddit: r.get(3)?,
+                posts: r.get(4)?,
+                total_score: r.get(5)?,
+            }))?;
+            rows.collect()
+        };
+        query().map_err(|e| String::from(format!("Couldn't work out the stats: {}", e)))
+    }
+
+    fn save_messages(&self, post_id: &str, messages: &[MessageRecord]) {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        for m in messages {
+            let result = conn.execute(
+                "INSERT OR REPLACE INTO messages (post_id, destination, channel, message, sent_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![post_id, m.destination as i64, m.channel as i64, m.message as i64, now],
+            );
+            if let Err(e) = result {
+                error!("Couldn't remember message {} for post {}: {}", m.message, post_id, e);
+            }
+        }
+    }
+
+    fn messages_for(&self, post_id: &str) -> Vec<MessageRecord> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<Vec<MessageRecord>, rusqlite::Error> {
+            let mut statement = conn.prepare("SELECT destination, channel, message FROM messages WHERE post_id = ?1")?;
+            let rows = statement.query_map(params![post_id], |r| {
+                let (destination, channel, message): (i64, i64, i64) = (r.get(0)?, r.get(1)?, r.get(2)?);
+                Ok(MessageRecord {
+                    destination: destination as u64,
+                    channel: channel as u64,
+                    message: message as u64,
+                })
+            })?;
+            rows.collect()
+        };
+        match query() {
+            Ok(m) => m,
+            Err(e) => {
+                error!("Couldn't look up our messages for post {}: {}", post_id, e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn post_for_message(&self, message: u64) -> Option<String> {
+        let conn = self.conn.lock().unwrap();
+        let found = conn.query_row(
+            "SELECT post_id FROM messages WHERE message = ?1",
+            params![message as i64],
+            |r| r.get(0),
+        ).optional();
+        match found {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Couldn't look up which post message {} was for: {}", message, e);
+                None
+            }
+        }
+    }
+
+    fn forget_message(&self, message: u64) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(e) = conn.execute("DELETE FROM messages WHERE message = ?1", params![message as i64]) {
+            error!("Couldn't forget message {}: {}", message, e);
+        }
+    }
+
+    fn forget_messages(&self, post_id: &str) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(e) = conn.execute("DELETE FROM messages WHERE post_id = ?1", params![post_id]) {
+            error!("Couldn't forget the messages for post {}: {}", post_id, e);
+        }
+    }
+
+    fn dead_letter(&self, post: &SnifferPost, destination: u64, error: &str, sent_chunks: usize) {
+        // The whole post, so it goes out again exactly like it would have
+        let json = match serde_json::to_string(post) {
+            Ok(j) => j,
+            Err(e) => {
+                error!("Couldn't dead letter {}: {}", post.id, e);
+                return;
+            }
+        };
+        let conn = self.conn.lock().unwrap();
+        let result = conn.execute(
+            "INSERT INTO dead_letters (post_id, destination, post, error, failed_at, attempts, sent_chunks)
+            VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)
+            ON CONFLICT (post_id, destination) DO UPDATE SET
+                post = excluded.post, error = excluded.error, failed_at = excluded.failed_at,
+                attempts = attempts + 1, sent_chunks = excluded.sent_chunks",
+            params![post.id, destination as i64, json, error, Utc::now().timestamp(), sent_chunks as i64],
+        );
+        if let Err(e) = result {
+            error!("Couldn't dead letter {}: {}", post.id, e);
+        }
+    }
+
+    fn dead_letters(&self) -> Result<Vec<DeadLetter>, String> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<Vec<(i64, String, String, i64, i64, i64)>, rusqlite::Error> {
+            let mut statement = conn.prepare(
+                "SELECT destination, post, error, failed_at, attempts, sent_chunks FROM dead_letters ORDER BY failed_at"
+            )?;
+            let rows = statement.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?)))?;
+            rows.collect()
+        };
+        let rows = query().map_err(|e| String::from(format!("Couldn't look up the dead letters: {}", e)))?;
+        let mut letters = Vec::new();
+        for (destination, json, error, failed_at, attempts, sent_chunks) in rows {
+            match serde_json::from_str::<SnifferPost>(&json) {
+                Ok(post) => letters.push(DeadLetter {
+                    post: post,
+                    destination: destination as u64,
+                    error: error,
+                    failed_at: failed_at,
+                    attempts: attempts,
+                    sent_chunks: sent_chunks as usize,
+                }),
+                // Left for pruning to clear out, one bad row shouldn't hide the rest
+                Err(e) => error!("Couldn't read a dead letter for {}: {}", destination, e),
+            }
+        }
+        Ok(letters)
+    }
+
+    fn clear_dead_letter(&self, post_id: &str, destination: u64) {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.execute(
+            "DELETE FROM dead_letters WHERE post_id = ?1 AND destination = ?2",
+            params![post_id, destination as i64],
+        );
+        if let Err(e) = result {
+            error!("Couldn't clear the dead letter for {} to {}: {}", post_id, destination, e);
+        }
+    }
+
+    fn hold_post(&self, post: &SnifferPost, due: i64) {
+        let json = match serde_json::to_string(post) {
+            Ok(j) => j,
+            Err(e) => {
+                error!("Couldn't keep held post {}: {}", post.id, e);
+                return;
+            }
+        };
+        let conn = self.conn.lock().unwrap();
+        let result = conn.execute(
+            "INSERT OR REPLACE INTO held_posts (post_id, post, due_at) VALUES (?1, ?2, ?3)",
+            params![post.id, json, due],
+        );
+        if let Err(e) = result {
+            error!("Couldn't keep held post {}: {}", post.id, e);
+        }
+    }
+
+    fn held_posts(&self) -> Vec<(i64, SnifferPost)> {
+        let conn = self.conn.lock().unwrap();
+        let query = || -> Result<Vec<(i64, String)>, rusqlite::Error> {
+            let mut statement = conn.prepare("SELECT due_at, post FROM held_posts ORDER BY due_at")?;
+            let rows = statement.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+            rows.collect()
+        };
+        let rows = match query() {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Couldn't look up the held posts: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut held = Vec::new();
+        for (due, json) in rows {
+            match serde_json::from_str::<SnifferPost>(&json) {
+                Ok(post) => held.push((due, post)),
+                Err(e) => error!("Couldn't read a held post: {}", e),
+            }
+        }
+        held
+    }
+
+    fn release_post(&self, post_id: &str) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(e) = conn.execute("DELETE FROM held_posts WHERE post_id = ?1", params![post_id]) {
+            error!("Couldn't let go of held post {}: {}", post_id, e);
+        }
+    }
+
+    fn prune(&self, before: i64) -> Result<usize, String> {
+        let mut conn = self.conn.lock().unwrap();
+        let mut prune = || -> Result<usize, rusqlite::Error> {
+            let tx = conn.transaction()?;
+            // Stats outlive the posts they came from
+            tx.execute(ROLL_UP_STATS, [])?;
+            tx.execute(
+                "DELETE FROM score_snapshots WHERE taken_at < ?1
+                    OR fullname IN (SELECT fullname FROM posts WHERE created_at < ?1)",
+                params![before],
+            )?;
+            let posts = tx.execute("DELETE FROM posts WHERE created_at < ?1", params![before])?;
+            tx.execute("DELETE FROM messages WHERE sent_at < ?1", params![before])?;
+            tx.execute("DELETE FROM dead_letters WHERE failed_at < ?1", params![before])?;
+            tx.execute("DELETE FROM held_posts WHERE due_at < ?1", params![before])?;
+            // Anything this old is behind every source's cursor, it won't come back around as new
+            tx.execute("DELETE FROM relayed WHERE relayed_at < ?1", params![before])?;
+            tx.commit()?;
+            Ok(posts)
+        };
+        prune().map_err(|e| String::from(format!("Couldn't prune the database: {}", e)))
+    }
+
+    fn snapshot(&self, path: &str) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("VACUUM INTO ?1", params![path])
+            .map(|_| ())
+            .map_err(|e| String::from(format!("Couldn't copy the database to {}: {}", path, e)))
+    }
+
+    fn vacuum(&self) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch("VACUUM").map_err(|e| String::from(format!("Couldn't vacuum the database: {}", e)))
+    }
+
+    fn mark_relayed(&self, post: &SnifferPost) {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.execute(
+            "INSERT OR IGNORE INTO relayed (fullname, source, relayed_at) VALUES (?1, ?2, ?3)",
+            params![post.fullname(), post.source, Utc::now().timestamp()],
+        );
+        if let Err(e) = result {
+            error!("Couldn't remember relaying {}: {}", post.id, e);
+        }
+    }
+}
+
+// Columns in the order the archive queries select them
+fn archived_post(r: &rusqlite::Row) -> Result<ArchivedPost, rusqlite::Error> {
+    Ok(ArchivedPost {
+        id: r.get(0)?,
+        source: r.get(1)?,
+        subreddit: r.get(2)?,
+        author: r.get(3)?,
+        title: r.get(4)?,
+        body: r.get(5)?,
+        url: r.get(6)?,
+        permalink: r.get(7)?,
+        flair: r.get(8)?,
+        nsfw: r.get(9)?,
+        comment: r.get(10)?,
+        score: r.get(11)?,
+        removed: r.get(12)?,
+        created_at: r.get(13)?,
+        edited_at: r.get(14)?,
+        first_seen: r.get(15)?,
+        last_seen: r.get(16)?,
+    })
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), rusqlite::Error> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    let version = version as usize;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        warn!("Migrating the database to version {}", i + 1);
+        tx.execute_batch(migration)?;
+    }
+    // Pragmas don't take parameters
+    tx.execute_batch(&format!("PRAGMA user_version = {}", MIGRATIONS.len()))?;
+    tx.commit()
+}
@@ -0,0 +1,60 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+use tokio_util::sync::CancellationToken;
+
+// Waits between restarts start here and double every time it dies again, up to the max
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(5);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(10 * 60);
+// Running this long before dying means it's not stuck in a crash loop, back to the short wait
+const STABLE_AFTER: Duration = Duration::from_secs(30 * 60);
+
+/// Keep a task running, starting it again whenever it panics or stops when it shouldn't have,
+/// waiting longer each time. Every restart gets alerted on. Only gives back once shutdown is
+/// cancelled, the task should stop on its own when that happens too
+pub async fn supervise<F, Fut, A, AFut>(name: &'static str, shutdown: CancellationToken, mut start: F, alert: A)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+    A: Fn(String) -> AFut,
+    AFut: Future<Output = ()>,
+{
+    let mut delay = MIN_RESTART_DELAY;
+    loop {
+        let started = Instant::now();
+        // Its own task, so a panic stops there instead of taking us with it
+        let result = tokio::spawn(start()).await;
+        if shutdown.is_cancelled() {
+            return;
+        }
+        let what = match result {
+            Ok(_) => String::from("stopped on its own"),
+            Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e.into_panic())),
+            Err(e) => format!("got cancelled: {}", e),
+        };
+        if started.elapsed() >= STABLE_AFTER {
+            delay = MIN_RESTART_DELAY;
+        }
+        error!("The {} task {}, restarting it in {:?}", name, what, delay);
+        crate::metrics::error("task_restart");
+        alert(format!("The {} task {} after {}s, restarting it in {}s", name, what, started.elapsed().as_secs(), delay.as_secs())).await;
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = sleep(delay) => {}
+        }
+        delay = std::cmp::min(delay * 2, MAX_RESTART_DELAY);
+        warn!("Restarting the {} task", name);
+    }
+}
+
+// Panics are nearly always a &str or a String
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(s) => *s,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(s) => String::from(*s),
+            Err(_) => String::from("no message"),
+        },
+    }
+}
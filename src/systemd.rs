@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use tokio::time::Interval;
+
+/// Whether systemd started us as Type=notify and is listening
+pub fn enabled() -> bool {
+    std::env::var_os("NOTIFY_SOCKET").is_some()
+}
+
+// Never worth more than a log line, systemd not hearing from us isn't something we can fix
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        error!("Couldn't tell systemd what we're up to: {}", e);
+    }
+}
+
+/// Everything's connected and we're doing our job
+pub fn ready() {
+    if enabled() {
+        warn!("Telling systemd we're ready");
+    }
+    notify(&[NotifyState::Ready]);
+}
+
+/// On our way out, so systemd doesn't think we've hung while we finish up
+pub fn stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+/// Still alive, has to come more often than WatchdogSec or systemd restarts us
+pub fn watchdog() {
+    notify(&[NotifyState::Watchdog]);
+}
+
+/// Ticks twice as often as systemd wants to hear from us, if it's watching at all
+pub fn watchdog_timer() -> Option<Interval> {
+    let mut usec = 0;
+    match sd_notify::watchdog_enabled(false, &mut usec) {
+        true => {
+            let every = Duration::from_micros(usec) / 2;
+            warn!("Systemd's watching us, checking in every {:?}", every);
+            Some(tokio::time::interval(every))
+        }
+        false => None,
+    }
+}
+
+/// The next watchdog tick, or never without a watchdog
+pub async fn tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(t) => {
+            t.tick().await;
+        }
+        None => std::future::pending::<()>().await,
+    }
+}